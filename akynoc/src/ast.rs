@@ -1,6 +1,10 @@
 //! Abstract Syntax Tree (AST), produced by the [Parser](super::parser).
 //!
 //! The AST is the first intermediate representation (IR) produced by the compiler from the raw, tokenized input.
+//! Every node records the byte range of the source it was parsed from as a [`SimpleSpan`].
+use std::io::Write;
+
+use chumsky::span::SimpleSpan;
 use serde::Serialize;
 
 pub mod exprs;
@@ -11,17 +15,23 @@ pub mod structs;
 pub mod types;
 use crate::{
     ast::{
-        exprs::Literal,
+        exprs::Expr,
         functions::{FnDecl, FnDefn},
         structs::StructDecl,
-        types::{Ident, Ty},
+        types::{Ident, Ty, TyKind},
     },
-    util::NodeId,
+    util::{NodeId, Writable, Writer},
 };
 
+// There are few enough items that boxing function definitions isn't worth it.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Serialize)]
 pub enum ItemKind {
+    /// Placeholder for an item that failed to parse.
+    Invalid,
     Static(StaticDecl),
+    /// A `const` item. Unlike a static, it must have a value and cannot be assigned to.
+    Const(StaticDecl),
     StructDecl(StructDecl),
     FnDecl(FnDecl),
     FnDefn(FnDefn),
@@ -31,14 +41,17 @@ pub enum ItemKind {
 #[derive(Clone, Serialize)]
 pub struct Item {
     pub id: NodeId,
+    pub span: SimpleSpan,
     pub kind: ItemKind,
 }
 
-#[derive(Clone, Eq, Serialize)]
+#[derive(Clone, Serialize)]
 pub struct StaticDecl {
     pub ident: Ident,
     pub ty: Ty,
-    pub value: Option<Literal>,
+    /// The initial value, a constant expression evaluated at compile time by
+    /// [constant evaluation](crate::sem::consteval).
+    pub value: Option<Expr>,
 }
 
 /// Equal if [`Ident`] and [`Ty`] are equal
@@ -48,17 +61,64 @@ impl PartialEq for StaticDecl {
     }
 }
 
-impl From<ItemKind> for Item {
-    fn from(value: ItemKind) -> Self {
+impl From<(ItemKind, SimpleSpan)> for Item {
+    fn from((kind, span): (ItemKind, SimpleSpan)) -> Self {
         Self {
             id: NodeId::next(),
-            kind: value,
+            span,
+            kind,
         }
     }
 }
 
-impl From<(Ident, Ty, Option<Literal>)> for StaticDecl {
-    fn from((ident, ty, value): (Ident, Ty, Option<Literal>)) -> Self {
+impl From<(Ident, Ty, Option<Expr>)> for StaticDecl {
+    fn from((ident, ty, value): (Ident, Ty, Option<Expr>)) -> Self {
         Self { ident, ty, value }
     }
 }
+
+impl Writable for Item {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        match &self.kind {
+            ItemKind::Invalid => write!(writer, "INVALID;")?,
+            ItemKind::Static(decl) => {
+                write!(writer, "static ")?;
+                decl.write(writer, false)?;
+            }
+            ItemKind::Const(decl) => {
+                write!(writer, "const ")?;
+                decl.write(writer, false)?;
+            }
+            ItemKind::StructDecl(decl) => decl.write(writer, false)?,
+            ItemKind::FnDecl(decl) => {
+                decl.sig.write(writer, false)?;
+                write!(writer, ";")?;
+            }
+            ItemKind::FnDefn(defn) => defn.write(writer, false)?,
+        }
+        writer.close(self.span.end);
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
+
+impl Writable for StaticDecl {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        write!(writer, "{}", self.ident)?;
+        if !matches!(*self.ty.kind, TyKind::Infer) {
+            write!(writer, ": ")?;
+            self.ty.write(writer, false)?;
+        }
+        if let Some(value) = &self.value {
+            write!(writer, " = ")?;
+            value.write(writer, false)?;
+        }
+        write!(writer, ";")?;
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
-use std::fmt::Display;
+use std::{fmt::Display, io::Write};
 
+use chumsky::span::SimpleSpan;
 use internment::Intern;
 use serde::Serialize;
 
 use crate::{
-    ast::types::{Ident, Ty},
+    ast::types::{Ident, Length, Ty},
     lexer::Token,
-    util::NodeId,
+    util::{NodeId, Writable, Writer},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Hash)]
@@ -15,6 +16,7 @@ pub enum Value {
     Int(u32),
     Char(char),
     Str(Intern<String>),
+    Bool(bool),
 }
 
 impl From<u32> for Value {
@@ -29,6 +31,12 @@ impl From<char> for Value {
     }
 }
 
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
 impl From<String> for Value {
     fn from(value: String) -> Self {
         Self::Str(Intern::new(value))
@@ -46,6 +54,14 @@ pub struct Literal {
     pub value: Intern<Value>,
 }
 
+impl From<Value> for Literal {
+    fn from(value: Value) -> Self {
+        Self {
+            value: value.into(),
+        }
+    }
+}
+
 impl From<u32> for Literal {
     fn from(value: u32) -> Self {
         Self {
@@ -62,6 +78,14 @@ impl From<char> for Literal {
     }
 }
 
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Self {
+            value: Into::<Value>::into(value).into(),
+        }
+    }
+}
+
 impl From<String> for Literal {
     fn from(value: String) -> Self {
         Self {
@@ -93,6 +117,19 @@ pub enum Operator {
     Ne,
     Or,
     And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+    BitNot,
 }
 
 #[derive(Clone, Serialize)]
@@ -104,45 +141,65 @@ pub struct CallFn {
     pub args: Vec<Expr>,
 }
 
+/// A field of a [struct literal](ExprKind::StructLit).
+#[derive(Clone, Serialize)]
+pub struct FieldInit {
+    pub name: Ident,
+    /// Span of the field's name.
+    pub span: SimpleSpan,
+    pub value: Expr,
+}
+
 #[derive(Clone, Serialize)]
 pub enum ExprKind {
     Invalid,
     Literal(Literal),
     Ident(Ident),
     Assign(Box<Expr>, Box<Expr>),
+    /// `lhs op= rhs`, which only evaluates `lhs` once.
+    CompoundAssign(Box<Expr>, Operator, Box<Expr>),
     BinOp(Box<Expr>, Operator, Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
     CallFn(CallFn),
     Typecast(Ty, Box<Expr>),
     Deref(Box<Expr>),
     Ref(Box<Expr>),
     Index(Box<Expr>, Box<Expr>),
     FieldAccess(Box<Expr>, Ident),
+    /// `struct s { x: 1, y: 2 }`, whose type is always a [`TyKind::Struct`](super::types::TyKind).
+    StructLit(Ty, Vec<FieldInit>),
+    /// `[1, 2, 3]`, with at least one element.
+    ArrayLit(Vec<Expr>),
+    /// `[0; 16]`, which evaluates its element once.
+    ArrayRepeat(Box<Expr>, Length),
 }
 
 #[derive(Clone, Serialize)]
 pub struct Expr {
     pub id: NodeId,
+    pub span: SimpleSpan,
     pub kind: ExprKind,
 }
 
-impl From<ExprKind> for Expr {
-    fn from(value: ExprKind) -> Self {
+impl From<(ExprKind, SimpleSpan)> for Expr {
+    fn from((kind, span): (ExprKind, SimpleSpan)) -> Self {
         Self {
             id: NodeId::next(),
-            kind: value,
+            span,
+            kind,
         }
     }
 }
 
-impl From<Literal> for Expr {
-    fn from(value: Literal) -> Self {
-        ExprKind::Literal(value).into()
+impl From<(Literal, SimpleSpan)> for Expr {
+    fn from((value, span): (Literal, SimpleSpan)) -> Self {
+        (ExprKind::Literal(value), span).into()
     }
 }
 
-impl From<Ident> for Expr {
-    fn from(value: Ident) -> Self {
-        ExprKind::Ident(value).into()
+impl From<(Ident, SimpleSpan)> for Expr {
+    fn from((value, span): (Ident, SimpleSpan)) -> Self {
+        (ExprKind::Ident(value), span).into()
     }
 }
 
@@ -159,13 +216,35 @@ impl Display for ExprKind {
             Self::Literal(i) => write!(f, "{i}"),
             Self::Ident(id) => write!(f, "{id}"),
             Self::Assign(lhs, rhs) => write!(f, "({lhs} = {rhs})"),
+            Self::CompoundAssign(lhs, op, rhs) => write!(f, "({lhs} {op}= {rhs})"),
             Self::BinOp(lhs, op, rhs) => write!(f, "({lhs} {op} {rhs})"),
+            Self::Unary(op, expr) => write!(f, "({op}{expr})"),
             Self::CallFn(call) => write!(f, "({call})"),
             Self::Typecast(cast_to, expr) => write!(f, "(({cast_to}) {expr})"),
             Self::Ref(expr) => write!(f, "(&{expr})"),
             Self::Deref(expr) => write!(f, "(*{expr})"),
             Self::Index(arr, ind) => write!(f, "({arr}[{ind}])"),
             Self::FieldAccess(str, field) => write!(f, "({str}.{field})"),
+            Self::StructLit(ty, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| format!("{}: {}", field.name, field.value))
+                    .collect::<Vec<_>>();
+                match fields.is_empty() {
+                    true => write!(f, "{ty} {{}}"),
+                    false => write!(f, "{ty} {{ {} }}", fields.join(", ")),
+                }
+            }
+            Self::ArrayLit(elems) => write!(
+                f,
+                "[{}]",
+                elems
+                    .iter()
+                    .map(Expr::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::ArrayRepeat(elem, len) => write!(f, "[{elem}; {len}]"),
         }
     }
 }
@@ -186,6 +265,23 @@ impl Display for Operator {
             Self::Ne => "!=",
             Self::Or => "||",
             Self::And => "&&",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::BitAnd => "&",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Neg => "-",
+            Self::Plus => "+",
+            Self::Not => "!",
+            Self::BitNot => "~",
         };
         write!(f, "{s}")
     }
@@ -196,12 +292,29 @@ impl Display for Value {
         match self {
             Value::Invalid => write!(f, "INVALID"),
             &Value::Int(i) => write!(f, "{i}"),
-            &Value::Char(c) => write!(f, "'{c}'"),
-            &Value::Str(s) => write!(f, "\"{s}\""),
+            &Value::Char(c) => write!(f, "'{}'", escape(c, '\'')),
+            &Value::Str(s) => {
+                let s = s.chars().map(|c| escape(c, '"')).collect::<String>();
+                write!(f, "\"{s}\"")
+            }
+            &Value::Bool(b) => write!(f, "{b}"),
         }
     }
 }
 
+/// `c` as written in a literal delimited by `quote`, escaped if it must be.
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\n' => r"\n".into(),
+        '\t' => r"\t".into(),
+        '\r' => r"\r".into(),
+        '\\' => r"\\".into(),
+        c if c == quote => format!("\\{c}"),
+        c if c.is_ascii_control() => format!("\\x{:02X}", c as u8),
+        c => c.to_string(),
+    }
+}
+
 impl Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.value)
@@ -218,16 +331,34 @@ impl<'a> From<Token<'a>> for Operator {
             Token::Lt => Self::Lt,
             Token::Gt => Self::Gt,
             Token::Le => Self::Le,
-            Token::Plus => Self::Add,
-            Token::Minus => Self::Minus,
-            Token::Asterisk => Self::Times,
-            Token::Div => Self::Div,
-            Token::Rem => Self::Mod,
+            Token::Ge => Self::Ge,
+            Token::Plus | Token::AddAssign => Self::Add,
+            Token::Minus | Token::SubAssign => Self::Minus,
+            Token::Asterisk | Token::MulAssign => Self::Times,
+            Token::Div | Token::DivAssign => Self::Div,
+            Token::Rem | Token::RemAssign => Self::Mod,
+            Token::Pipe | Token::OrAssign => Self::BitOr,
+            Token::Caret | Token::XorAssign => Self::BitXor,
+            Token::And | Token::AndAssign => Self::BitAnd,
+            Token::Shl | Token::ShlAssign => Self::Shl,
+            Token::Shr | Token::ShrAssign => Self::Shr,
             _ => panic!("Attempt to convert invalid token {value} to Operator"),
         }
     }
 }
 
+impl<'a> From<Token<'a>> for UnaryOp {
+    fn from(value: Token) -> Self {
+        match value {
+            Token::Minus => Self::Neg,
+            Token::Plus => Self::Plus,
+            Token::Bang => Self::Not,
+            Token::Tilde => Self::BitNot,
+            _ => panic!("Attempt to convert invalid token {value} to UnaryOp"),
+        }
+    }
+}
+
 impl Display for CallFn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -251,3 +382,188 @@ impl From<(Expr, Vec<Expr>)> for CallFn {
         }
     }
 }
+
+impl Operator {
+    /// Binding power of the operator in the Pratt parser.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 3,
+            Self::And => 5,
+            Self::Eq | Self::Ne => 7,
+            Self::Lt | Self::Le | Self::Gt | Self::Ge => 9,
+            Self::BitOr => 11,
+            Self::BitXor => 13,
+            Self::BitAnd => 15,
+            Self::Shl | Self::Shr => 17,
+            Self::Add | Self::Minus => 19,
+            Self::Times | Self::Div | Self::Mod => 21,
+        }
+    }
+}
+
+/// Binding power of prefix operators and typecasts.
+const PREFIX: u8 = 23;
+/// Binding power of indexing, field accesses and calls.
+const POSTFIX: u8 = 25;
+
+impl Expr {
+    /// The value of an integer literal, possibly under prefix `-`, `+` and `~`, such as
+    /// `-2147483648`. Literals wrap to `int`, so that negating them gives the expected value.
+    pub fn fold_int(&self) -> Option<i32> {
+        match &self.kind {
+            ExprKind::Literal(l) => match *l.value {
+                Value::Int(i) => Some(i as i32),
+                _ => None,
+            },
+            ExprKind::Unary(op, expr) => {
+                let i = expr.fold_int()?;
+                match op {
+                    UnaryOp::Neg => Some(i.wrapping_neg()),
+                    UnaryOp::Plus => Some(i),
+                    UnaryOp::BitNot => Some(!i),
+                    UnaryOp::Not => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Binding power of the outermost operator of the expression, as in the Pratt parser.
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExprKind::Assign(..) | ExprKind::CompoundAssign(..) => 1,
+            ExprKind::BinOp(_, op, _) => op.precedence(),
+            ExprKind::Unary(..)
+            | ExprKind::Typecast(..)
+            | ExprKind::Deref(_)
+            | ExprKind::Ref(_) => PREFIX,
+            ExprKind::Index(..) | ExprKind::FieldAccess(..) | ExprKind::CallFn(_) => POSTFIX,
+            ExprKind::Invalid
+            | ExprKind::Literal(_)
+            | ExprKind::Ident(_)
+            | ExprKind::StructLit(..)
+            | ExprKind::ArrayLit(_)
+            | ExprKind::ArrayRepeat(..) => u8::MAX,
+        }
+    }
+
+    /// Writes the expression as an operand that binds at least as tightly as `min`.
+    fn write_operand<T: Write>(&self, writer: &mut Writer<T>, min: u8) -> anyhow::Result<()> {
+        if self.precedence() < min {
+            write!(writer, "(")?;
+            self.write(writer, false)?;
+            write!(writer, ")")?;
+        } else {
+            self.write(writer, false)?;
+        }
+        Ok(())
+    }
+}
+
+/// Unlike [`Display`], only parenthesizes where precedence requires it.
+impl Writable for Expr {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        match &self.kind {
+            ExprKind::Invalid => write!(writer, "{}", self.kind)?,
+            // Literals are printed as written, keeping the escapes chosen in the source.
+            ExprKind::Literal(l) => match writer.source(self.span) {
+                Some(text) => write!(writer, "{text}")?,
+                None => write!(writer, "{l}")?,
+            },
+            ExprKind::Ident(id) => write!(writer, "{id}")?,
+            ExprKind::Assign(lhs, rhs) => {
+                lhs.write_operand(writer, 2)?;
+                write!(writer, " = ")?;
+                rhs.write_operand(writer, 1)?;
+            }
+            ExprKind::CompoundAssign(lhs, op, rhs) => {
+                lhs.write_operand(writer, 2)?;
+                write!(writer, " {op}= ")?;
+                rhs.write_operand(writer, 1)?;
+            }
+            ExprKind::BinOp(lhs, op, rhs) => {
+                lhs.write_operand(writer, op.precedence())?;
+                write!(writer, " {op} ")?;
+                rhs.write_operand(writer, op.precedence() + 1)?;
+            }
+            ExprKind::CallFn(call) => {
+                call.name.write_operand(writer, POSTFIX)?;
+                write!(writer, "(")?;
+                for (i, arg) in call.args.iter().enumerate() {
+                    if i > 0 {
+                        write!(writer, ", ")?;
+                    }
+                    arg.write(writer, false)?;
+                }
+                write!(writer, ")")?;
+            }
+            ExprKind::Typecast(ty, expr) => {
+                write!(writer, "(")?;
+                ty.write(writer, false)?;
+                write!(writer, ") ")?;
+                expr.write_operand(writer, PREFIX)?;
+            }
+            ExprKind::Deref(expr) => {
+                write!(writer, "*")?;
+                expr.write_operand(writer, PREFIX)?;
+            }
+            // `- -x` would read as a decrement if printed `--x`.
+            ExprKind::Unary(op, expr) => {
+                write!(writer, "{op}")?;
+                if let ExprKind::Unary(UnaryOp::Neg | UnaryOp::Plus, _) = expr.kind
+                    && let UnaryOp::Neg | UnaryOp::Plus = op
+                {
+                    write!(writer, " ")?;
+                }
+                expr.write_operand(writer, PREFIX)?;
+            }
+            // `& &x` would lex as `&&x`.
+            ExprKind::Ref(expr) => {
+                write!(writer, "&")?;
+                let min = match expr.kind {
+                    ExprKind::Ref(_) => u8::MAX,
+                    _ => PREFIX,
+                };
+                expr.write_operand(writer, min)?;
+            }
+            ExprKind::Index(arr, ind) => {
+                arr.write_operand(writer, POSTFIX)?;
+                write!(writer, "[")?;
+                ind.write(writer, false)?;
+                write!(writer, "]")?;
+            }
+            ExprKind::FieldAccess(expr, field) => {
+                expr.write_operand(writer, POSTFIX)?;
+                write!(writer, ".{field}")?;
+            }
+            ExprKind::StructLit(ty, fields) => {
+                ty.write(writer, false)?;
+                write!(writer, " {{")?;
+                for (i, field) in fields.iter().enumerate() {
+                    write!(writer, "{} {}: ", if i > 0 { "," } else { "" }, field.name)?;
+                    field.value.write(writer, false)?;
+                }
+                write!(writer, "{}}}", if fields.is_empty() { "" } else { " " })?;
+            }
+            ExprKind::ArrayLit(elems) => {
+                write!(writer, "[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(writer, ", ")?;
+                    }
+                    elem.write(writer, false)?;
+                }
+                write!(writer, "]")?;
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                write!(writer, "[")?;
+                elem.write(writer, false)?;
+                write!(writer, "; {len}]")?;
+            }
+        }
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,15 @@
 //! Constructs for encoding functions.
+use std::io::Write;
+
+use chumsky::span::SimpleSpan;
 use serde::Serialize;
 
-use crate::ast::{
-    statements::Stmt,
-    types::{Ident, Ty},
+use crate::{
+    ast::{
+        statements::Stmt,
+        types::{Ident, Ty},
+    },
+    util::{Writable, Writer},
 };
 
 /// Encodes a single parameter in a [`FnSig`].
@@ -16,6 +22,7 @@ use crate::ast::{
 pub struct Param {
     pub name: Ident,
     pub ty: Ty,
+    pub span: SimpleSpan,
 }
 
 /// Encodes the signature of a function.
@@ -62,3 +69,26 @@ pub struct FnDefn {
     pub decl: Option<FnDecl>,
     pub block: Stmt,
 }
+
+impl Writable for FnSig {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        write!(writer, "{}(", self.name)?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ", ")?;
+            }
+            write!(writer, "{}: ", param.name)?;
+            param.ty.write(writer, false)?;
+        }
+        write!(writer, "): ")?;
+        self.ty.write(writer, eol)
+    }
+}
+
+impl Writable for FnDefn {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        self.sig.write(writer, false)?;
+        write!(writer, " ")?;
+        self.block.write(writer, eol)
+    }
+}
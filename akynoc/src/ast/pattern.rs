@@ -1,13 +1,17 @@
+use std::io::Write;
+
+use chumsky::span::SimpleSpan;
 use serde::Serialize;
 
 use crate::{
     ast::{exprs::Expr, types::Ident},
-    util::NodeId,
+    util::{NodeId, Writable, Writer},
 };
 
 #[derive(Clone, Serialize)]
 pub struct Pattern {
     pub id: NodeId,
+    pub span: SimpleSpan,
     pub kind: PatternKind,
 }
 
@@ -24,11 +28,34 @@ pub enum Range {
     InclusiveExclusive(Expr, Expr),
 }
 
-impl From<(Ident, Range)> for Pattern {
-    fn from((id, range): (Ident, Range)) -> Self {
+impl From<(Ident, Range, SimpleSpan)> for Pattern {
+    fn from((id, range, span): (Ident, Range, SimpleSpan)) -> Self {
         Self {
             id: NodeId::next(),
+            span,
             kind: PatternKind::RangePattern(id, range),
         }
     }
 }
+
+impl Writable for Pattern {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        let PatternKind::RangePattern(id, range) = &self.kind;
+        let (open, start, end, close) = match range {
+            Range::Exclusive(start, end) => ("(", start, end, ")"),
+            Range::Inclusive(start, end) => ("[", start, end, "]"),
+            Range::ExclusiveInclusive(start, end) => ("(", start, end, "]"),
+            Range::InclusiveExclusive(start, end) => ("[", start, end, ")"),
+        };
+        write!(writer, "{id}: {open}")?;
+        start.write(writer, false)?;
+        write!(writer, "; ")?;
+        end.write(writer, false)?;
+        write!(writer, "{close}")?;
+        writer.close(self.span.end);
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
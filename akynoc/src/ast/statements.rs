@@ -1,13 +1,16 @@
 //! Constructs for encoding statements.
+use std::io::Write;
+
+use chumsky::span::SimpleSpan;
 use serde::Serialize;
 
 use crate::{
     ast::{
         exprs::Expr,
         pattern::Pattern,
-        types::{Ident, Ty},
+        types::{Ident, Ty, TyKind},
     },
-    util::NodeId,
+    util::{NodeId, Writable, Writer},
 };
 
 /// Encodes a block.
@@ -19,6 +22,8 @@ pub struct Block {
 /// Encodes a stack-allocated variable declaration.
 #[derive(Clone, Serialize)]
 pub struct Local {
+    /// Declared with `let mut`. Only mutable locals may be assigned to after their declaration.
+    pub mutable: bool,
     pub name: Ident,
     pub ty: Ty,
     pub value: Option<Expr>,
@@ -27,6 +32,8 @@ pub struct Local {
 /// Encodes information for each [`Stmt`]
 #[derive(Clone, Serialize)]
 pub enum StmtKind {
+    /// Placeholder for a statement that failed to parse.
+    Invalid,
     Block(Block),
     Local(Local),
     For(Pattern, Box<Stmt>),
@@ -42,14 +49,16 @@ pub enum StmtKind {
 #[derive(Clone, Serialize)]
 pub struct Stmt {
     pub id: NodeId,
+    pub span: SimpleSpan,
     pub kind: StmtKind,
 }
 
-impl From<StmtKind> for Stmt {
-    fn from(value: StmtKind) -> Self {
+impl From<(StmtKind, SimpleSpan)> for Stmt {
+    fn from((kind, span): (StmtKind, SimpleSpan)) -> Self {
         Self {
             id: NodeId::next(),
-            kind: value,
+            span,
+            kind,
         }
     }
 }
@@ -66,8 +75,117 @@ impl From<Stmt> for Block {
     }
 }
 
-impl From<(Ident, Ty, Option<Expr>)> for Local {
-    fn from((name, ty, value): (Ident, Ty, Option<Expr>)) -> Self {
-        Self { name, ty, value }
+impl From<(bool, Ident, Ty, Option<Expr>)> for Local {
+    fn from((mutable, name, ty, value): (bool, Ident, Ty, Option<Expr>)) -> Self {
+        Self {
+            mutable,
+            name,
+            ty,
+            value,
+        }
+    }
+}
+
+impl Writable for Stmt {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        match &self.kind {
+            StmtKind::Invalid => write!(writer, "INVALID;")?,
+            StmtKind::Block(block) => {
+                // The closing brace is the last byte of the block.
+                let end = self.span.end.saturating_sub(1);
+                if block.stmts.is_empty() && !writer.has_comments(end) {
+                    write!(writer, "{{}}")?;
+                } else {
+                    writeln!(writer, "{{")?;
+                    writer.inctabs();
+                    writer.open();
+                    for stmt in &block.stmts {
+                        writer.comments(stmt.span.start, false)?;
+                        writer.tabs()?;
+                        stmt.write(writer, true)?;
+                    }
+                    writer.closing_comments(end)?;
+                    writer.dectabs();
+                    writer.tabs()?;
+                    write!(writer, "}}")?;
+                }
+            }
+            StmtKind::Local(local) => {
+                write!(writer, "let ")?;
+                if local.mutable {
+                    write!(writer, "mut ")?;
+                }
+                write!(writer, "{}", local.name)?;
+                match &local.value {
+                    Some(value) if matches!(*local.ty.kind, TyKind::Infer) => {
+                        write!(writer, " := ")?;
+                        value.write(writer, false)?;
+                    }
+                    value => {
+                        write!(writer, ": ")?;
+                        local.ty.write(writer, false)?;
+                        if let Some(value) = value {
+                            write!(writer, " = ")?;
+                            value.write(writer, false)?;
+                        }
+                    }
+                }
+                write!(writer, ";")?;
+            }
+            StmtKind::For(pattern, body) => {
+                write!(writer, "for (")?;
+                pattern.write(writer, false)?;
+                write!(writer, ") ")?;
+                body.write(writer, false)?;
+            }
+            StmtKind::While(cond, body) => {
+                write!(writer, "while (")?;
+                cond.write(writer, false)?;
+                write!(writer, ") ")?;
+                body.write(writer, false)?;
+            }
+            StmtKind::If(cond, then, els) => {
+                write!(writer, "if (")?;
+                cond.write(writer, false)?;
+                write!(writer, ") ")?;
+                then.write(writer, false)?;
+                if let Some(els) = els {
+                    if let StmtKind::Block(_) = then.kind {
+                        write!(writer, " ")?;
+                    } else {
+                        writer.eol()?;
+                        writer.tabs()?;
+                    }
+                    write!(writer, "else ")?;
+                    els.write(writer, false)?;
+                }
+            }
+            StmtKind::Expr(expr) => {
+                expr.write(writer, false)?;
+                // The AST does not record whether there was a semicolon, so keep it as written.
+                let semi = match writer.source(self.span) {
+                    Some(text) => text.ends_with(';'),
+                    None => self.span.end > expr.span.end,
+                };
+                if semi {
+                    write!(writer, ";")?;
+                }
+            }
+            StmtKind::Return(expr) => {
+                write!(writer, "return")?;
+                if let Some(expr) = expr {
+                    write!(writer, " ")?;
+                    expr.write(writer, false)?;
+                }
+                write!(writer, ";")?;
+            }
+            StmtKind::Continue => write!(writer, "continue;")?,
+            StmtKind::Break => write!(writer, "break;")?,
+        }
+        writer.close(self.span.end);
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
     }
 }
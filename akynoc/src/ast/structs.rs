@@ -1,6 +1,11 @@
+use std::io::Write;
+
 use serde::Serialize;
 
-use crate::ast::types::{Ident, Ty, TyKind};
+use crate::{
+    ast::types::{Ident, Ty, TyKind},
+    util::{Writable, Writer},
+};
 
 #[derive(Clone, Serialize)]
 pub struct Field {
@@ -47,3 +52,31 @@ impl PartialEq for StructDecl {
         true
     }
 }
+
+impl Writable for StructDecl {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        write!(writer, "struct {} {{", self.name)?;
+        if !self.fields.is_empty() {
+            writeln!(writer)?;
+            writer.inctabs();
+            writer.open();
+            // Fields have no span of their own, so comments are placed relative to their types.
+            for field in &self.fields {
+                writer.comments(field.ty.span.start, false)?;
+                writer.tabs()?;
+                write!(writer, "{}: ", field.name)?;
+                field.ty.write(writer, false)?;
+                write!(writer, ";")?;
+                writer.close(field.ty.span.end);
+                writer.eol()?;
+            }
+            writer.dectabs();
+            writer.tabs()?;
+        }
+        write!(writer, "}}")?;
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
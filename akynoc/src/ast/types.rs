@@ -1,10 +1,11 @@
 //! Constructs for encoding types.
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
+use chumsky::span::SimpleSpan;
 use internment::Intern;
 use serde::Serialize;
 
-use crate::util::NodeId;
+use crate::util::{NodeId, Writable, Writer};
 
 /// Encodes an identifier.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Hash)]
@@ -12,43 +13,107 @@ pub struct Ident {
     pub name: Intern<String>,
 }
 
-/// Encodes the two primitive types: `int` and `char`.
+/// Encodes the three primitive types: `int`, `char` and `bool`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Hash)]
 pub enum Primitive {
     Int,
     Char,
+    Bool,
 }
 
 /// Encodes information for [`Ty`].
 ///
-/// Since all [`TyKind`] are interned, two different [`Items`](super::Item) with equivalent type
-/// will have the exact same [`TyKind`].
-#[derive(Debug, Clone, Copy, Eq, Serialize, Hash)]
+/// Each [`Ty`] holds its kind behind an [`Arc`], so that types are cheap to clone and the AST can
+/// be shared with the interpreter's thread. Kinds are not shared between equivalent types, since
+/// the types nested in them keep their own ids and spans.
+#[derive(Debug, Clone, Eq, Serialize)]
 pub enum TyKind {
     Primitive(Primitive),
     Void,
     Struct(Ident),
     Pointer(Ty),
-    Array(usize, Ty),
+    Array(Length, Ty),
     Infer,
 }
 
+/// The length of an array type or repeated array literal, as written.
+///
+/// Named lengths are replaced with their values by [constant evaluation](crate::sem::consteval),
+/// so later passes only ever see [`Length::Known`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Hash)]
+pub enum Length {
+    Known(usize),
+    /// The name of a static holding the length.
+    Named(Ident),
+}
+
+impl Length {
+    /// The number of elements.
+    pub fn get(self) -> usize {
+        match self {
+            Length::Known(len) => len,
+            Length::Named(name) => unreachable!("length `{name}` after constant evaluation"),
+        }
+    }
+}
+
+impl From<usize> for Length {
+    fn from(len: usize) -> Self {
+        Length::Known(len)
+    }
+}
+
 /// Encodes a single type.
-#[derive(Debug, Clone, Copy, Eq, Serialize, Hash)]
+#[derive(Debug, Clone, Eq, Serialize)]
 pub struct Ty {
     pub id: NodeId,
-    pub kind: Intern<TyKind>,
+    pub span: SimpleSpan,
+    pub kind: Arc<TyKind>,
 }
 
+/// Types synthesized by later passes do not appear in the source, and so have an empty span.
 impl From<TyKind> for Ty {
     fn from(value: TyKind) -> Self {
+        (value, SimpleSpan::from(0..0)).into()
+    }
+}
+
+impl From<(TyKind, SimpleSpan)> for Ty {
+    fn from((kind, span): (TyKind, SimpleSpan)) -> Self {
         Self {
             id: NodeId::next(),
-            kind: Intern::new(value),
+            span,
+            kind: Arc::new(kind),
         }
     }
 }
 
+impl Ty {
+    /// Whether this type contains a [`TyKind::Infer`] placeholder anywhere within it.
+    pub fn has_infer(&self) -> bool {
+        match &*self.kind {
+            TyKind::Infer => true,
+            TyKind::Pointer(inner) | TyKind::Array(_, inner) => inner.has_infer(),
+            TyKind::Primitive(_) | TyKind::Void | TyKind::Struct(_) => false,
+        }
+    }
+
+    /// The struct whose fields a field access on this type reads: the struct itself, or the one
+    /// behind a single pointer, which is dereferenced automatically.
+    pub fn field_owner(&self) -> Option<Ident> {
+        match &*self.kind {
+            TyKind::Struct(name) => Some(*name),
+            TyKind::Pointer(inner) => match &*inner.kind {
+                TyKind::Struct(name) => Some(*name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Placeholders never compare equal, except in tests. Outside of tests, every placeholder is
+/// resolved by [inference](crate::sem::infer) before types are compared.
 impl PartialEq for TyKind {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -76,11 +141,21 @@ impl Display for Ident {
     }
 }
 
+impl Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Length::Known(len) => write!(f, "{len}"),
+            Length::Named(name) => write!(f, "{name}"),
+        }
+    }
+}
+
 impl Display for Primitive {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Primitive::Int => write!(f, "int"),
             Primitive::Char => write!(f, "char"),
+            Primitive::Bool => write!(f, "bool"),
         }
     }
 }
@@ -89,11 +164,11 @@ use std::io::Write;
 impl Display for TyKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            &Self::Primitive(p) => write!(f, "{p}"),
-            &Self::Void => write!(f, "void"),
-            &Self::Struct(id) => write!(f, "struct {id}"),
-            &Self::Pointer(ty) => write!(f, "(&{})", ty),
-            &Self::Array(size, ty) => {
+            Self::Primitive(p) => write!(f, "{p}"),
+            Self::Void => write!(f, "void"),
+            Self::Struct(id) => write!(f, "struct {id}"),
+            Self::Pointer(ty) => write!(f, "(&{})", ty),
+            Self::Array(size, ty) => {
                 let mut out = vec![];
                 write!(&mut out, "{}", ty).unwrap();
                 let inner_type = String::from_utf8(out).unwrap();
@@ -106,7 +181,7 @@ impl Display for TyKind {
                     }
                 }
             }
-            &Self::Infer => write!(f, "_"),
+            Self::Infer => write!(f, "_"),
         }
     }
 }
@@ -117,6 +192,51 @@ impl Display for Ty {
     }
 }
 
+/// Unlike [`Display`], only parenthesizes where the parser needs it: around a pointer type with
+/// array sizes, and between two `&`, which would otherwise lex as `&&`.
+impl Writable for Ty {
+    fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> anyhow::Result<()> {
+        match &*self.kind {
+            TyKind::Primitive(p) => write!(writer, "{p}")?,
+            TyKind::Void => write!(writer, "void")?,
+            TyKind::Struct(id) => write!(writer, "struct {id}")?,
+            TyKind::Infer => write!(writer, "_")?,
+            TyKind::Pointer(ty) => {
+                if let TyKind::Pointer(_) = *ty.kind {
+                    write!(writer, "&(")?;
+                    ty.write(writer, false)?;
+                    write!(writer, ")")?;
+                } else {
+                    write!(writer, "&")?;
+                    ty.write(writer, false)?;
+                }
+            }
+            TyKind::Array(..) => {
+                let mut sizes = vec![];
+                let mut ty = self;
+                while let TyKind::Array(size, inner) = &*ty.kind {
+                    sizes.push(*size);
+                    ty = inner;
+                }
+                if let TyKind::Pointer(_) = *ty.kind {
+                    write!(writer, "(")?;
+                    ty.write(writer, false)?;
+                    write!(writer, ")")?;
+                } else {
+                    ty.write(writer, false)?;
+                }
+                for size in sizes {
+                    write!(writer, "[{size}]")?;
+                }
+            }
+        }
+        if eol {
+            writer.eol()?;
+        }
+        Ok(())
+    }
+}
+
 impl From<&str> for Ident {
     fn from(value: &str) -> Self {
         Self {
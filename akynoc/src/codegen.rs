@@ -0,0 +1,132 @@
+//! Code generation from the [AST](crate::ast), run once semantic analysis succeeds.
+//!
+//! Two targets are supported:
+//! - [x86-64](x86_64), producing GNU assembler source that follows the System V calling
+//!   convention. The assembly can be written out as is, or handed to the system's `cc` to be
+//!   assembled and linked into an executable.
+//! - [MIPS32](mips), producing assembly for MARS, SPIM or the `mipsim` simulator, which can only be
+//!   written out.
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    ast::{Item, ItemKind},
+    diagnostic::Diagnostic,
+    sem::Analysis,
+    util::CompilerPass,
+};
+
+pub mod mips;
+pub mod x86_64;
+
+/// The architecture to generate code for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    #[value(name = "x86-64")]
+    X86_64,
+    Mips,
+}
+
+/// What the code generator produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// Assembly source.
+    Asm,
+    /// An executable, linked by `cc`.
+    Exe,
+}
+
+/// The code generation stage of the pipeline.
+#[derive(Default)]
+pub struct CodeGen {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CodeGen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles the program for `target`, writing `emit` to `output`.
+    pub fn emit(
+        &mut self,
+        items: &[Item],
+        analysis: &Analysis,
+        target: Target,
+        emit: Emit,
+        output: &Path,
+    ) {
+        let result = match (target, emit) {
+            (Target::Mips, Emit::Exe) => Err((
+                "E0501",
+                "cannot link an executable for MIPS, only `--emit asm` is supported".into(),
+            )),
+            (_, Emit::Exe) if !has_main(items) => {
+                Err(("E0502", "no `main` function to link".into()))
+            }
+            (Target::X86_64, Emit::Exe) => link(&x86_64::generate(items, analysis), output),
+            (target, Emit::Asm) => {
+                let asm = match target {
+                    Target::X86_64 => x86_64::generate(items, analysis),
+                    Target::Mips => mips::generate(items, analysis),
+                };
+                fs::write(output, asm).map_err(|err| {
+                    let message = format!("could not write `{}`: {err}", output.display());
+                    ("E0503", message)
+                })
+            }
+        };
+        if let Err((code, message)) = result {
+            self.report(Diagnostic::error(code, message));
+        }
+    }
+}
+
+impl CompilerPass for CodeGen {
+    const FAIL_CODE: u8 = 230;
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+fn has_main(items: &[Item]) -> bool {
+    items.iter().any(|item| match &item.kind {
+        ItemKind::FnDefn(defn) => *defn.sig.name.name == "main",
+        _ => false,
+    })
+}
+
+/// Assembles and links `asm` into the executable `output`, using the system's `cc`. Fails with
+/// the code of the error and its message.
+fn link(asm: &str, output: &Path) -> Result<(), (&'static str, String)> {
+    let mut cc = Command::new("cc")
+        .args(["-x", "assembler", "-", "-o"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| ("E0504", format!("could not run `cc`: {err}")))?;
+    cc.stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(asm.as_bytes())
+        .map_err(|err| ("E0504", format!("could not write to `cc`: {err}")))?;
+    let status = cc
+        .wait()
+        .map_err(|err| ("E0504", format!("could not run `cc`: {err}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(("E0504", format!("`cc` failed with {status}")))
+    }
+}
@@ -0,0 +1,937 @@
+//! MIPS32 code generation, producing assembly for MARS, SPIM or the `mipsim` simulator.
+//!
+//! Code is generated directly from the AST, one expression at a time. Every expression leaves its
+//! value in `$v0`, with structs and arrays represented by their address. Intermediate values are
+//! pushed onto the stack.
+//!
+//! Calls use a simple stack-based convention:
+//! - The caller copies its arguments onto the stack, laid out like the fields of a struct, so that
+//!   the callee finds them just above its saved `$fp` and `$ra`.
+//! - Scalars are returned in `$v0`. Structs and arrays are written to memory provided by the
+//!   caller, whose address is passed in `$a0` and returned in `$v0`.
+//!
+//! Every variable lives in the stack frame, at a fixed offset from `$fp`. Statics, constants and
+//! string literals are placed in `.data`. The runtime [services](crate::runtime) are syscalls, and
+//! the program starts at `__start`, which calls `main` and exits with its result.
+use std::{collections::HashMap, fmt::Write};
+
+use internment::Intern;
+
+use crate::{
+    ast::{
+        exprs::{CallFn, Expr, ExprKind, Operator, UnaryOp, Value},
+        functions::{FnDefn, FnSig},
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        types::{Ident, Primitive, Ty, TyKind},
+        Item, ItemKind,
+    },
+    layout::{align_to, Layout, Layouts},
+    runtime::Service,
+    sem::{consteval::Const, resolve::Binding, Analysis},
+    util::NodeId,
+};
+
+/// Size of a pointer, in bytes.
+const PTR_SIZE: usize = 4;
+
+/// Largest struct or array copied with unrolled loads and stores, rather than a loop.
+const UNROLL_LIMIT: usize = 8;
+
+/// Writes a single instruction or directive.
+macro_rules! emit {
+    ($gen:expr, $($arg:tt)*) => {
+        writeln!($gen.out, "\t{}", format_args!($($arg)*)).unwrap()
+    };
+}
+
+/// Stack frame of the function being generated. Offsets are relative to `$fp`.
+#[derive(Default)]
+struct Frame {
+    size: usize,
+    vars: HashMap<Binding, i64>,
+    /// Temporaries holding the structs and arrays returned by calls, keyed by the call.
+    temps: HashMap<NodeId, i64>,
+    /// The counter and last value of each `for` loop, keyed by its pattern.
+    counters: HashMap<NodeId, (i64, i64)>,
+    /// Where the address to return a struct or array to is kept.
+    ret_ptr: Option<i64>,
+}
+
+impl Frame {
+    fn alloc(&mut self, layout: Layout) -> i64 {
+        self.size = align_to(self.size + layout.size, layout.align);
+        -(self.size as i64)
+    }
+}
+
+struct Generator<'ast> {
+    analysis: &'ast Analysis,
+    layouts: Layouts,
+    /// Maps the [`NodeId`] of each function item to its signature.
+    sigs: HashMap<NodeId, &'ast FnSig>,
+    /// Maps the [`NodeId`] of each declared runtime service to the service.
+    services: HashMap<NodeId, Service>,
+    /// Maps the [`NodeId`] of each static item to its name.
+    statics: HashMap<NodeId, Ident>,
+    /// Labels of the string literals seen so far.
+    strings: HashMap<Intern<String>, String>,
+    out: String,
+    frame: Frame,
+    /// Return type of the function being generated.
+    ret: Ty,
+    /// Label of the epilogue of the function being generated.
+    ret_label: String,
+    /// The `continue` and `break` labels of each enclosing loop.
+    loops: Vec<(String, String)>,
+    labels: usize,
+}
+
+/// Generates assembly for a program that passed semantic analysis.
+pub fn generate(items: &[Item], analysis: &Analysis) -> String {
+    let declared = Service::declared(items);
+    let mut sigs = HashMap::new();
+    let mut services = HashMap::new();
+    let mut statics = HashMap::new();
+    for item in items {
+        match &item.kind {
+            ItemKind::FnDecl(decl) => {
+                sigs.insert(item.id, &decl.sig);
+                if let Some(service) = Service::of(&decl.sig)
+                    && declared.contains(&service)
+                {
+                    services.insert(item.id, service);
+                }
+            }
+            ItemKind::FnDefn(defn) => {
+                sigs.insert(item.id, &defn.sig);
+            }
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                statics.insert(item.id, decl.ident);
+            }
+            ItemKind::StructDecl(_) | ItemKind::Invalid => (),
+        }
+    }
+    let mut generator = Generator {
+        analysis,
+        layouts: Layouts::new(items, PTR_SIZE),
+        sigs,
+        services,
+        statics,
+        strings: HashMap::new(),
+        out: String::new(),
+        frame: Frame::default(),
+        ret: TyKind::Void.into(),
+        ret_label: String::new(),
+        loops: vec![],
+        labels: 0,
+    };
+    emit!(generator, ".text");
+    let has_main = items.iter().any(|item| match &item.kind {
+        ItemKind::FnDefn(defn) => *defn.sig.name.name == "main",
+        _ => false,
+    });
+    if has_main {
+        emit!(generator, ".globl __start");
+        generator.label("__start");
+        emit!(generator, "jal main");
+        emit!(generator, "move $a0, $v0");
+        emit!(generator, "li $v0, 17");
+        emit!(generator, "syscall");
+    }
+    for item in items {
+        if let ItemKind::FnDefn(defn) = &item.kind {
+            generator.function(item, defn);
+        }
+    }
+    generator.data(items);
+    generator.out
+}
+
+fn is_aggregate(ty: &Ty) -> bool {
+    matches!(*ty.kind, TyKind::Struct(_) | TyKind::Array(..))
+}
+
+impl<'ast> Generator<'ast> {
+    fn fresh(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.out, "{label}:").unwrap();
+    }
+
+    fn ty(&self, expr: &Expr) -> Ty {
+        self.analysis.types.exprs[&expr.id].clone()
+    }
+
+    /// The offset of each parameter of `sig` from the first, along with the size of the stack
+    /// area needed for the arguments.
+    fn arg_offsets(&self, sig: &FnSig) -> (Vec<usize>, usize) {
+        let mut size = 0;
+        let offsets = sig
+            .params
+            .iter()
+            .map(|param| {
+                let layout = self.layouts.of(&param.ty);
+                let offset = align_to(size, layout.align);
+                size = offset + layout.size;
+                offset
+            })
+            .collect();
+        (offsets, align_to(size, 4))
+    }
+
+    fn function(&mut self, item: &Item, defn: &FnDefn) {
+        self.frame = Frame::default();
+        self.ret = defn.sig.ty.clone();
+        self.ret_label = self.fresh();
+        if is_aggregate(&defn.sig.ty) {
+            self.frame.ret_ptr = Some(self.frame.alloc(Layout { size: 4, align: 4 }));
+        }
+        let (offsets, _) = self.arg_offsets(&defn.sig);
+        for (index, offset) in offsets.into_iter().enumerate() {
+            let binding = Binding::Param {
+                item: item.id,
+                index,
+            };
+            // Above the saved `$fp` and `$ra`.
+            self.frame.vars.insert(binding, 8 + offset as i64);
+        }
+        self.allocate(&defn.block);
+
+        let name = defn.sig.name;
+        emit!(self, ".globl {name}");
+        self.label(&name.to_string());
+        emit!(self, "addiu $sp, $sp, -8");
+        emit!(self, "sw $ra, 4($sp)");
+        emit!(self, "sw $fp, 0($sp)");
+        emit!(self, "move $fp, $sp");
+        let size = align_to(self.frame.size, 8);
+        if size > 0 {
+            emit!(self, "addiu $sp, $sp, -{size}");
+        }
+        if let Some(ret_ptr) = self.frame.ret_ptr {
+            emit!(self, "sw $a0, {ret_ptr}($fp)");
+        }
+
+        if *self.ret.kind == TyKind::Void {
+            self.stmt(&defn.block);
+        } else {
+            self.tail(&defn.block);
+            // Falling off the end of a non-void function.
+            emit!(self, "break");
+        }
+        let ret_label = self.ret_label.clone();
+        self.label(&ret_label);
+        if *self.ret.kind == TyKind::Void && *name.name == "main" {
+            emit!(self, "li $v0, 0");
+        }
+        emit!(self, "move $sp, $fp");
+        emit!(self, "lw $fp, 0($sp)");
+        emit!(self, "lw $ra, 4($sp)");
+        emit!(self, "addiu $sp, $sp, 8");
+        emit!(self, "jr $ra");
+    }
+
+    /// Reserves space in the frame for every variable and temporary in `stmt`.
+    fn allocate(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.allocate(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                let ty = self.analysis.types.decls[&stmt.id].clone();
+                let offset = self.frame.alloc(self.layouts.of(&ty));
+                self.frame.vars.insert(Binding::Local(stmt.id), offset);
+                if let Some(value) = &local.value {
+                    self.allocate_expr(value);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                self.allocate_expr(start);
+                self.allocate_expr(end);
+                let ty = self.analysis.types.decls[&pattern.id].clone();
+                let offset = self.frame.alloc(self.layouts.of(&ty));
+                self.frame.vars.insert(Binding::Pattern(pattern.id), offset);
+                let word = Layout { size: 4, align: 4 };
+                let counter = (self.frame.alloc(word), self.frame.alloc(word));
+                self.frame.counters.insert(pattern.id, counter);
+                self.allocate(body);
+            }
+            StmtKind::While(cond, body) => {
+                self.allocate_expr(cond);
+                self.allocate(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.allocate_expr(cond);
+                self.allocate(then);
+                if let Some(els) = els {
+                    self.allocate(els);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.allocate_expr(expr),
+            StmtKind::Return(None) | StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn allocate_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Invalid | ExprKind::Literal(_) | ExprKind::Ident(_) => (),
+            ExprKind::Assign(lhs, rhs)
+            | ExprKind::CompoundAssign(lhs, _, rhs)
+            | ExprKind::BinOp(lhs, _, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.allocate_expr(lhs);
+                self.allocate_expr(rhs);
+            }
+            ExprKind::CallFn(call) => {
+                for arg in &call.args {
+                    self.allocate_expr(arg);
+                }
+                let ty = self.ty(expr);
+                if is_aggregate(&ty) {
+                    self.allocate_temp(expr);
+                }
+            }
+            ExprKind::StructLit(_, fields) => {
+                for field in fields {
+                    self.allocate_expr(&field.value);
+                }
+                self.allocate_temp(expr);
+            }
+            ExprKind::ArrayLit(elems) => {
+                for elem in elems {
+                    self.allocate_expr(elem);
+                }
+                self.allocate_temp(expr);
+            }
+            ExprKind::ArrayRepeat(elem, _) => {
+                self.allocate_expr(elem);
+                self.allocate_temp(expr);
+            }
+            ExprKind::Typecast(_, inner)
+            | ExprKind::Deref(inner)
+            | ExprKind::Ref(inner)
+            | ExprKind::Unary(_, inner)
+            | ExprKind::FieldAccess(inner, _) => self.allocate_expr(inner),
+        }
+    }
+
+    /// Reserves room in the frame for the struct or array built by `expr`.
+    fn allocate_temp(&mut self, expr: &Expr) {
+        let offset = self.frame.alloc(self.layouts.of(&self.ty(expr)));
+        self.frame.temps.insert(expr.id, offset);
+    }
+
+    /// The frame offset of the array built by `expr`, along with its element type and size.
+    fn array_temp(&self, expr: &Expr) -> (i64, Ty, usize) {
+        let ty = self.ty(expr);
+        let TyKind::Array(_, elem) = &*ty.kind else {
+            unreachable!("array literal of a non-array type");
+        };
+        (
+            self.frame.temps[&expr.id],
+            elem.clone(),
+            self.layouts.size_of(elem),
+        )
+    }
+
+    fn push(&mut self) {
+        emit!(self, "addiu $sp, $sp, -4");
+        emit!(self, "sw $v0, 0($sp)");
+    }
+
+    fn pop(&mut self, reg: &str) {
+        emit!(self, "lw {reg}, 0($sp)");
+        emit!(self, "addiu $sp, $sp, 4");
+    }
+
+    /// Replaces the address in `$v0` with the value of type `ty` stored there.
+    fn load(&mut self, ty: &Ty) {
+        match *ty.kind {
+            TyKind::Primitive(Primitive::Int) | TyKind::Pointer(_) => emit!(self, "lw $v0, 0($v0)"),
+            TyKind::Primitive(Primitive::Char | Primitive::Bool) => emit!(self, "lbu $v0, 0($v0)"),
+            // Aggregates are represented by their address.
+            TyKind::Struct(_) | TyKind::Array(..) => (),
+            TyKind::Void | TyKind::Infer => unreachable!("cannot load a value of type `{ty}`"),
+        }
+    }
+
+    /// Stores the value of type `ty` in `$v0` at the address in `$t1`, leaving the value in `$v0`.
+    fn store(&mut self, ty: &Ty) {
+        match *ty.kind {
+            TyKind::Primitive(Primitive::Int) | TyKind::Pointer(_) => emit!(self, "sw $v0, 0($t1)"),
+            TyKind::Primitive(Primitive::Char | Primitive::Bool) => emit!(self, "sb $v0, 0($t1)"),
+            TyKind::Struct(_) | TyKind::Array(..) => {
+                emit!(self, "move $t0, $v0");
+                self.copy(self.layouts.of(ty));
+                emit!(self, "move $v0, $t1");
+            }
+            TyKind::Void | TyKind::Infer => unreachable!("cannot store a value of type `{ty}`"),
+        }
+    }
+
+    /// Copies a value with `layout` from the address in `$t0` to the address in `$t1`, a word at a
+    /// time if its alignment allows. Preserves `$t0` and `$t1`.
+    fn copy(&mut self, layout: Layout) {
+        let (unit, load, store) = match layout.align {
+            4 => (4, "lw", "sw"),
+            _ => (1, "lbu", "sb"),
+        };
+        if layout.size <= UNROLL_LIMIT * unit {
+            for offset in (0..layout.size).step_by(unit) {
+                emit!(self, "{load} $t2, {offset}($t0)");
+                emit!(self, "{store} $t2, {offset}($t1)");
+            }
+            return;
+        }
+        let top = self.fresh();
+        emit!(self, "move $t3, $t0");
+        emit!(self, "move $t4, $t1");
+        emit!(self, "addiu $t5, $t0, {}", layout.size);
+        self.label(&top);
+        emit!(self, "{load} $t2, 0($t3)");
+        emit!(self, "{store} $t2, 0($t4)");
+        emit!(self, "addiu $t3, $t3, {unit}");
+        emit!(self, "addiu $t4, $t4, {unit}");
+        emit!(self, "bne $t3, $t5, {top}");
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Invalid => unreachable!("invalid statement after semantic analysis"),
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.stmt(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                if let Some(value) = &local.value {
+                    self.expr(value);
+                    let offset = self.frame.vars[&Binding::Local(stmt.id)];
+                    emit!(self, "addiu $t1, $fp, {offset}");
+                    self.store(&self.analysis.types.decls[&stmt.id]);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (start, end, skip_start, skip_end) = match range {
+                    Range::Inclusive(start, end) => (start, end, false, false),
+                    Range::Exclusive(start, end) => (start, end, true, true),
+                    Range::ExclusiveInclusive(start, end) => (start, end, true, false),
+                    Range::InclusiveExclusive(start, end) => (start, end, false, true),
+                };
+                let (counter, last) = self.frame.counters[&pattern.id];
+                let (top, cont, brk) = (self.fresh(), self.fresh(), self.fresh());
+                self.expr(start);
+                emit!(self, "sw $v0, {counter}($fp)");
+                self.expr(end);
+                emit!(self, "sw $v0, {last}($fp)");
+                // Bounds are adjusted only once the range is known to be non-empty, so that
+                // neither can overflow.
+                emit!(self, "lw $t0, {counter}($fp)");
+                if skip_start || skip_end {
+                    emit!(self, "slt $t2, $t0, $v0");
+                    emit!(self, "beqz $t2, {brk}");
+                }
+                if skip_start {
+                    emit!(self, "addiu $t0, $t0, 1");
+                    emit!(self, "sw $t0, {counter}($fp)");
+                }
+                if skip_end {
+                    emit!(self, "addiu $v0, $v0, -1");
+                    emit!(self, "sw $v0, {last}($fp)");
+                }
+                emit!(self, "slt $t2, $v0, $t0");
+                emit!(self, "bnez $t2, {brk}");
+
+                // The counter stops at the last value, so that it cannot overflow past it.
+                let var = self.frame.vars[&Binding::Pattern(pattern.id)];
+                self.label(&top);
+                emit!(self, "lw $t0, {counter}($fp)");
+                emit!(self, "sw $t0, {var}($fp)");
+                self.loops.push((cont.clone(), brk.clone()));
+                self.stmt(body);
+                self.loops.pop();
+                self.label(&cont);
+                emit!(self, "lw $t0, {counter}($fp)");
+                emit!(self, "lw $t1, {last}($fp)");
+                emit!(self, "beq $t0, $t1, {brk}");
+                emit!(self, "addiu $t0, $t0, 1");
+                emit!(self, "sw $t0, {counter}($fp)");
+                emit!(self, "b {top}");
+                self.label(&brk);
+            }
+            StmtKind::While(cond, body) => {
+                let (start, brk) = (self.fresh(), self.fresh());
+                self.label(&start);
+                self.expr(cond);
+                emit!(self, "beqz $v0, {brk}");
+                self.loops.push((start.clone(), brk.clone()));
+                self.stmt(body);
+                self.loops.pop();
+                emit!(self, "b {start}");
+                self.label(&brk);
+            }
+            StmtKind::If(cond, then, els) => {
+                let (els_label, end) = (self.fresh(), self.fresh());
+                self.expr(cond);
+                emit!(self, "beqz $v0, {els_label}");
+                self.stmt(then);
+                if let Some(els) = els {
+                    emit!(self, "b {end}");
+                    self.label(&els_label);
+                    self.stmt(els);
+                    self.label(&end);
+                } else {
+                    self.label(&els_label);
+                }
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                    self.ret_value();
+                }
+                emit!(self, "b {}", self.ret_label);
+            }
+            StmtKind::Continue => emit!(self, "b {}", self.loops.last().unwrap().0),
+            StmtKind::Break => emit!(self, "b {}", self.loops.last().unwrap().1),
+        }
+    }
+
+    /// Generates `stmt` as the final statement of a non-void function, returning the value of a
+    /// final expression statement.
+    fn tail(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                if let Some((last, init)) = block.stmts.split_last() {
+                    for stmt in init {
+                        self.stmt(stmt);
+                    }
+                    self.tail(last);
+                }
+            }
+            StmtKind::If(cond, then, Some(els)) => {
+                let (els_label, end) = (self.fresh(), self.fresh());
+                self.expr(cond);
+                emit!(self, "beqz $v0, {els_label}");
+                self.tail(then);
+                emit!(self, "b {end}");
+                self.label(&els_label);
+                self.tail(els);
+                self.label(&end);
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+                self.ret_value();
+                emit!(self, "b {}", self.ret_label);
+            }
+            _ => self.stmt(stmt),
+        }
+    }
+
+    /// Moves the value in `$v0` to where the current function returns it.
+    fn ret_value(&mut self) {
+        if let Some(ret_ptr) = self.frame.ret_ptr {
+            emit!(self, "move $t0, $v0");
+            emit!(self, "lw $t1, {ret_ptr}($fp)");
+            self.copy(self.layouts.of(&self.ret));
+            emit!(self, "move $v0, $t1");
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Invalid => unreachable!("invalid expression after semantic analysis"),
+            ExprKind::Literal(lit) => match *lit.value {
+                Value::Int(v) => emit!(self, "li $v0, {}", v as i32),
+                Value::Char(c) => emit!(self, "li $v0, {}", c as u8),
+                Value::Bool(b) => emit!(self, "li $v0, {}", b as u8),
+                Value::Str(s) => {
+                    let label = self.string(s);
+                    emit!(self, "la $v0, {label}");
+                }
+                Value::Invalid => unreachable!("invalid literal after semantic analysis"),
+            },
+            ExprKind::Ident(_)
+            | ExprKind::Deref(_)
+            | ExprKind::Index(..)
+            | ExprKind::FieldAccess(..) => {
+                self.place(expr);
+                self.load(&self.ty(expr));
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                self.place(lhs);
+                self.push();
+                self.expr(rhs);
+                self.pop("$t1");
+                self.store(&self.ty(lhs));
+            }
+            // The address of `lhs` is only computed once, and kept on the stack meanwhile.
+            ExprKind::CompoundAssign(lhs, op, rhs) => {
+                let ty = self.ty(lhs);
+                self.place(lhs);
+                self.push();
+                self.expr(rhs);
+                emit!(self, "move $t1, $v0");
+                emit!(self, "lw $v0, 0($sp)");
+                self.load(&ty);
+                emit!(self, "move $t0, $v0");
+                self.operate(*op, &ty);
+                self.pop("$t1");
+                self.store(&ty);
+            }
+            ExprKind::BinOp(lhs, op, rhs) => self.binop(lhs, *op, rhs),
+            ExprKind::CallFn(call) => self.call(expr, call),
+            ExprKind::Typecast(_, inner) => {
+                self.expr(inner);
+                match *self.ty(expr).kind {
+                    TyKind::Primitive(Primitive::Char) => emit!(self, "andi $v0, $v0, 0xff"),
+                    TyKind::Primitive(Primitive::Bool) => emit!(self, "sltu $v0, $zero, $v0"),
+                    _ => (),
+                }
+            }
+            ExprKind::Ref(inner) => self.place(inner),
+            ExprKind::StructLit(ty, fields) => {
+                let TyKind::Struct(name) = *ty.kind else {
+                    unreachable!("struct literal of a non-struct type");
+                };
+                let temp = self.frame.temps[&expr.id];
+                for field in fields {
+                    let (offset, field_ty) = self.layouts.field(name, field.name);
+                    self.expr(&field.value);
+                    emit!(self, "addiu $t1, $fp, {}", temp + offset as i64);
+                    self.store(&field_ty);
+                }
+                emit!(self, "addiu $v0, $fp, {temp}");
+            }
+            ExprKind::ArrayLit(elems) => {
+                let (temp, elem_ty, size) = self.array_temp(expr);
+                for (i, elem) in elems.iter().enumerate() {
+                    self.expr(elem);
+                    emit!(self, "addiu $t1, $fp, {}", temp + (i * size) as i64);
+                    self.store(&elem_ty);
+                }
+                emit!(self, "addiu $v0, $fp, {temp}");
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                let len = len.get();
+                let (temp, elem_ty, size) = self.array_temp(expr);
+                self.expr(elem);
+                if len > 0 {
+                    emit!(self, "addiu $t1, $fp, {temp}");
+                    self.store(&elem_ty);
+                }
+                if len > 1 {
+                    // A forward copy overlapping its source by one element repeats the first.
+                    emit!(self, "addiu $t0, $fp, {temp}");
+                    emit!(self, "addiu $t1, $fp, {}", temp + size as i64);
+                    let align = self.layouts.of(&elem_ty).align;
+                    self.copy(Layout {
+                        size: (len - 1) * size,
+                        align,
+                    });
+                }
+                emit!(self, "addiu $v0, $fp, {temp}");
+            }
+            ExprKind::Unary(op, inner) => {
+                if let Some(v) = expr.fold_int() {
+                    emit!(self, "li $v0, {v}");
+                    return;
+                }
+                self.expr(inner);
+                match op {
+                    UnaryOp::Neg => emit!(self, "subu $v0, $zero, $v0"),
+                    UnaryOp::Plus => (),
+                    UnaryOp::Not => emit!(self, "xori $v0, $v0, 1"),
+                    UnaryOp::BitNot => emit!(self, "nor $v0, $v0, $zero"),
+                }
+            }
+        }
+    }
+
+    /// Leaves the address of the lvalue `expr` in `$v0`.
+    fn place(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(_) => match self.analysis.resolutions.values[&expr.id] {
+                Binding::Static(item) => emit!(self, "la $v0, {}", self.statics[&item]),
+                binding => emit!(self, "addiu $v0, $fp, {}", self.frame.vars[&binding]),
+            },
+            ExprKind::Deref(inner) => self.expr(inner),
+            ExprKind::Index(base, index) => {
+                self.expr(base);
+                self.push();
+                self.expr(index);
+                let size = self.layouts.size_of(&self.ty(expr));
+                if size.is_power_of_two() {
+                    if size != 1 {
+                        emit!(self, "sll $v0, $v0, {}", size.trailing_zeros());
+                    }
+                } else {
+                    emit!(self, "li $t1, {size}");
+                    emit!(self, "mul $v0, $v0, $t1");
+                }
+                self.pop("$t0");
+                emit!(self, "addu $v0, $t0, $v0");
+            }
+            ExprKind::FieldAccess(base, field) => {
+                // A pointer holds the address of its struct, like the struct's own value does.
+                let Some(name) = self.ty(base).field_owner() else {
+                    unreachable!("field access on a non-struct after semantic analysis");
+                };
+                self.expr(base);
+                let (offset, _) = self.layouts.field(name, *field);
+                if offset != 0 {
+                    emit!(self, "addiu $v0, $v0, {offset}");
+                }
+            }
+            _ => unreachable!("`{expr}` is not an lvalue"),
+        }
+    }
+
+    fn binop(&mut self, lhs: &Expr, op: Operator, rhs: &Expr) {
+        // Logical operators short-circuit.
+        if let Operator::Or | Operator::And = op {
+            let (short, end) = (self.fresh(), self.fresh());
+            let (jump, short_value) = match op {
+                Operator::Or => ("bnez", 1),
+                _ => ("beqz", 0),
+            };
+            self.expr(lhs);
+            emit!(self, "{jump} $v0, {short}");
+            self.expr(rhs);
+            emit!(self, "sltu $v0, $zero, $v0");
+            emit!(self, "b {end}");
+            self.label(&short);
+            emit!(self, "li $v0, {short_value}");
+            self.label(&end);
+            return;
+        }
+
+        let operand = self.ty(lhs);
+        self.expr(lhs);
+        self.push();
+        self.expr(rhs);
+        emit!(self, "move $t1, $v0");
+        self.pop("$t0");
+        self.operate(op, &operand);
+    }
+
+    /// Applies the non-logical `op` to the operands of type `operand` in `$t0` and `$t1`, leaving
+    /// the result in `$v0`.
+    fn operate(&mut self, op: Operator, operand: &Ty) {
+        // `int`s compare as signed, and `char`s and pointers as unsigned.
+        let slt = match *operand.kind {
+            TyKind::Primitive(Primitive::Int) => "slt",
+            _ => "sltu",
+        };
+        match op {
+            Operator::Add => emit!(self, "addu $v0, $t0, $t1"),
+            Operator::Minus => emit!(self, "subu $v0, $t0, $t1"),
+            Operator::Times => emit!(self, "mul $v0, $t0, $t1"),
+            Operator::Div | Operator::Mod => {
+                // Division by zero does not trap on its own.
+                emit!(self, "teq $t1, $zero, 7");
+                emit!(self, "div $t0, $t1");
+                match op {
+                    Operator::Div => emit!(self, "mflo $v0"),
+                    _ => emit!(self, "mfhi $v0"),
+                }
+            }
+            Operator::BitOr => emit!(self, "or $v0, $t0, $t1"),
+            Operator::BitXor => emit!(self, "xor $v0, $t0, $t1"),
+            Operator::BitAnd => emit!(self, "and $v0, $t0, $t1"),
+            Operator::Shl => emit!(self, "sllv $v0, $t0, $t1"),
+            Operator::Shr => emit!(self, "srav $v0, $t0, $t1"),
+            Operator::Lt => emit!(self, "{slt} $v0, $t0, $t1"),
+            Operator::Gt => emit!(self, "{slt} $v0, $t1, $t0"),
+            Operator::Le => {
+                emit!(self, "{slt} $v0, $t1, $t0");
+                emit!(self, "xori $v0, $v0, 1");
+            }
+            Operator::Ge => {
+                emit!(self, "{slt} $v0, $t0, $t1");
+                emit!(self, "xori $v0, $v0, 1");
+            }
+            Operator::Eq => {
+                emit!(self, "xor $v0, $t0, $t1");
+                emit!(self, "sltiu $v0, $v0, 1");
+            }
+            Operator::Ne => {
+                emit!(self, "xor $v0, $t0, $t1");
+                emit!(self, "sltu $v0, $zero, $v0");
+            }
+            Operator::And | Operator::Or => unreachable!(),
+        }
+    }
+
+    fn call(&mut self, expr: &Expr, call: &CallFn) {
+        let Binding::Fn(item) = self.analysis.resolutions.values[&call.name.id] else {
+            unreachable!("call to a non-function after semantic analysis");
+        };
+        if let Some(&service) = self.services.get(&item) {
+            self.service(service, call);
+            return;
+        }
+        let sig = self.sigs[&item];
+        let (offsets, area) = self.arg_offsets(sig);
+        for arg in &call.args {
+            self.expr(arg);
+            self.push();
+        }
+        let n = call.args.len();
+        if area > 0 {
+            emit!(self, "addiu $sp, $sp, -{area}");
+        }
+        for (i, (param, offset)) in sig.params.iter().zip(offsets).enumerate() {
+            // Where the value of the `i`th argument was pushed, relative to `$sp`.
+            let pushed = area + (n - 1 - i) * 4;
+            match *param.ty.kind {
+                TyKind::Struct(_) | TyKind::Array(..) => {
+                    emit!(self, "lw $t0, {pushed}($sp)");
+                    emit!(self, "addiu $t1, $sp, {offset}");
+                    self.copy(self.layouts.of(&param.ty));
+                }
+                TyKind::Primitive(Primitive::Char | Primitive::Bool) => {
+                    emit!(self, "lw $t0, {pushed}($sp)");
+                    emit!(self, "sb $t0, {offset}($sp)");
+                }
+                _ => {
+                    emit!(self, "lw $t0, {pushed}($sp)");
+                    emit!(self, "sw $t0, {offset}($sp)");
+                }
+            }
+        }
+        if let Some(temp) = self.frame.temps.get(&expr.id) {
+            emit!(self, "addiu $a0, $fp, {temp}");
+        }
+        emit!(self, "jal {}", sig.name);
+        if area + n * 4 > 0 {
+            emit!(self, "addiu $sp, $sp, {}", area + n * 4);
+        }
+    }
+
+    /// Performs the runtime `service` with a syscall.
+    fn service(&mut self, service: Service, call: &CallFn) {
+        if let Some(arg) = call.args.first() {
+            self.expr(arg);
+            emit!(self, "move $a0, $v0");
+        }
+        let code = match service {
+            Service::PrintI => 1,
+            Service::PrintS => 4,
+            Service::ReadI => 5,
+            Service::Malloc => 9,
+            Service::PrintC => 11,
+            Service::ReadC => 12,
+        };
+        emit!(self, "li $v0, {code}");
+        emit!(self, "syscall");
+        if let Service::ReadC = service {
+            // The end of input reads as `-1`, which truncates to `(char) 255`.
+            emit!(self, "andi $v0, $v0, 0xff");
+        }
+    }
+
+    /// The label of the string literal `s`.
+    fn string(&mut self, s: Intern<String>) -> String {
+        let next = self.strings.len();
+        self.strings
+            .entry(s)
+            .or_insert_with(|| format!(".Lstr{next}"))
+            .clone()
+    }
+
+    /// Emits the constant `value`, of type `ty`, padding structs as laid out.
+    fn constant(&mut self, ty: &Ty, value: &Const) {
+        match value {
+            Const::Int(v) => emit!(self, ".word {v}"),
+            Const::Char(c) => emit!(self, ".byte {c}"),
+            Const::Bool(b) => emit!(self, ".byte {}", *b as u8),
+            Const::Str(s) => {
+                let label = self.string(*s);
+                emit!(self, ".word {label}");
+            }
+            Const::Struct(name, fields) => {
+                let mut fields = fields
+                    .iter()
+                    .map(|(field, value)| (self.layouts.field(*name, *field), value))
+                    .collect::<Vec<_>>();
+                fields.sort_by_key(|((offset, _), _)| *offset);
+                let mut end = 0;
+                for ((offset, field_ty), value) in fields {
+                    if offset > end {
+                        emit!(self, ".space {}", offset - end);
+                    }
+                    self.constant(&field_ty, value);
+                    end = offset + self.layouts.size_of(&field_ty);
+                }
+                let size = self.layouts.size_of(ty);
+                if size > end {
+                    emit!(self, ".space {}", size - end);
+                }
+            }
+            Const::Array(elems) => {
+                let TyKind::Array(_, elem_ty) = &*ty.kind else {
+                    unreachable!("array constant of a non-array type");
+                };
+                for value in elems {
+                    self.constant(elem_ty, value);
+                }
+            }
+        }
+    }
+
+    /// Generates statics, constants and string literals.
+    fn data(&mut self, items: &[Item]) {
+        emit!(self, ".data");
+        for item in items {
+            let (ItemKind::Static(decl) | ItemKind::Const(decl)) = &item.kind else {
+                continue;
+            };
+            let ty = self.analysis.types.decls[&item.id].clone();
+            let layout = self.layouts.of(&ty);
+            emit!(self, ".align {}", layout.align.trailing_zeros());
+            self.label(&decl.ident.to_string());
+            match self.analysis.consts.get(&item.id) {
+                Some(value) => self.constant(&ty, value),
+                None => emit!(self, ".space {}", layout.size),
+            }
+        }
+
+        let mut strings = self.strings.drain().collect::<Vec<_>>();
+        strings.sort_by(|(_, l1), (_, l2)| l1.cmp(l2));
+        for (s, label) in strings {
+            self.label(&label);
+            // Bytes MARS cannot escape are written out one by one.
+            if s.bytes().all(|b| matches!(b, b' '..=b'~' | b'\n' | b'\t')) {
+                emit!(self, ".asciiz \"{}\"", escape(&s));
+            } else {
+                let bytes = s.bytes().chain([0]).map(|b| b.to_string());
+                emit!(self, ".byte {}", bytes.collect::<Vec<_>>().join(", "));
+            }
+        }
+    }
+}
+
+/// Escapes `s` for use in a MARS string.
+fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '"' | '\\' => format!("\\{c}"),
+            '\n' => "\\n".into(),
+            '\t' => "\\t".into(),
+            c => c.to_string(),
+        })
+        .collect()
+}
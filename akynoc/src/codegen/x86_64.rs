@@ -0,0 +1,1135 @@
+//! x86-64 code generation, producing GNU assembler (AT&T syntax) source.
+//!
+//! Code is generated directly from the AST, one expression at a time. Every expression leaves its
+//! value in `%rax`, or `%eax` for `int` and `char`, with structs and arrays represented by their
+//! address. Intermediate values are pushed onto the stack.
+//!
+//! Calls follow the System V ABI, so that Akyno and C functions can call one another:
+//! - Scalars, and structs and arrays of at most 16 bytes, are passed in the next free registers of
+//!   `%rdi`, `%rsi`, `%rdx`, `%rcx`, `%r8` and `%r9`, one register per eightbyte. Larger
+//!   aggregates, and any argument that does not fit in the remaining registers, are copied onto the
+//!   stack.
+//! - Scalars are returned in `%rax`, and aggregates of at most 16 bytes in `%rax` and `%rdx`.
+//!   Larger aggregates are written to memory provided by the caller, whose address is passed in
+//!   `%rdi` and returned in `%rax`.
+//!
+//! Every variable lives in the stack frame, at a fixed offset from `%rbp`. Statics are placed in
+//! `.data` if they are initialized and in `.bss` otherwise, constants in `.data.rel.ro`, and string
+//! literals in `.rodata`.
+//!
+//! Each runtime [service](crate::runtime) the program declares is defined as a local function,
+//! calling into the C library.
+use std::{collections::HashMap, fmt::Write};
+
+use internment::Intern;
+
+use crate::{
+    ast::{
+        exprs::{CallFn, Expr, ExprKind, Operator, UnaryOp, Value},
+        functions::{FnDefn, FnSig},
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        types::{Ident, Primitive, Ty, TyKind},
+        Item, ItemKind,
+    },
+    interp::Interpreter,
+    layout::{align_to, Layout, Layouts},
+    runtime::Service,
+    sem::{consteval::Const, resolve::Binding, Analysis},
+    util::{CompilerPass, NodeId},
+};
+
+/// Size of a pointer, in bytes.
+const PTR_SIZE: usize = 8;
+
+/// Registers used to pass arguments, in order.
+const ARG_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+/// Writes a single instruction or directive.
+macro_rules! emit {
+    ($gen:expr, $($arg:tt)*) => {
+        writeln!($gen.out, "\t{}", format_args!($($arg)*)).unwrap()
+    };
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+}
+
+impl Reg {
+    /// The name of the register's low `size` bytes.
+    fn name(self, size: usize) -> &'static str {
+        let names = match self {
+            Reg::Rax => ["%al", "%ax", "%eax", "%rax"],
+            Reg::Rcx => ["%cl", "%cx", "%ecx", "%rcx"],
+            Reg::Rdx => ["%dl", "%dx", "%edx", "%rdx"],
+            Reg::Rsi => ["%sil", "%si", "%esi", "%rsi"],
+            Reg::Rdi => ["%dil", "%di", "%edi", "%rdi"],
+            Reg::R8 => ["%r8b", "%r8w", "%r8d", "%r8"],
+            Reg::R9 => ["%r9b", "%r9w", "%r9d", "%r9"],
+        };
+        names[size.trailing_zeros() as usize]
+    }
+}
+
+/// How a value is passed to or returned from a function.
+#[derive(Debug, Clone, Copy)]
+enum Class {
+    /// In registers, one per eightbyte.
+    Regs(usize),
+    /// In memory.
+    Memory,
+}
+
+/// Where a single argument is passed.
+#[derive(Debug, Clone, Copy)]
+enum ArgLoc {
+    /// In consecutive registers, starting with the given index into [`ARG_REGS`].
+    Regs(usize),
+    /// On the stack, at the given offset from the first stack argument.
+    Stack(usize),
+}
+
+/// Stack frame of the function being generated. Offsets are relative to `%rbp`.
+#[derive(Default)]
+struct Frame {
+    size: usize,
+    vars: HashMap<Binding, i64>,
+    /// Temporaries holding the structs and arrays returned by calls, keyed by the call.
+    temps: HashMap<NodeId, i64>,
+    /// The counter and last value of each `for` loop, keyed by its pattern.
+    counters: HashMap<NodeId, (i64, i64)>,
+    /// Where the address to return a large struct or array to is kept.
+    ret_ptr: Option<i64>,
+}
+
+impl Frame {
+    fn alloc(&mut self, layout: Layout) -> i64 {
+        self.size = align_to(self.size + layout.size, layout.align);
+        -(self.size as i64)
+    }
+}
+
+struct Generator<'ast> {
+    analysis: &'ast Analysis,
+    layouts: Layouts,
+    /// Maps the [`NodeId`] of each function item to its signature.
+    sigs: HashMap<NodeId, &'ast FnSig>,
+    /// Maps the [`NodeId`] of each static item to its name.
+    statics: HashMap<NodeId, Ident>,
+    /// Labels of the string literals seen so far.
+    strings: HashMap<Intern<String>, String>,
+    out: String,
+    frame: Frame,
+    /// Return type of the function being generated.
+    ret: Ty,
+    /// Label of the epilogue of the function being generated.
+    ret_label: String,
+    /// The `continue` and `break` labels of each enclosing loop.
+    loops: Vec<(String, String)>,
+    labels: usize,
+    /// Number of eightbytes pushed onto the stack since the frame was set up.
+    pushed: usize,
+    /// Whether a division checks for a zero divisor, so that [`DIV_FAULT`] must be generated.
+    div_fault: bool,
+}
+
+/// Generates assembly for a program that passed semantic analysis.
+pub fn generate(items: &[Item], analysis: &Analysis) -> String {
+    let mut sigs = HashMap::new();
+    let mut statics = HashMap::new();
+    for item in items {
+        match &item.kind {
+            ItemKind::FnDecl(decl) => {
+                sigs.insert(item.id, &decl.sig);
+            }
+            ItemKind::FnDefn(defn) => {
+                sigs.insert(item.id, &defn.sig);
+            }
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                statics.insert(item.id, decl.ident);
+            }
+            ItemKind::StructDecl(_) | ItemKind::Invalid => (),
+        }
+    }
+    let mut generator = Generator {
+        analysis,
+        layouts: Layouts::new(items, PTR_SIZE),
+        sigs,
+        statics,
+        strings: HashMap::new(),
+        out: String::new(),
+        frame: Frame::default(),
+        ret: TyKind::Void.into(),
+        ret_label: String::new(),
+        loops: vec![],
+        labels: 0,
+        pushed: 0,
+        div_fault: false,
+    };
+    emit!(generator, ".text");
+    for item in items {
+        if let ItemKind::FnDefn(defn) = &item.kind {
+            generator.function(item, defn);
+        }
+    }
+    generator.div_fault();
+    generator.services(&Service::declared(items));
+    generator.data(items);
+    generator.out
+}
+
+/// Label of the code run on division by zero, which exits with the status of an interpreter fault.
+const DIV_FAULT: &str = ".Ldiv_fault";
+
+fn is_aggregate(ty: &Ty) -> bool {
+    matches!(*ty.kind, TyKind::Struct(_) | TyKind::Array(..))
+}
+
+impl<'ast> Generator<'ast> {
+    fn fresh(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.out, "{label}:").unwrap();
+    }
+
+    fn ty(&self, expr: &Expr) -> Ty {
+        self.analysis.types.exprs[&expr.id].clone()
+    }
+
+    fn classify(&self, ty: &Ty) -> Class {
+        match self.layouts.size_of(ty) {
+            size if size > 16 && is_aggregate(ty) => Class::Memory,
+            size => Class::Regs(size.div_ceil(8)),
+        }
+    }
+
+    /// Assigns each parameter of `sig` a location, returning them along with the size of the stack
+    /// area needed for the arguments.
+    fn arg_locs(&self, sig: &FnSig) -> (Vec<ArgLoc>, usize) {
+        let mut next_reg = match self.classify(&sig.ty) {
+            Class::Memory => 1,
+            Class::Regs(_) => 0,
+        };
+        let mut stack = 0;
+        let locs = sig
+            .params
+            .iter()
+            .map(|param| match self.classify(&param.ty) {
+                Class::Regs(n) if next_reg + n <= ARG_REGS.len() => {
+                    next_reg += n;
+                    ArgLoc::Regs(next_reg - n)
+                }
+                _ => {
+                    let offset = stack;
+                    stack += align_to(self.layouts.size_of(&param.ty), 8);
+                    ArgLoc::Stack(offset)
+                }
+            })
+            .collect();
+        (locs, stack)
+    }
+
+    fn function(&mut self, item: &Item, defn: &FnDefn) {
+        self.frame = Frame::default();
+        self.pushed = 0;
+        self.ret = defn.sig.ty.clone();
+        self.ret_label = self.fresh();
+        if let Class::Memory = self.classify(&defn.sig.ty) {
+            self.frame.ret_ptr = Some(self.frame.alloc(Layout { size: 8, align: 8 }));
+        }
+        let (locs, _) = self.arg_locs(&defn.sig);
+        for (index, (param, loc)) in defn.sig.params.iter().zip(&locs).enumerate() {
+            let offset = match loc {
+                ArgLoc::Regs(_) => self.frame.alloc(self.layouts.of(&param.ty)),
+                // Above the saved `%rbp` and the return address.
+                ArgLoc::Stack(offset) => 16 + *offset as i64,
+            };
+            let binding = Binding::Param {
+                item: item.id,
+                index,
+            };
+            self.frame.vars.insert(binding, offset);
+        }
+        self.allocate(&defn.block);
+
+        let name = defn.sig.name;
+        emit!(self, ".globl {name}");
+        emit!(self, ".type {name}, @function");
+        self.label(&name.to_string());
+        emit!(self, "pushq %rbp");
+        emit!(self, "movq %rsp, %rbp");
+        let size = align_to(self.frame.size, 16);
+        if size > 0 {
+            emit!(self, "subq ${size}, %rsp");
+        }
+        if let Some(ret_ptr) = self.frame.ret_ptr {
+            emit!(self, "movq %rdi, {ret_ptr}(%rbp)");
+        }
+        for (index, (param, loc)) in defn.sig.params.iter().zip(locs).enumerate() {
+            let ArgLoc::Regs(first) = loc else {
+                continue;
+            };
+            let offset = self.frame.vars[&Binding::Param {
+                item: item.id,
+                index,
+            }];
+            let size = self.layouts.size_of(&param.ty);
+            for (k, reg) in ARG_REGS[first..].iter().take(size.div_ceil(8)).enumerate() {
+                self.store_partial(*reg, "%rbp", offset + 8 * k as i64, (size - 8 * k).min(8));
+            }
+        }
+
+        if *self.ret.kind == TyKind::Void {
+            self.stmt(&defn.block);
+        } else {
+            self.tail(&defn.block);
+            // Type checking makes sure every path returns, so the end is never reached.
+            emit!(self, "ud2");
+        }
+        let ret_label = self.ret_label.clone();
+        self.label(&ret_label);
+        if *self.ret.kind == TyKind::Void && *name.name == "main" {
+            emit!(self, "xorl %eax, %eax");
+        }
+        emit!(self, "leave");
+        emit!(self, "ret");
+        emit!(self, ".size {name}, .-{name}");
+    }
+
+    /// Reserves space in the frame for every variable and temporary in `stmt`.
+    fn allocate(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.allocate(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                let ty = self.analysis.types.decls[&stmt.id].clone();
+                let offset = self.frame.alloc(self.layouts.of(&ty));
+                self.frame.vars.insert(Binding::Local(stmt.id), offset);
+                if let Some(value) = &local.value {
+                    self.allocate_expr(value);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                self.allocate_expr(start);
+                self.allocate_expr(end);
+                let ty = self.analysis.types.decls[&pattern.id].clone();
+                let offset = self.frame.alloc(self.layouts.of(&ty));
+                self.frame.vars.insert(Binding::Pattern(pattern.id), offset);
+                let quad = Layout { size: 8, align: 8 };
+                let counter = (self.frame.alloc(quad), self.frame.alloc(quad));
+                self.frame.counters.insert(pattern.id, counter);
+                self.allocate(body);
+            }
+            StmtKind::While(cond, body) => {
+                self.allocate_expr(cond);
+                self.allocate(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.allocate_expr(cond);
+                self.allocate(then);
+                if let Some(els) = els {
+                    self.allocate(els);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.allocate_expr(expr),
+            StmtKind::Return(None) | StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn allocate_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Invalid | ExprKind::Literal(_) | ExprKind::Ident(_) => (),
+            ExprKind::Assign(lhs, rhs)
+            | ExprKind::CompoundAssign(lhs, _, rhs)
+            | ExprKind::BinOp(lhs, _, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.allocate_expr(lhs);
+                self.allocate_expr(rhs);
+            }
+            ExprKind::CallFn(call) => {
+                for arg in &call.args {
+                    self.allocate_expr(arg);
+                }
+                let ty = self.ty(expr);
+                if is_aggregate(&ty) {
+                    self.allocate_temp(expr);
+                }
+            }
+            ExprKind::StructLit(_, fields) => {
+                for field in fields {
+                    self.allocate_expr(&field.value);
+                }
+                self.allocate_temp(expr);
+            }
+            ExprKind::ArrayLit(elems) => {
+                for elem in elems {
+                    self.allocate_expr(elem);
+                }
+                self.allocate_temp(expr);
+            }
+            ExprKind::ArrayRepeat(elem, _) => {
+                self.allocate_expr(elem);
+                self.allocate_temp(expr);
+            }
+            ExprKind::Typecast(_, inner)
+            | ExprKind::Deref(inner)
+            | ExprKind::Ref(inner)
+            | ExprKind::Unary(_, inner)
+            | ExprKind::FieldAccess(inner, _) => self.allocate_expr(inner),
+        }
+    }
+
+    /// Reserves room in the frame for the struct or array built by `expr`.
+    fn allocate_temp(&mut self, expr: &Expr) {
+        let offset = self.frame.alloc(self.layouts.of(&self.ty(expr)));
+        self.frame.temps.insert(expr.id, offset);
+    }
+
+    /// The frame offset of the array built by `expr`, along with its element type and size.
+    fn array_temp(&self, expr: &Expr) -> (i64, Ty, usize) {
+        let ty = self.ty(expr);
+        let TyKind::Array(_, elem) = &*ty.kind else {
+            unreachable!("array literal of a non-array type");
+        };
+        (
+            self.frame.temps[&expr.id],
+            elem.clone(),
+            self.layouts.size_of(elem),
+        )
+    }
+
+    fn push(&mut self) {
+        emit!(self, "pushq %rax");
+        self.pushed += 1;
+    }
+
+    fn pop(&mut self, reg: Reg) {
+        emit!(self, "popq {}", reg.name(8));
+        self.pushed -= 1;
+    }
+
+    /// Loads the `size` bytes at `offset(base)` into `dst`, zero-extended.
+    fn load_partial(&mut self, dst: Reg, base: &str, offset: i64, size: usize) {
+        match size {
+            8 => emit!(self, "movq {offset}({base}), {}", dst.name(8)),
+            4 => emit!(self, "movl {offset}({base}), {}", dst.name(4)),
+            2 => emit!(self, "movzwl {offset}({base}), {}", dst.name(4)),
+            1 => emit!(self, "movzbl {offset}({base}), {}", dst.name(4)),
+            _ => {
+                emit!(self, "xorl {0}, {0}", dst.name(4));
+                for byte in (0..size as i64).rev() {
+                    emit!(self, "shlq $8, {}", dst.name(8));
+                    emit!(self, "movzbl {}({base}), %r10d", offset + byte);
+                    emit!(self, "orq %r10, {}", dst.name(8));
+                }
+            }
+        }
+    }
+
+    /// Stores the low `size` bytes of `src` at `offset(base)`. May clobber `src`.
+    fn store_partial(&mut self, src: Reg, base: &str, offset: i64, size: usize) {
+        match size {
+            8 | 4 | 2 | 1 => emit!(self, "mov {}, {offset}({base})", src.name(size)),
+            _ => {
+                for byte in 0..size as i64 {
+                    emit!(self, "movb {}, {}({base})", src.name(1), offset + byte);
+                    emit!(self, "shrq $8, {}", src.name(8));
+                }
+            }
+        }
+    }
+
+    /// Replaces the address in `%rax` with the value of type `ty` stored there.
+    fn load(&mut self, ty: &Ty) {
+        match *ty.kind {
+            TyKind::Primitive(Primitive::Int) => emit!(self, "movl (%rax), %eax"),
+            TyKind::Primitive(Primitive::Char | Primitive::Bool) => {
+                emit!(self, "movzbl (%rax), %eax")
+            }
+            TyKind::Pointer(_) => emit!(self, "movq (%rax), %rax"),
+            // Aggregates are represented by their address.
+            TyKind::Struct(_) | TyKind::Array(..) => (),
+            TyKind::Void | TyKind::Infer => unreachable!("cannot load a value of type `{ty}`"),
+        }
+    }
+
+    /// Stores the value of type `ty` in `%rax` at the address in `%rcx`, leaving the value in
+    /// `%rax`.
+    fn store(&mut self, ty: &Ty) {
+        match *ty.kind {
+            TyKind::Primitive(Primitive::Int) => emit!(self, "movl %eax, (%rcx)"),
+            TyKind::Primitive(Primitive::Char | Primitive::Bool) => emit!(self, "movb %al, (%rcx)"),
+            TyKind::Pointer(_) => emit!(self, "movq %rax, (%rcx)"),
+            TyKind::Struct(_) | TyKind::Array(..) => {
+                emit!(self, "movq %rax, %rsi");
+                emit!(self, "movq %rcx, %rdi");
+                emit!(self, "movq %rcx, %rax");
+                self.copy(self.layouts.size_of(ty));
+            }
+            TyKind::Void | TyKind::Infer => unreachable!("cannot store a value of type `{ty}`"),
+        }
+    }
+
+    /// Copies `size` bytes from the address in `%rsi` to the address in `%rdi`.
+    fn copy(&mut self, size: usize) {
+        emit!(self, "movq ${size}, %rcx");
+        emit!(self, "rep movsb");
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Invalid => unreachable!("invalid statement after semantic analysis"),
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.stmt(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                if let Some(value) = &local.value {
+                    self.expr(value);
+                    let offset = self.frame.vars[&Binding::Local(stmt.id)];
+                    emit!(self, "leaq {offset}(%rbp), %rcx");
+                    self.store(&self.analysis.types.decls[&stmt.id]);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (start, end, skip_start, skip_end) = match range {
+                    Range::Inclusive(start, end) => (start, end, false, false),
+                    Range::Exclusive(start, end) => (start, end, true, true),
+                    Range::ExclusiveInclusive(start, end) => (start, end, true, false),
+                    Range::InclusiveExclusive(start, end) => (start, end, false, true),
+                };
+                // The counter is 64 bits wide, so that it cannot overflow past the last value.
+                let (counter, last) = self.frame.counters[&pattern.id];
+                self.expr(start);
+                emit!(self, "movslq %eax, %rax");
+                if skip_start {
+                    emit!(self, "incq %rax");
+                }
+                emit!(self, "movq %rax, {counter}(%rbp)");
+                self.expr(end);
+                emit!(self, "movslq %eax, %rax");
+                if skip_end {
+                    emit!(self, "decq %rax");
+                }
+                emit!(self, "movq %rax, {last}(%rbp)");
+
+                let (cond, cont, brk) = (self.fresh(), self.fresh(), self.fresh());
+                let var = self.frame.vars[&Binding::Pattern(pattern.id)];
+                self.label(&cond);
+                emit!(self, "movq {counter}(%rbp), %rax");
+                emit!(self, "cmpq {last}(%rbp), %rax");
+                emit!(self, "jg {brk}");
+                emit!(self, "movl %eax, {var}(%rbp)");
+                self.loops.push((cont.clone(), brk.clone()));
+                self.stmt(body);
+                self.loops.pop();
+                self.label(&cont);
+                emit!(self, "incq {counter}(%rbp)");
+                emit!(self, "jmp {cond}");
+                self.label(&brk);
+            }
+            StmtKind::While(cond, body) => {
+                let (start, brk) = (self.fresh(), self.fresh());
+                self.label(&start);
+                self.expr(cond);
+                emit!(self, "testl %eax, %eax");
+                emit!(self, "je {brk}");
+                self.loops.push((start.clone(), brk.clone()));
+                self.stmt(body);
+                self.loops.pop();
+                emit!(self, "jmp {start}");
+                self.label(&brk);
+            }
+            StmtKind::If(cond, then, els) => {
+                let (els_label, end) = (self.fresh(), self.fresh());
+                self.expr(cond);
+                emit!(self, "testl %eax, %eax");
+                emit!(self, "je {els_label}");
+                self.stmt(then);
+                if let Some(els) = els {
+                    emit!(self, "jmp {end}");
+                    self.label(&els_label);
+                    self.stmt(els);
+                    self.label(&end);
+                } else {
+                    self.label(&els_label);
+                }
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                    self.ret_value();
+                }
+                emit!(self, "jmp {}", self.ret_label);
+            }
+            StmtKind::Continue => emit!(self, "jmp {}", self.loops.last().unwrap().0),
+            StmtKind::Break => emit!(self, "jmp {}", self.loops.last().unwrap().1),
+        }
+    }
+
+    /// Generates `stmt` as the final statement of a non-void function, returning the value of a
+    /// final expression statement.
+    fn tail(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                if let Some((last, init)) = block.stmts.split_last() {
+                    for stmt in init {
+                        self.stmt(stmt);
+                    }
+                    self.tail(last);
+                }
+            }
+            StmtKind::If(cond, then, Some(els)) => {
+                let (els_label, end) = (self.fresh(), self.fresh());
+                self.expr(cond);
+                emit!(self, "testl %eax, %eax");
+                emit!(self, "je {els_label}");
+                self.tail(then);
+                emit!(self, "jmp {end}");
+                self.label(&els_label);
+                self.tail(els);
+                self.label(&end);
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+                self.ret_value();
+                emit!(self, "jmp {}", self.ret_label);
+            }
+            _ => self.stmt(stmt),
+        }
+    }
+
+    /// Moves the value in `%rax` to where the current function returns it.
+    fn ret_value(&mut self) {
+        let size = self.layouts.size_of(&self.ret);
+        match self.classify(&self.ret) {
+            _ if !is_aggregate(&self.ret) => (),
+            Class::Regs(n) => {
+                emit!(self, "movq %rax, %rsi");
+                if n == 2 {
+                    self.load_partial(Reg::Rdx, "%rsi", 8, size - 8);
+                }
+                if n >= 1 {
+                    self.load_partial(Reg::Rax, "%rsi", 0, size.min(8));
+                }
+            }
+            Class::Memory => {
+                let ret_ptr = self.frame.ret_ptr.unwrap();
+                emit!(self, "movq %rax, %rsi");
+                emit!(self, "movq {ret_ptr}(%rbp), %rdi");
+                self.copy(size);
+                emit!(self, "movq {ret_ptr}(%rbp), %rax");
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Invalid => unreachable!("invalid expression after semantic analysis"),
+            ExprKind::Literal(lit) => match *lit.value {
+                Value::Int(v) => emit!(self, "movl ${}, %eax", v as i32),
+                Value::Char(c) => emit!(self, "movl ${}, %eax", c as u8),
+                Value::Bool(b) => emit!(self, "movl ${}, %eax", b as u8),
+                Value::Str(s) => {
+                    let label = self.string(s);
+                    emit!(self, "leaq {label}(%rip), %rax");
+                }
+                Value::Invalid => unreachable!("invalid literal after semantic analysis"),
+            },
+            ExprKind::Ident(_)
+            | ExprKind::Deref(_)
+            | ExprKind::Index(..)
+            | ExprKind::FieldAccess(..) => {
+                self.place(expr);
+                self.load(&self.ty(expr));
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                self.place(lhs);
+                self.push();
+                self.expr(rhs);
+                self.pop(Reg::Rcx);
+                self.store(&self.ty(lhs));
+            }
+            // The address of `lhs` is only computed once, and kept on the stack meanwhile.
+            ExprKind::CompoundAssign(lhs, op, rhs) => {
+                let ty = self.ty(lhs);
+                self.place(lhs);
+                self.push();
+                self.expr(rhs);
+                emit!(self, "movq %rax, %rcx");
+                emit!(self, "movq (%rsp), %rax");
+                self.load(&ty);
+                self.operate(*op, &ty);
+                self.pop(Reg::Rcx);
+                self.store(&ty);
+            }
+            ExprKind::BinOp(lhs, op, rhs) => self.binop(lhs, *op, rhs),
+            ExprKind::CallFn(call) => self.call(expr, call),
+            ExprKind::Typecast(_, inner) => {
+                self.expr(inner);
+                match *self.ty(expr).kind {
+                    TyKind::Primitive(Primitive::Char) => emit!(self, "movzbl %al, %eax"),
+                    TyKind::Primitive(Primitive::Bool) => {
+                        emit!(self, "testl %eax, %eax");
+                        emit!(self, "setne %al");
+                        emit!(self, "movzbl %al, %eax");
+                    }
+                    _ => (),
+                }
+            }
+            ExprKind::Ref(inner) => self.place(inner),
+            ExprKind::StructLit(ty, fields) => {
+                let TyKind::Struct(name) = *ty.kind else {
+                    unreachable!("struct literal of a non-struct type");
+                };
+                let temp = self.frame.temps[&expr.id];
+                for field in fields {
+                    let (offset, field_ty) = self.layouts.field(name, field.name);
+                    self.expr(&field.value);
+                    emit!(self, "leaq {}(%rbp), %rcx", temp + offset as i64);
+                    self.store(&field_ty);
+                }
+                emit!(self, "leaq {temp}(%rbp), %rax");
+            }
+            ExprKind::ArrayLit(elems) => {
+                let (temp, elem_ty, size) = self.array_temp(expr);
+                for (i, elem) in elems.iter().enumerate() {
+                    self.expr(elem);
+                    emit!(self, "leaq {}(%rbp), %rcx", temp + (i * size) as i64);
+                    self.store(&elem_ty);
+                }
+                emit!(self, "leaq {temp}(%rbp), %rax");
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                let len = len.get();
+                let (temp, elem_ty, size) = self.array_temp(expr);
+                self.expr(elem);
+                if len > 0 {
+                    emit!(self, "leaq {temp}(%rbp), %rcx");
+                    self.store(&elem_ty);
+                }
+                if len > 1 {
+                    // A forward copy overlapping its source by one element repeats the first.
+                    emit!(self, "leaq {temp}(%rbp), %rsi");
+                    emit!(self, "leaq {}(%rbp), %rdi", temp + size as i64);
+                    self.copy((len - 1) * size);
+                }
+                emit!(self, "leaq {temp}(%rbp), %rax");
+            }
+            ExprKind::Unary(op, inner) => {
+                if let Some(v) = expr.fold_int() {
+                    emit!(self, "movl ${v}, %eax");
+                    return;
+                }
+                self.expr(inner);
+                match op {
+                    UnaryOp::Neg => emit!(self, "negl %eax"),
+                    UnaryOp::Plus => (),
+                    UnaryOp::Not => emit!(self, "xorl $1, %eax"),
+                    UnaryOp::BitNot => emit!(self, "notl %eax"),
+                }
+            }
+        }
+    }
+
+    /// Leaves the address of the lvalue `expr` in `%rax`.
+    fn place(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(_) => match self.analysis.resolutions.values[&expr.id] {
+                Binding::Static(item) => emit!(self, "leaq {}(%rip), %rax", self.statics[&item]),
+                binding => emit!(self, "leaq {}(%rbp), %rax", self.frame.vars[&binding]),
+            },
+            ExprKind::Deref(inner) => self.expr(inner),
+            ExprKind::Index(base, index) => {
+                self.expr(base);
+                self.push();
+                self.expr(index);
+                emit!(self, "movslq %eax, %rcx");
+                let size = self.layouts.size_of(&self.ty(expr));
+                if size != 1 {
+                    emit!(self, "imulq ${size}, %rcx");
+                }
+                self.pop(Reg::Rax);
+                emit!(self, "addq %rcx, %rax");
+            }
+            ExprKind::FieldAccess(base, field) => {
+                // A pointer holds the address of its struct, like the struct's own value does.
+                let Some(name) = self.ty(base).field_owner() else {
+                    unreachable!("field access on a non-struct after semantic analysis");
+                };
+                self.expr(base);
+                let (offset, _) = self.layouts.field(name, *field);
+                if offset != 0 {
+                    emit!(self, "addq ${offset}, %rax");
+                }
+            }
+            _ => unreachable!("`{expr}` is not an lvalue"),
+        }
+    }
+
+    fn binop(&mut self, lhs: &Expr, op: Operator, rhs: &Expr) {
+        // Logical operators short-circuit.
+        if let Operator::Or | Operator::And = op {
+            let (short, end) = (self.fresh(), self.fresh());
+            let (jump, short_value) = match op {
+                Operator::Or => ("jne", 1),
+                _ => ("je", 0),
+            };
+            self.expr(lhs);
+            emit!(self, "testl %eax, %eax");
+            emit!(self, "{jump} {short}");
+            self.expr(rhs);
+            emit!(self, "testl %eax, %eax");
+            emit!(self, "setne %al");
+            emit!(self, "movzbl %al, %eax");
+            emit!(self, "jmp {end}");
+            self.label(&short);
+            emit!(self, "movl ${short_value}, %eax");
+            self.label(&end);
+            return;
+        }
+
+        let operand = self.ty(lhs);
+        self.expr(lhs);
+        self.push();
+        self.expr(rhs);
+        emit!(self, "movq %rax, %rcx");
+        self.pop(Reg::Rax);
+        self.operate(op, &operand);
+    }
+
+    /// Applies the non-logical `op` to the operands of type `operand` in `%rax` and `%rcx`, leaving
+    /// the result in `%rax`.
+    fn operate(&mut self, op: Operator, operand: &Ty) {
+        match op {
+            Operator::Add => emit!(self, "addl %ecx, %eax"),
+            Operator::Minus => emit!(self, "subl %ecx, %eax"),
+            Operator::Times => emit!(self, "imull %ecx, %eax"),
+            Operator::Div | Operator::Mod => self.divide(op),
+            Operator::BitOr => emit!(self, "orl %ecx, %eax"),
+            Operator::BitXor => emit!(self, "xorl %ecx, %eax"),
+            Operator::BitAnd => emit!(self, "andl %ecx, %eax"),
+            Operator::Shl => emit!(self, "sall %cl, %eax"),
+            Operator::Shr => emit!(self, "sarl %cl, %eax"),
+            _ => {
+                // `int`s compare as signed, and `char`s and pointers as unsigned.
+                let signed = *operand.kind == TyKind::Primitive(Primitive::Int);
+                let set = match (op, signed) {
+                    (Operator::Lt, true) => "setl",
+                    (Operator::Le, true) => "setle",
+                    (Operator::Gt, true) => "setg",
+                    (Operator::Ge, true) => "setge",
+                    (Operator::Lt, false) => "setb",
+                    (Operator::Le, false) => "setbe",
+                    (Operator::Gt, false) => "seta",
+                    (Operator::Ge, false) => "setae",
+                    (Operator::Eq, _) => "sete",
+                    (Operator::Ne, _) => "setne",
+                    _ => unreachable!(),
+                };
+                match *operand.kind {
+                    TyKind::Pointer(_) => emit!(self, "cmpq %rcx, %rax"),
+                    _ => emit!(self, "cmpl %ecx, %eax"),
+                }
+                emit!(self, "{set} %al");
+                emit!(self, "movzbl %al, %eax");
+            }
+        }
+    }
+
+    fn call(&mut self, expr: &Expr, call: &CallFn) {
+        let Binding::Fn(item) = self.analysis.resolutions.values[&call.name.id] else {
+            unreachable!("call to a non-function after semantic analysis");
+        };
+        let sig = self.sigs[&item];
+        let (locs, stack) = self.arg_locs(sig);
+        for arg in &call.args {
+            self.expr(arg);
+            self.push();
+        }
+        let n = call.args.len();
+        // `%rsp` must be 16-byte aligned at the call.
+        let area = align_to(stack + self.pushed * 8, 16) - self.pushed * 8;
+        if area > 0 {
+            emit!(self, "subq ${area}, %rsp");
+        }
+        // Where the value of the `i`th argument was pushed, relative to `%rsp`.
+        let pushed = |i: usize| area + (n - 1 - i) * 8;
+
+        // Copy arguments onto the stack first, since copying clobbers argument registers.
+        for (i, (param, loc)) in sig.params.iter().zip(&locs).enumerate() {
+            let ArgLoc::Stack(offset) = *loc else {
+                continue;
+            };
+            if is_aggregate(&param.ty) {
+                emit!(self, "movq {}(%rsp), %rsi", pushed(i));
+                emit!(self, "leaq {offset}(%rsp), %rdi");
+                self.copy(self.layouts.size_of(&param.ty));
+            } else {
+                emit!(self, "movq {}(%rsp), %rax", pushed(i));
+                emit!(self, "movq %rax, {offset}(%rsp)");
+            }
+        }
+        for (i, (param, loc)) in sig.params.iter().zip(&locs).enumerate() {
+            let ArgLoc::Regs(first) = *loc else {
+                continue;
+            };
+            if is_aggregate(&param.ty) {
+                let size = self.layouts.size_of(&param.ty);
+                emit!(self, "movq {}(%rsp), %rax", pushed(i));
+                for (k, reg) in ARG_REGS[first..].iter().take(size.div_ceil(8)).enumerate() {
+                    self.load_partial(*reg, "%rax", 8 * k as i64, (size - 8 * k).min(8));
+                }
+            } else {
+                emit!(
+                    self,
+                    "movq {}(%rsp), {}",
+                    pushed(i),
+                    ARG_REGS[first].name(8)
+                );
+            }
+        }
+        let ret = sig.ty.clone();
+        let temp = self.frame.temps.get(&expr.id).copied();
+        if let Class::Memory = self.classify(&ret) {
+            emit!(self, "leaq {}(%rbp), %rdi", temp.unwrap());
+        }
+        emit!(self, "call {}", sig.name);
+        if area + n * 8 > 0 {
+            emit!(self, "addq ${}, %rsp", area + n * 8);
+        }
+        self.pushed -= n;
+
+        match (&*ret.kind, self.classify(&ret)) {
+            (TyKind::Primitive(Primitive::Char | Primitive::Bool), _) => {
+                emit!(self, "movzbl %al, %eax")
+            }
+            (TyKind::Struct(_) | TyKind::Array(..), class) => {
+                let temp = temp.unwrap();
+                let size = self.layouts.size_of(&ret);
+                if let Class::Regs(n) = class {
+                    if n >= 1 {
+                        self.store_partial(Reg::Rax, "%rbp", temp, size.min(8));
+                    }
+                    if n == 2 {
+                        self.store_partial(Reg::Rdx, "%rbp", temp + 8, size - 8);
+                    }
+                }
+                emit!(self, "leaq {temp}(%rbp), %rax");
+            }
+            _ => (),
+        }
+    }
+
+    /// Divides `%eax` by `%ecx`, leaving the quotient or remainder of `op` in `%eax`. As in the
+    /// interpreter, dividing by zero faults, and dividing by `-1` wraps rather than trapping.
+    fn divide(&mut self, op: Operator) {
+        let (divide, end) = (self.fresh(), self.fresh());
+        self.div_fault = true;
+        emit!(self, "testl %ecx, %ecx");
+        emit!(self, "je {DIV_FAULT}");
+        emit!(self, "cmpl $-1, %ecx");
+        emit!(self, "jne {divide}");
+        match op {
+            Operator::Div => emit!(self, "negl %eax"),
+            _ => emit!(self, "xorl %eax, %eax"),
+        }
+        emit!(self, "jmp {end}");
+        self.label(&divide);
+        emit!(self, "cltd");
+        emit!(self, "idivl %ecx");
+        if op == Operator::Mod {
+            emit!(self, "movl %edx, %eax");
+        }
+        self.label(&end);
+    }
+
+    /// Generates [`DIV_FAULT`] if any division jumps to it.
+    fn div_fault(&mut self) {
+        if !self.div_fault {
+            return;
+        }
+        self.label(DIV_FAULT);
+        // The stack may be anywhere within a frame, so it is realigned for the call.
+        emit!(self, "andq $-16, %rsp");
+        emit!(self, "movl ${}, %edi", Interpreter::FAIL_CODE);
+        emit!(self, "call exit@PLT");
+    }
+
+    /// Generates each of `services` as a local function.
+    fn services(&mut self, services: &[Service]) {
+        if services.is_empty() {
+            return;
+        }
+        for service in services {
+            let name = service.name();
+            emit!(self, ".type {name}, @function");
+            self.label(name);
+            match service {
+                Service::PrintI => {
+                    emit!(self, "movl %edi, %esi");
+                    emit!(self, "leaq .Lfmt_int(%rip), %rdi");
+                    emit!(self, "xorl %eax, %eax");
+                    emit!(self, "jmp printf@PLT");
+                }
+                Service::PrintC => {
+                    emit!(self, "movzbl %dil, %edi");
+                    emit!(self, "jmp putchar@PLT");
+                }
+                Service::PrintS => {
+                    emit!(self, "movq %rdi, %rsi");
+                    emit!(self, "leaq .Lfmt_str(%rip), %rdi");
+                    emit!(self, "xorl %eax, %eax");
+                    emit!(self, "jmp printf@PLT");
+                }
+                Service::ReadI => {
+                    // The number is read into a zeroed slot, which is left as is if there is none.
+                    emit!(self, "subq $24, %rsp");
+                    emit!(self, "movl $0, 12(%rsp)");
+                    emit!(self, "leaq 12(%rsp), %rsi");
+                    emit!(self, "leaq .Lfmt_int(%rip), %rdi");
+                    emit!(self, "xorl %eax, %eax");
+                    emit!(self, "call scanf@PLT");
+                    emit!(self, "movl 12(%rsp), %eax");
+                    emit!(self, "addq $24, %rsp");
+                    emit!(self, "ret");
+                }
+                Service::ReadC => {
+                    // Realigns `%rsp` to 16 bytes; `getchar`'s `EOF` truncates to `(char) 255`.
+                    emit!(self, "subq $8, %rsp");
+                    emit!(self, "call getchar@PLT");
+                    emit!(self, "addq $8, %rsp");
+                    emit!(self, "ret");
+                }
+                Service::Malloc => {
+                    emit!(self, "movslq %edi, %rdi");
+                    emit!(self, "jmp malloc@PLT");
+                }
+            }
+            emit!(self, ".size {name}, .-{name}");
+        }
+        emit!(self, ".section .rodata");
+        self.label(".Lfmt_int");
+        emit!(self, ".asciz \"%d\"");
+        self.label(".Lfmt_str");
+        emit!(self, ".asciz \"%s\"");
+    }
+
+    /// The label of the string literal `s`.
+    fn string(&mut self, s: Intern<String>) -> String {
+        let next = self.strings.len();
+        self.strings
+            .entry(s)
+            .or_insert_with(|| format!(".Lstr{next}"))
+            .clone()
+    }
+
+    /// Emits the constant `value`, of type `ty`, padding structs as laid out.
+    fn constant(&mut self, ty: &Ty, value: &Const) {
+        match value {
+            Const::Int(v) => emit!(self, ".long {v}"),
+            Const::Char(c) => emit!(self, ".byte {c}"),
+            Const::Bool(b) => emit!(self, ".byte {}", *b as u8),
+            Const::Str(s) => {
+                let label = self.string(*s);
+                emit!(self, ".quad {label}");
+            }
+            Const::Struct(name, fields) => {
+                let mut fields = fields
+                    .iter()
+                    .map(|(field, value)| (self.layouts.field(*name, *field), value))
+                    .collect::<Vec<_>>();
+                fields.sort_by_key(|((offset, _), _)| *offset);
+                let mut end = 0;
+                for ((offset, field_ty), value) in fields {
+                    if offset > end {
+                        emit!(self, ".zero {}", offset - end);
+                    }
+                    self.constant(&field_ty, value);
+                    end = offset + self.layouts.size_of(&field_ty);
+                }
+                let size = self.layouts.size_of(ty);
+                if size > end {
+                    emit!(self, ".zero {}", size - end);
+                }
+            }
+            Const::Array(elems) => {
+                let TyKind::Array(_, elem_ty) = &*ty.kind else {
+                    unreachable!("array constant of a non-array type");
+                };
+                for value in elems {
+                    self.constant(elem_ty, value);
+                }
+            }
+        }
+    }
+
+    /// Generates statics, constants and string literals.
+    fn data(&mut self, items: &[Item]) {
+        for item in items {
+            let (ItemKind::Static(decl) | ItemKind::Const(decl)) = &item.kind else {
+                continue;
+            };
+            let ty = self.analysis.types.decls[&item.id].clone();
+            let layout = self.layouts.of(&ty);
+            let value = self.analysis.consts.get(&item.id);
+            // Constants may hold pointers, which need relocating before they become read-only.
+            let section = match (&item.kind, value) {
+                (ItemKind::Const(_), _) => ".section .data.rel.ro,\"aw\"",
+                (_, Some(_)) => ".data",
+                (_, None) => ".bss",
+            };
+            emit!(self, "{section}");
+            emit!(self, ".p2align {}", layout.align.trailing_zeros());
+            self.label(&decl.ident.to_string());
+            match value {
+                Some(value) => self.constant(&ty, value),
+                None => emit!(self, ".zero {}", layout.size),
+            }
+        }
+
+        let mut strings = self.strings.drain().collect::<Vec<_>>();
+        strings.sort_by(|(_, l1), (_, l2)| l1.cmp(l2));
+        if !strings.is_empty() {
+            emit!(self, ".section .rodata");
+        }
+        for (s, label) in strings {
+            self.label(&label);
+            emit!(self, ".asciz \"{}\"", escape(&s));
+        }
+        emit!(self, ".section .note.GNU-stack,\"\",@progbits");
+    }
+}
+
+/// Escapes `s` for use in a GNU assembler string.
+fn escape(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'"' | b'\\' => format!("\\{}", b as char),
+            b' '..=b'~' => (b as char).to_string(),
+            _ => format!("\\{b:03o}"),
+        })
+        .collect()
+}
@@ -0,0 +1,429 @@
+//! Lossless Concrete Syntax Tree (CST), built alongside the [AST](crate::ast).
+//!
+//! The AST keeps only what later passes need. The CST keeps every token of the source, including
+//! whitespace and comments, so that printing it reproduces the source byte for byte. Its nodes
+//! mirror the AST nodes that record a span, and remember the [`NodeId`] of the node they were built
+//! from, if it has one. A node owns the tokens within its span that none of its children claim, so
+//! trivia between two nodes belongs to their parent, and anything the parser skipped while
+//! recovering from an error belongs to the innermost node around it.
+use std::fmt::Display;
+
+use chumsky::span::SimpleSpan;
+use serde::Serialize;
+
+use crate::{
+    ast::{
+        exprs::{Expr, ExprKind},
+        functions::{FnSig, Param},
+        pattern::{Pattern, PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        types::Ty,
+        Item, ItemKind,
+    },
+    lexer::{lex_lossless, SourceFile, Token},
+    util::NodeId,
+};
+
+/// What a [`SyntaxNode`] was built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SyntaxKind {
+    /// The whole source file.
+    File,
+    /// An item, statement or expression that failed to parse.
+    Error,
+    Static,
+    Const,
+    StructDecl,
+    FnDecl,
+    FnDefn,
+    Param,
+    Block,
+    Local,
+    For,
+    While,
+    If,
+    ExprStmt,
+    Return,
+    Continue,
+    Break,
+    Literal,
+    Ident,
+    Assign,
+    CompoundAssign,
+    BinOp,
+    Unary,
+    CallFn,
+    Typecast,
+    Deref,
+    Ref,
+    Index,
+    FieldAccess,
+    StructLit,
+    ArrayLit,
+    ArrayRepeat,
+    Ty,
+    Pattern,
+}
+
+/// A single token, trivia included, along with the exact source text it was lexed from.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyntaxToken<'src> {
+    pub token: Token<'src>,
+    pub span: SimpleSpan,
+    pub text: &'src str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum SyntaxElement<'src> {
+    Node(SyntaxNode<'src>),
+    Token(SyntaxToken<'src>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyntaxNode<'src> {
+    pub kind: SyntaxKind,
+    /// The AST node this was built from. [`Param`]s and the file itself have none.
+    pub id: Option<NodeId>,
+    pub span: SimpleSpan,
+    pub children: Vec<SyntaxElement<'src>>,
+}
+
+impl<'src> SyntaxNode<'src> {
+    /// Builds the CST of `src`, given the `items` parsed from it. The items need not be complete:
+    /// tokens that belong to no item are kept as children of the [`File`](SyntaxKind::File) node.
+    pub fn build(src: &'src SourceFile, items: &[Item]) -> Self {
+        let text = src.source.text();
+        let mut builder = Builder {
+            text,
+            tokens: lex_lossless(src).collect(),
+            next: 0,
+        };
+        let file = Shape {
+            kind: SyntaxKind::File,
+            id: None,
+            span: (0..text.len()).into(),
+            children: items.iter().map(Shape::item).collect(),
+        };
+        builder.node(file)
+    }
+
+    /// Every token within this node, in source order.
+    pub fn tokens(&self) -> Box<dyn Iterator<Item = &SyntaxToken<'src>> + '_> {
+        Box::new(self.children.iter().flat_map(|child| match child {
+            SyntaxElement::Node(node) => node.tokens(),
+            SyntaxElement::Token(token) => Box::new(std::iter::once(token)),
+        }))
+    }
+
+    /// The child nodes of this node, skipping its tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode<'src>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+}
+
+/// Prints the source text of the node, trivia included.
+impl Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.tokens()
+            .try_for_each(|token| write!(f, "{}", token.text))
+    }
+}
+
+/// The outline of a node, taken from the AST, before it is filled with tokens.
+struct Shape {
+    kind: SyntaxKind,
+    id: Option<NodeId>,
+    span: SimpleSpan,
+    children: Vec<Shape>,
+}
+
+impl Shape {
+    fn item(item: &Item) -> Self {
+        let (kind, children) = match &item.kind {
+            ItemKind::Invalid => (SyntaxKind::Error, vec![]),
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                let mut children = vec![Self::ty(&decl.ty)];
+                children.extend(decl.value.iter().map(Self::expr));
+                let kind = match item.kind {
+                    ItemKind::Const(_) => SyntaxKind::Const,
+                    _ => SyntaxKind::Static,
+                };
+                (kind, children)
+            }
+            ItemKind::StructDecl(decl) => (
+                SyntaxKind::StructDecl,
+                decl.fields
+                    .iter()
+                    .map(|field| Self::ty(&field.ty))
+                    .collect(),
+            ),
+            ItemKind::FnDecl(decl) => (SyntaxKind::FnDecl, Self::sig(&decl.sig)),
+            ItemKind::FnDefn(defn) => {
+                let mut children = Self::sig(&defn.sig);
+                children.push(Self::stmt(&defn.block));
+                (SyntaxKind::FnDefn, children)
+            }
+        };
+        Self::new(kind, Some(item.id), item.span, children)
+    }
+
+    fn sig(sig: &FnSig) -> Vec<Self> {
+        let mut children = sig.params.iter().map(Self::param).collect::<Vec<_>>();
+        children.push(Self::ty(&sig.ty));
+        children
+    }
+
+    fn param(param: &Param) -> Self {
+        Self::new(
+            SyntaxKind::Param,
+            None,
+            param.span,
+            vec![Self::ty(&param.ty)],
+        )
+    }
+
+    fn stmt(stmt: &Stmt) -> Self {
+        let (kind, children) = match &stmt.kind {
+            StmtKind::Invalid => (SyntaxKind::Error, vec![]),
+            StmtKind::Block(block) => (
+                SyntaxKind::Block,
+                block.stmts.iter().map(Self::stmt).collect(),
+            ),
+            StmtKind::Local(local) => {
+                let mut children = vec![Self::ty(&local.ty)];
+                children.extend(local.value.iter().map(Self::expr));
+                (SyntaxKind::Local, children)
+            }
+            StmtKind::For(pattern, body) => (
+                SyntaxKind::For,
+                vec![Self::pattern(pattern), Self::stmt(body)],
+            ),
+            StmtKind::While(cond, body) => {
+                (SyntaxKind::While, vec![Self::expr(cond), Self::stmt(body)])
+            }
+            StmtKind::If(cond, then, els) => {
+                let mut children = vec![Self::expr(cond), Self::stmt(then)];
+                children.extend(els.iter().map(|els| Self::stmt(els)));
+                (SyntaxKind::If, children)
+            }
+            StmtKind::Expr(expr) => (SyntaxKind::ExprStmt, vec![Self::expr(expr)]),
+            StmtKind::Return(expr) => (SyntaxKind::Return, expr.iter().map(Self::expr).collect()),
+            StmtKind::Continue => (SyntaxKind::Continue, vec![]),
+            StmtKind::Break => (SyntaxKind::Break, vec![]),
+        };
+        Self::new(kind, Some(stmt.id), stmt.span, children)
+    }
+
+    fn expr(expr: &Expr) -> Self {
+        let (kind, children) = match &expr.kind {
+            ExprKind::Invalid => (SyntaxKind::Error, vec![]),
+            ExprKind::Literal(_) => (SyntaxKind::Literal, vec![]),
+            ExprKind::Ident(_) => (SyntaxKind::Ident, vec![]),
+            ExprKind::Assign(lhs, rhs) => {
+                (SyntaxKind::Assign, vec![Self::expr(lhs), Self::expr(rhs)])
+            }
+            ExprKind::CompoundAssign(lhs, _, rhs) => (
+                SyntaxKind::CompoundAssign,
+                vec![Self::expr(lhs), Self::expr(rhs)],
+            ),
+            ExprKind::BinOp(lhs, _, rhs) => {
+                (SyntaxKind::BinOp, vec![Self::expr(lhs), Self::expr(rhs)])
+            }
+            ExprKind::Unary(_, expr) => (SyntaxKind::Unary, vec![Self::expr(expr)]),
+            ExprKind::CallFn(call) => {
+                let mut children = vec![Self::expr(&call.name)];
+                children.extend(call.args.iter().map(Self::expr));
+                (SyntaxKind::CallFn, children)
+            }
+            ExprKind::Typecast(ty, expr) => {
+                (SyntaxKind::Typecast, vec![Self::ty(ty), Self::expr(expr)])
+            }
+            ExprKind::Deref(expr) => (SyntaxKind::Deref, vec![Self::expr(expr)]),
+            ExprKind::Ref(expr) => (SyntaxKind::Ref, vec![Self::expr(expr)]),
+            ExprKind::Index(arr, ind) => {
+                (SyntaxKind::Index, vec![Self::expr(arr), Self::expr(ind)])
+            }
+            ExprKind::FieldAccess(expr, _) => (SyntaxKind::FieldAccess, vec![Self::expr(expr)]),
+            ExprKind::StructLit(ty, fields) => {
+                let mut children = vec![Self::ty(ty)];
+                children.extend(fields.iter().map(|field| Self::expr(&field.value)));
+                (SyntaxKind::StructLit, children)
+            }
+            ExprKind::ArrayLit(elems) => {
+                (SyntaxKind::ArrayLit, elems.iter().map(Self::expr).collect())
+            }
+            ExprKind::ArrayRepeat(elem, _) => (SyntaxKind::ArrayRepeat, vec![Self::expr(elem)]),
+        };
+        Self::new(kind, Some(expr.id), expr.span, children)
+    }
+
+    /// Types nested within another carry the span of the first equivalent type to be interned, so
+    /// only the outermost type becomes a node.
+    fn ty(ty: &Ty) -> Self {
+        Self::new(SyntaxKind::Ty, Some(ty.id), ty.span, vec![])
+    }
+
+    fn pattern(pattern: &Pattern) -> Self {
+        let PatternKind::RangePattern(_, range) = &pattern.kind;
+        let (Range::Exclusive(start, end)
+        | Range::Inclusive(start, end)
+        | Range::ExclusiveInclusive(start, end)
+        | Range::InclusiveExclusive(start, end)) = range;
+        Self::new(
+            SyntaxKind::Pattern,
+            Some(pattern.id),
+            pattern.span,
+            vec![Self::expr(start), Self::expr(end)],
+        )
+    }
+
+    /// Keeps only the children that actually cover source text of their own. Nodes synthesized by
+    /// the parser, such as the `0` in `-x` or the inferred type of an unannotated static, either
+    /// have an empty span or overlap the siblings they were made from, so of two overlapping
+    /// siblings only the smaller is kept.
+    fn new(kind: SyntaxKind, id: Option<NodeId>, span: SimpleSpan, children: Vec<Shape>) -> Self {
+        let overlaps = |a: SimpleSpan, b: SimpleSpan| a.start < b.end && b.start < a.end;
+        let keep = children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| {
+                child.span.start < child.span.end
+                    && span.start <= child.span.start
+                    && child.span.end <= span.end
+                    && !children.iter().enumerate().any(|(j, other)| {
+                        let len = |span: SimpleSpan| span.end.saturating_sub(span.start);
+                        let smaller = (len(other.span), j) < (len(child.span), i);
+                        smaller && overlaps(child.span, other.span)
+                    })
+            })
+            .collect::<Vec<_>>();
+        let mut children = children
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(child, keep)| keep.then_some(child))
+            .collect::<Vec<_>>();
+        children.sort_by_key(|child| child.span.start);
+        Self {
+            kind,
+            id,
+            span,
+            children,
+        }
+    }
+}
+
+/// Hands out the lossless tokens, in order, to the nodes that own them.
+struct Builder<'src> {
+    text: &'src str,
+    tokens: Vec<(Token<'src>, SimpleSpan)>,
+    next: usize,
+}
+
+impl<'src> Builder<'src> {
+    /// Fills `shape` with every remaining token that starts within its span.
+    fn node(&mut self, shape: Shape) -> SyntaxNode<'src> {
+        let mut children = vec![];
+        let mut nested = shape.children.into_iter().peekable();
+        while let Some(&(_, span)) = self.tokens.get(self.next) {
+            if span.start >= shape.span.end {
+                break;
+            }
+            match nested.next_if(|child| child.span.start <= span.start) {
+                Some(child) => children.push(SyntaxElement::Node(self.node(child))),
+                None => children.push(SyntaxElement::Token(self.token())),
+            }
+        }
+        SyntaxNode {
+            kind: shape.kind,
+            id: shape.id,
+            span: shape.span,
+            children,
+        }
+    }
+
+    fn token(&mut self) -> SyntaxToken<'src> {
+        let (token, span) = self.tokens[self.next].clone();
+        self.next += 1;
+        SyntaxToken {
+            token,
+            span,
+            text: &self.text[span.into_range()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ariadne::FileCache;
+    use chumsky::Parser;
+    use rstest::{fixture, rstest};
+    use tempfile::NamedTempFile;
+
+    use super::{SyntaxElement, SyntaxNode};
+    use crate::{lexer::SourceFile, parser};
+
+    #[fixture]
+    fn cache() -> FileCache {
+        FileCache::default()
+    }
+
+    fn src(s: String, cache: FileCache) -> SourceFile {
+        let mut f = NamedTempFile::new().unwrap();
+        write!(f, "{}", s).unwrap();
+        SourceFile::from_path(f.path(), cache)
+    }
+
+    /// Prints the tree as nested `Kind(...)`, leaving out whitespace.
+    fn outline(node: &SyntaxNode) -> String {
+        let children = node
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(outline(node)),
+                SyntaxElement::Token(token) if token.text.trim().is_empty() => None,
+                SyntaxElement::Token(token) => Some(token.text.trim().to_string()),
+            })
+            .collect::<Vec<_>>();
+        format!("{:?}({})", node.kind, children.join(" "))
+    }
+
+    #[rstest]
+    #[case::static_decl(
+        "static x: int = 4;",
+        "File(Static(static x : Ty(int) = Literal(4) ;))"
+    )]
+    #[case::inferred_static("static x;", "File(Static(static x ;))")]
+    #[case::const_decl("const N: int = 4;", "File(Const(const N : Ty(int) = Literal(4) ;))")]
+    #[case::trivia(
+        "/* a */ f(): void { // b\n}",
+        "File(/* a */ FnDefn(f ( ) : Ty(void) Block({ // b })))"
+    )]
+    #[case::params(
+        "f(a: int, b: &char): void;",
+        "File(FnDecl(f ( Param(a : Ty(int)) , Param(b : Ty(& char)) ) : Ty(void) ;))"
+    )]
+    #[case::negation(
+        "f(): int { return -x; }",
+        "File(FnDefn(f ( ) : Ty(int) Block({ Return(return Unary(- Ident(x)) ;) })))"
+    )]
+    #[case::recovery(
+        "f(): void { x = ; }",
+        "File(FnDefn(f ( ) : Ty(void) Block({ ExprStmt(Ident(x)) Error(= ;) })))"
+    )]
+    #[case::unparsed("}", "File(Error(}))")]
+    fn test_cst(#[case] input: String, #[case] expected: String, cache: FileCache) {
+        let src_file = src(input.clone(), cache);
+        let items = parser::parser()
+            .parse(parser::token_stream(&src_file))
+            .into_output()
+            .unwrap_or_default();
+        let cst = SyntaxNode::build(&src_file, &items);
+        assert_eq!(outline(&cst), expected);
+        assert_eq!(cst.to_string(), input);
+    }
+}
@@ -0,0 +1,310 @@
+//! Diagnostics reported by the passes of the pipeline, and how they are rendered.
+//!
+//! Passes describe each problem they find as a [`Diagnostic`], and [report](CompilerPass::report)
+//! it rather than printing it. The driver then [emits](emit) the diagnostics of each pass in the
+//! [`ErrorFormat`] chosen on the command line:
+//! - [`Human`](ErrorFormat::Human) reports, with the labelled source underlined.
+//! - [`Json`](ErrorFormat::Json) lines, one object per diagnostic, for tools. For example:
+//!
+//! ```json
+//! {"code":"E0208","labels":[{"end":{"column":6,"line":2,"offset":18},"file":"a.akn","message":null,"primary":true,"start":{"column":5,"line":2,"offset":17}}],"message":"cannot find value `y` in this scope","notes":[],"severity":"error"}
+//! ```
+//!
+//! Lines and columns count from 1, and columns count characters. Offsets count bytes from the
+//! start of the file.
+//!
+//! Every diagnostic has a stable code, such as `E0208`, explained at length in the [`codes`]
+//! catalogue and by `akynoc --explain`.
+use std::io::{self, Write};
+
+use ariadne::{Color, Config, IndexType, Label as Annotation, Report, ReportKind};
+use chumsky::span::SimpleSpan;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::lexer::SourceFile;
+
+#[cfg(doc)]
+use crate::util::CompilerPass;
+
+pub mod codes;
+
+/// How serious a [`Diagnostic`] is. Only errors make a pass fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span of source, with a message about it.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: SimpleSpan,
+    pub message: Option<String>,
+    /// Whether the label marks the source the diagnostic is about, rather than related source.
+    pub primary: bool,
+}
+
+/// A problem found in a source file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The stable code of the problem, with an explanation in the [`codes`] catalogue.
+    pub code: &'static str,
+    pub message: String,
+    /// Primary labels first, in the order they were added.
+    pub labels: Vec<Label>,
+    /// Further explanation, not tied to any source.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    /// Marks `span` as the source the diagnostic is about.
+    pub fn at(self, span: SimpleSpan) -> Self {
+        self.label(span, None, true)
+    }
+
+    /// Marks `span` as the source the diagnostic is about, explaining why with `message`.
+    pub fn with_primary(self, span: SimpleSpan, message: impl Into<String>) -> Self {
+        self.label(span, Some(message.into()), true)
+    }
+
+    /// Adds a label to related source, such as a previous definition.
+    pub fn with_label(self, span: SimpleSpan, message: impl Into<String>) -> Self {
+        self.label(span, Some(message.into()), false)
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn label(mut self, span: SimpleSpan, message: Option<String>, primary: bool) -> Self {
+        let label = Label {
+            span,
+            message,
+            primary,
+        };
+        let at = self.labels.iter().take_while(|label| label.primary).count();
+        if primary {
+            self.labels.insert(at, label);
+        } else {
+            self.labels.push(label);
+        }
+        self
+    }
+
+    /// The span of the first primary label, if there is one.
+    pub fn span(&self) -> Option<SimpleSpan> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .map(|label| label.span)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// How diagnostics are rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// Reports for people, quoting the source
+    #[default]
+    Human,
+    /// One JSON object per line
+    Json,
+}
+
+/// Writes `diagnostics` about `src` to stderr.
+pub fn emit(src: &SourceFile, diagnostics: &[Diagnostic], format: ErrorFormat) {
+    render(src, diagnostics, format, &mut io::stderr().lock()).expect("writing to stderr");
+}
+
+/// Writes `diagnostics` about `src` to `out`.
+pub fn render(
+    src: &SourceFile,
+    diagnostics: &[Diagnostic],
+    format: ErrorFormat,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    for diagnostic in diagnostics {
+        match format {
+            ErrorFormat::Human => {
+                report(src, diagnostic).write((&src.name, &src.source), &mut *out)?
+            }
+            ErrorFormat::Json => writeln!(out, "{}", to_json(src, diagnostic))?,
+        }
+    }
+    Ok(())
+}
+
+fn report<'a>(
+    src: &'a SourceFile,
+    diagnostic: &Diagnostic,
+) -> Report<'a, (&'a String, std::ops::Range<usize>)> {
+    let name = &src.name;
+    let kind = match diagnostic.severity {
+        Severity::Error => ReportKind::Error,
+        Severity::Warning => ReportKind::Warning,
+    };
+    let span = diagnostic.span().unwrap_or(SimpleSpan::from(0..0));
+    let mut report = Report::build(kind, (name, span.into_range()))
+        .with_config(Config::new().with_index_type(IndexType::Byte))
+        .with_code(diagnostic.code)
+        .with_message(&diagnostic.message)
+        .with_labels(diagnostic.labels.iter().map(|label| {
+            let color = if label.primary {
+                Color::Red
+            } else {
+                Color::Blue
+            };
+            let annotation = Annotation::new((name, label.span.into_range())).with_color(color);
+            match &label.message {
+                Some(message) => annotation.with_message(message),
+                None => annotation,
+            }
+        }));
+    for note in &diagnostic.notes {
+        report.add_note(note);
+    }
+    report.finish()
+}
+
+fn to_json(src: &SourceFile, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            json!({
+                "primary": label.primary,
+                "message": label.message,
+                "file": src.name,
+                "start": location(src, label.span.start),
+                "end": location(src, label.span.end),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "severity": severity,
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "labels": labels,
+        "notes": diagnostic.notes,
+    })
+}
+
+fn location(src: &SourceFile, offset: usize) -> Value {
+    let text = src.source.text();
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "offset": offset,
+        "line": before.matches('\n').count() + 1,
+        "column": before[line_start..].chars().count() + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::span::SimpleSpan;
+    use rstest::rstest;
+    use serde_json::{json, Value};
+
+    use super::{render, Diagnostic, ErrorFormat};
+    use crate::lexer::SourceFile;
+
+    fn span(range: std::ops::Range<usize>) -> SimpleSpan {
+        SimpleSpan::from(range)
+    }
+
+    #[rstest]
+    #[case::no_labels(Diagnostic::error("E0501", "oops"), json!([]))]
+    #[case::primary(
+        Diagnostic::error("E0208", "bad").at(span(4..5)),
+        json!([{
+            "primary": true,
+            "message": null,
+            "file": "test.akn",
+            "start": { "offset": 4, "line": 2, "column": 1 },
+            "end": { "offset": 5, "line": 2, "column": 2 },
+        }])
+    )]
+    #[case::ordered(
+        Diagnostic::error("E0208", "bad")
+            .with_label(span(0..1), "before")
+            .with_primary(span(7..8), "here"),
+        json!([
+            {
+                "primary": true,
+                "message": "here",
+                "file": "test.akn",
+                "start": { "offset": 7, "line": 2, "column": 3 },
+                "end": { "offset": 8, "line": 2, "column": 4 },
+            },
+            {
+                "primary": false,
+                "message": "before",
+                "file": "test.akn",
+                "start": { "offset": 0, "line": 1, "column": 1 },
+                "end": { "offset": 1, "line": 1, "column": 2 },
+            },
+        ])
+    )]
+    fn test_json(#[case] diagnostic: Diagnostic, #[case] labels: Value) {
+        let src = SourceFile::new("test.akn".into(), "abc\nxé!\n".into());
+        let diagnostic = diagnostic.with_note("a note");
+        let mut out = vec![];
+        render(
+            &src,
+            std::slice::from_ref(&diagnostic),
+            ErrorFormat::Json,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(1, out.lines().count());
+        let value: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json!("error"), value["severity"]);
+        assert_eq!(json!(diagnostic.code), value["code"]);
+        assert_eq!(json!(diagnostic.message), value["message"]);
+        assert_eq!(labels, value["labels"]);
+        assert_eq!(json!(["a note"]), value["notes"]);
+    }
+
+    #[test]
+    fn test_human() {
+        let src = SourceFile::new("test.akn".into(), "let x := y;\n".into());
+        let diagnostic = Diagnostic::error("E0208", "cannot find value `y`")
+            .with_primary(span(9..10), "not found")
+            .with_note("declare it first");
+        let mut out = vec![];
+        render(&src, &[diagnostic], ErrorFormat::Human, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for expected in [
+            "[E0208] Error:",
+            "cannot find value `y`",
+            "test.akn:1:10",
+            "not found",
+            "declare it first",
+        ] {
+            assert!(out.contains(expected), "{expected} not in {out}");
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! The catalogue of error codes, each with a long-form explanation printed by `akynoc --explain`.
+//!
+//! Codes are grouped by the pass that reports them:
+//! - `E00xx`: lexing
+//! - `E01xx`: parsing
+//! - `E02xx`: name resolution
+//! - `E03xx`: type inference and type checking
+//! - `E04xx`: faults of a program run by the interpreter
+//! - `E05xx`: code generation
+//!
+//! Each explanation is a Markdown file in `codes/`. The first `akyno` block of an explanation is an
+//! example of code with the error, and any later ones show how it can be fixed.
+
+macro_rules! codes {
+    ($($code:literal),* $(,)?) => {
+        /// Every code, in order, with its explanation.
+        pub const CODES: &[(&str, &str)] = &[
+            $(($code, include_str!(concat!("codes/", $code, ".md")))),*
+        ];
+    };
+}
+
+codes!(
+    "E0001", "E0002", "E0003", "E0004", "E0005", "E0006", "E0007", "E0008", "E0009", "E0010",
+    "E0100", "E0101", "E0102", "E0103", "E0201", "E0202", "E0203", "E0204", "E0205", "E0206",
+    "E0207", "E0208", "E0209", "E0210", "E0301", "E0302", "E0303", "E0304", "E0305", "E0306",
+    "E0307", "E0308", "E0309", "E0310", "E0311", "E0312", "E0313", "E0314", "E0315", "E0316",
+    "E0317", "E0318", "E0319", "E0320", "E0321", "E0322", "E0323", "E0324", "E0325", "E0326",
+    "E0327", "E0328", "E0329", "E0330", "E0401", "E0402", "E0403", "E0404", "E0405", "E0407",
+    "E0408", "E0409", "E0410", "E0411", "E0412", "E0501", "E0502", "E0503", "E0504",
+);
+
+/// The explanation of `code`, such as `E0208`. The `E` may be lowercase, or left out.
+pub fn explain(code: &str) -> Option<&'static str> {
+    let number = code.strip_prefix(['E', 'e']).unwrap_or(code);
+    CODES
+        .iter()
+        .find(|(code, _)| code[1..] == *number)
+        .map(|&(_, explanation)| explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use rstest::rstest;
+
+    use super::{explain, CODES};
+    use crate::{
+        interp::Interpreter,
+        lexer::{LexerPass, SourceFile},
+        parser::ParserPass,
+        sem::SemanticAnalysis,
+        util::CompilerPass,
+    };
+
+    /// Every `.rs` file under `dir`, other than this catalogue.
+    fn sources(dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(sources(&path));
+            } else if path.extension().is_some_and(|ext| ext == "rs")
+                && !path.ends_with("diagnostic/codes.rs")
+            {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    /// Every string literal in `text` that looks like an error code.
+    fn codes_in(text: &str) -> BTreeSet<String> {
+        text.match_indices("\"E")
+            .filter_map(|(i, _)| {
+                let literal = text.get(i + 1..i + 7)?;
+                let digits = &literal[1..5];
+                (digits.bytes().all(|b| b.is_ascii_digit()) && literal.ends_with('"'))
+                    .then(|| literal[..5].to_string())
+            })
+            .collect()
+    }
+
+    fn codes(pass: &impl CompilerPass) -> Vec<&'static str> {
+        pass.diagnostics().iter().map(|d| d.code).collect()
+    }
+
+    /// The codes of every error found in `text`, by the first pass to find any. Runs the program
+    /// only if `run`.
+    fn reported(text: &str, run: bool) -> Vec<&'static str> {
+        let src = SourceFile::new("example.akn".into(), text.into());
+        let mut lexer = LexerPass::new(&src);
+        let tokens = lexer.lex();
+        if lexer.has_error() {
+            return codes(&lexer);
+        }
+        let mut parser = ParserPass::new(&src);
+        let Some(mut items) = parser.parse(tokens) else {
+            return codes(&parser);
+        };
+        let mut sem = SemanticAnalysis::new();
+        let Some(analysis) = sem.analyse(&mut items) else {
+            return codes(&sem);
+        };
+        let mut interp = Interpreter::new();
+        if run {
+            interp.run(&items, &analysis);
+        }
+        codes(&interp)
+    }
+
+    /// The `akyno` code blocks of an explanation.
+    fn examples(explanation: &str) -> Vec<String> {
+        let mut examples = vec![];
+        let mut lines = explanation.lines();
+        while let Some(line) = lines.next() {
+            if line == "```akyno" {
+                let block = lines.by_ref().take_while(|line| *line != "```");
+                examples.push(block.map(|line| format!("{line}\n")).collect());
+            }
+        }
+        examples
+    }
+
+    #[test]
+    fn test_codes_explained() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let used = sources(&src)
+            .iter()
+            .flat_map(|path| codes_in(&fs::read_to_string(path).unwrap()))
+            .collect::<BTreeSet<_>>();
+        assert!(used.len() > 1);
+        for code in used {
+            assert!(explain(&code).is_some(), "{code} has no explanation");
+        }
+    }
+
+    #[test]
+    fn test_codes_sorted() {
+        assert!(CODES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[rstest]
+    #[case::code("E0208", true)]
+    #[case::lowercase("e0208", true)]
+    #[case::number("0208", true)]
+    #[case::unknown("E9999", false)]
+    #[case::empty("", false)]
+    fn test_explain(#[case] code: &str, #[case] found: bool) {
+        assert_eq!(found, explain(code).is_some());
+    }
+
+    #[test]
+    fn test_examples() {
+        for (code, explanation) in CODES {
+            assert!(
+                explanation.starts_with(&format!("{code}: ")),
+                "{code} has no title"
+            );
+            // Faults only happen when the program is run.
+            let run = code.starts_with("E04");
+            let mut examples = examples(explanation).into_iter();
+            if let Some(erroneous) = examples.next() {
+                let reported = reported(&erroneous, run);
+                assert!(
+                    reported.contains(code),
+                    "{code} example reported {reported:?}"
+                );
+            }
+            for fixed in examples {
+                let reported = reported(&fixed, run);
+                assert!(reported.is_empty(), "{code} fix reported {reported:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Canonical formatting of source files, built on [`Writable`].
+//!
+//! The formatter prints the AST back out, so its output depends only on what was parsed, not on
+//! how it was laid out. Comments and blank lines, which the AST does not record, are carried over
+//! from the source by the [`Writer`]: a comment is written on its own line before the first item or
+//! statement that follows it, unless it ended a line of code, and runs of blank lines are kept as
+//! one.
+use anyhow::Result;
+
+use crate::{
+    ast::{Item, ItemKind},
+    lexer::SourceFile,
+    util::{Writable, Writer},
+};
+
+/// Text inserted for each level of indentation.
+const INDENT: &str = "    ";
+
+/// Formats the `items` parsed from `src`.
+pub fn format(src: &SourceFile, items: &[Item]) -> Result<String> {
+    let mut out = vec![];
+    let mut writer = Writer::with_source(&mut out, src).with_indent(INDENT);
+    let mut prev: Option<&Item> = None;
+    for item in items {
+        // Functions and structs are always set apart by a blank line.
+        let blank = prev.is_some_and(|prev| spacious(prev) || spacious(item));
+        writer.comments(item.span.start, blank)?;
+        item.write(&mut writer, true)?;
+        prev = Some(item);
+    }
+    writer.closing_comments(usize::MAX)?;
+    Ok(String::from_utf8(out)?)
+}
+
+fn spacious(item: &Item) -> bool {
+    matches!(item.kind, ItemKind::StructDecl(_) | ItemKind::FnDefn(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ariadne::FileCache;
+    use chumsky::Parser;
+    use rstest::{fixture, rstest};
+    use tempfile::NamedTempFile;
+
+    use crate::{lexer::SourceFile, parser};
+
+    #[fixture]
+    fn cache() -> FileCache {
+        FileCache::default()
+    }
+
+    fn src(s: String, cache: FileCache) -> SourceFile {
+        let mut f = NamedTempFile::new().unwrap();
+        write!(f, "{}", s).unwrap();
+        SourceFile::from_path(f.path(), cache)
+    }
+
+    #[rstest]
+    #[case::empty("", "")]
+    #[case::items(
+        "static a:int=1;static b;f():void;g(): void {}",
+        "static a: int = 1;\nstatic b;\nf(): void;\n\ng(): void {}\n"
+    )]
+    #[case::static_exprs(
+        "static SIZE=4*1024;static buf:char[SIZE];static origin=struct p{x:-1,y:SIZE/2};",
+        "static SIZE = 4 * 1024;\nstatic buf: char[SIZE];\nstatic origin = struct p { x: -1, y: SIZE / 2 };\n"
+    )]
+    #[case::consts(
+        "const N:int=4;const ORIGIN=struct p{x:0,y:N};",
+        "const N: int = 4;\nconst ORIGIN = struct p { x: 0, y: N };\n"
+    )]
+    #[case::structs(
+        "struct s{x:int;y:&(&int)[4];z:&int[2][3];}",
+        "struct s {\n    x: int;\n    y: &(&int)[4];\n    z: &int[2][3];\n}\n"
+    )]
+    #[case::precedence(
+        "f(): int { x = (a - (b - c)) * -(d + e) - (*p).x + *q.y + &(&z); }",
+        "f(): int {\n    x = (a - (b - c)) * -(d + e) - (*p).x + *q.y + &(&z);\n}\n"
+    )]
+    #[case::unary(
+        "f(): int { return - -a + -(+b) + ~-c + !(!d); }",
+        "f(): int {\n    return - -a + - +b + ~-c + !!d;\n}\n"
+    )]
+    #[case::compound_assign(
+        "f(): void { a[i]+=b<<=(1+2); x = y -= 1; }",
+        "f(): void {\n    a[i] += b <<= 1 + 2;\n    x = y -= 1;\n}\n"
+    )]
+    #[case::literals(
+        "f(): void { let p := struct p{x:1,y:[0;2]}; let a := [ [1,2] ,[3,4]]; }",
+        "f(): void {\n    let p := struct p { x: 1, y: [0; 2] };\n    let a := [[1, 2], [3, 4]];\n}\n"
+    )]
+    #[case::locals(
+        "f(): void { let a: _ = 1; let b: int; let c: char = 'c'; let mut d:=a; let mut e:int; }",
+        "f(): void {\n    let a := 1;\n    let b: int;\n    let c: char = 'c';\n    let mut d := a;\n    let mut e: int;\n}\n"
+    )]
+    #[case::control_flow(
+        "f(): int { for (i: (0; 4]) if (i) return 1; else continue; while (1) break; 0 }",
+        "f(): int {\n    for (i: (0; 4]) if (i) return 1;\n    else continue;\n    while (1) break;\n    0\n}\n"
+    )]
+    #[case::comments(
+        "// a\n\n\n/* b */ f(): void { // c\n    x; // d\n\n    // e\n}\n// f\n",
+        "// a\n\n/* b */\nf(): void {\n    // c\n    x; // d\n\n    // e\n}\n// f\n"
+    )]
+    fn test_format(#[case] input: String, #[case] expected: String, cache: FileCache) {
+        let src_file = src(input, cache);
+        let items = parser::parser()
+            .parse(parser::token_stream(&src_file))
+            .into_result()
+            .unwrap();
+        assert_eq!(super::format(&src_file, &items).unwrap(), expected);
+    }
+}
@@ -0,0 +1,1056 @@
+//! Tree-walking interpreter over the [AST](crate::ast), run once semantic analysis succeeds.
+//!
+//! Every variable lives in a simulated [`Memory`], laid out as on a 64-bit target (see
+//! [`layout`](crate::layout)), so that pointers behave as they would in compiled code. Statics
+//! are allocated and initialized before `main` runs. Each call pushes its parameters and locals
+//! onto the stack, and each block frees the locals it declared when it ends.
+//!
+//! Programs read from standard input and write to standard output through the runtime's
+//! [services](crate::runtime), allocating on a simulated heap.
+//!
+//! Running `main` produces the program's exit status: the value `main` returns, or `0` if it
+//! returns `void`. Faults, such as division by zero, out of bounds indexing or invalid memory
+//! accesses, stop the program and are reported at the expression that caused them.
+//!
+//! A program can also be run a piece at a time, as in the [REPL](crate::repl): a [`Session`]
+//! keeps memory, statics and top-level variables between calls to [`Interpreter::eval`].
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    thread,
+};
+
+use chumsky::span::SimpleSpan;
+use internment::Intern;
+
+use crate::{
+    ast::{
+        exprs::{CallFn, Expr, ExprKind, Operator, UnaryOp, Value},
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        structs::StructDecl,
+        types::{Ident, Primitive, Ty, TyKind},
+        Item, ItemKind,
+    },
+    diagnostic::Diagnostic,
+    layout::Layouts,
+    runtime::Service,
+    sem::{consteval::Const, resolve::Binding, Analysis},
+    util::{CompilerPass, NodeId},
+};
+
+pub mod memory;
+
+use memory::{MemError, Memory};
+
+/// Size of a pointer, in bytes.
+const PTR_SIZE: usize = 8;
+
+/// Maximum number of nested calls before the stack is considered to have overflowed.
+const MAX_DEPTH: usize = 10_000;
+
+/// Size of the host stack the interpreter runs on, which must fit [`MAX_DEPTH`] nested calls.
+pub(crate) const HOST_STACK_SIZE: usize = 512 << 20;
+
+/// A runtime fault, which stops the program.
+pub struct Fault {
+    pub code: &'static str,
+    /// The source being run when the fault occurred.
+    pub span: SimpleSpan,
+    pub message: String,
+}
+
+impl Fault {
+    pub fn new(code: &'static str, span: SimpleSpan, message: String) -> Self {
+        Self {
+            code,
+            span,
+            message,
+        }
+    }
+}
+
+impl From<Fault> for Diagnostic {
+    fn from(fault: Fault) -> Self {
+        Diagnostic::error(fault.code, fault.message).at(fault.span)
+    }
+}
+
+/// Attaches the span of the code performing a memory access to its [`MemError`].
+trait At<T> {
+    fn at(self, span: SimpleSpan) -> Result<T, Fault>;
+}
+
+impl<T> At<T> for Result<T, MemError> {
+    fn at(self, span: SimpleSpan) -> Result<T, Fault> {
+        self.map_err(|err| {
+            let (code, message) = match err {
+                MemError::Null => ("E0409", "null pointer dereference".into()),
+                MemError::Unmapped(addr) => (
+                    "E0410",
+                    format!("invalid memory access at address {addr:#x}"),
+                ),
+                MemError::ReadOnly(addr) => (
+                    "E0411",
+                    format!("write to read-only memory at address {addr:#x}"),
+                ),
+                MemError::StackOverflow => ("E0405", "stack overflow".into()),
+            };
+            Fault::new(code, span, message)
+        })
+    }
+}
+
+/// A value computed by an expression.
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Void,
+    Int(i32),
+    Char(u8),
+    Bool(bool),
+    Ptr(u64),
+    /// A struct or array, which stays in memory at the given address.
+    Agg(u64),
+}
+
+impl Val {
+    fn int(self) -> i32 {
+        match self {
+            Val::Int(v) => v,
+            _ => unreachable!("expected an `int`, found {self:?}"),
+        }
+    }
+
+    fn char(self) -> u8 {
+        match self {
+            Val::Char(c) => c,
+            _ => unreachable!("expected a `char`, found {self:?}"),
+        }
+    }
+
+    fn bool(self) -> bool {
+        match self {
+            Val::Bool(b) => b,
+            _ => unreachable!("expected a `bool`, found {self:?}"),
+        }
+    }
+
+    fn addr(self) -> u64 {
+        match self {
+            Val::Ptr(addr) | Val::Agg(addr) => addr,
+            _ => unreachable!("expected an address, found {self:?}"),
+        }
+    }
+}
+
+/// How control leaves a statement.
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Val),
+}
+
+/// Variables of a single call.
+struct Frame {
+    vars: HashMap<Binding, u64>,
+    /// Where to store a returned struct or array, which must outlive the call, and its size.
+    ret: Option<(u64, usize)>,
+}
+
+/// State kept between inputs evaluated one after the other by [`Interpreter::eval`].
+#[derive(Default)]
+pub struct Session {
+    mem: Memory,
+    statics: HashMap<NodeId, u64>,
+    strings: HashMap<Intern<String>, u64>,
+    /// Variables declared by statements evaluated at the top level.
+    vars: HashMap<Binding, u64>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The interpreter stage of the pipeline.
+#[derive(Default)]
+pub struct Interpreter {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the program's `main` function, returning its exit status. Returns [`None`] if the
+    /// program faulted, after reporting the fault.
+    pub fn run(&mut self, items: &[Item], analysis: &Analysis) -> Option<i32> {
+        // Deeply recursive programs need more stack than the main thread has.
+        let result = thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(HOST_STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    let (mut input, mut output) = (io::stdin().lock(), io::stdout().lock());
+                    let session = Session::new();
+                    Machine::new(items, analysis, session, &mut input, &mut output).run()
+                })
+                .expect("failed to spawn the interpreter thread")
+                .join()
+                .expect("the interpreter panicked")
+        });
+        match result {
+            Ok(status) => Some(status),
+            Err(fault) => {
+                self.report(fault.into());
+                None
+            }
+        }
+    }
+
+    /// Evaluates `stmts` at the top level of `session`, where the variables they declare outlive
+    /// them, then evaluates `result` and writes its value to `output`. `items` must be every item
+    /// of the session so far, and `analysis` must cover `stmts` and `result` as well. Statics not
+    /// seen before are allocated first. Returns [`None`] if evaluation faulted, after reporting the
+    /// fault, in which case the variables declared by `stmts` are forgotten.
+    #[allow(clippy::too_many_arguments)]
+    pub fn eval(
+        &mut self,
+        session: &mut Session,
+        items: &[Item],
+        analysis: &Analysis,
+        stmts: &[Stmt],
+        result: Option<&Expr>,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Option<()> {
+        let vars = session.vars.clone();
+        let mut machine = Machine::new(items, analysis, std::mem::take(session), input, output);
+        let sp = machine.mem.sp();
+        let result = machine.eval_top(stmts, result);
+        if result.is_err() {
+            machine.frames.truncate(1);
+            machine.frames[0].vars = vars;
+            machine.mem.free(sp);
+        }
+        *session = machine.into_session();
+        match result {
+            Ok(()) => Some(()),
+            Err(fault) => {
+                self.report(fault.into());
+                None
+            }
+        }
+    }
+}
+
+impl CompilerPass for Interpreter {
+    const FAIL_CODE: u8 = 230;
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+/// State of a running program.
+struct Machine<'ast, 'io> {
+    items: &'ast [Item],
+    analysis: &'ast Analysis,
+    layouts: Layouts,
+    /// Maps the [`NodeId`] of each function item to the item.
+    fns: HashMap<NodeId, &'ast Item>,
+    /// Maps the [`NodeId`] of each static item to its address.
+    statics: HashMap<NodeId, u64>,
+    /// Addresses of the string literals already copied into memory.
+    strings: HashMap<Intern<String>, u64>,
+    mem: Memory,
+    frames: Vec<Frame>,
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+}
+
+impl<'ast, 'io> Machine<'ast, 'io> {
+    /// Prepares to run `items`, picking up from the memory and variables of `session`, whose
+    /// top-level variables make up the outermost frame.
+    fn new(
+        items: &'ast [Item],
+        analysis: &'ast Analysis,
+        session: Session,
+        input: &'io mut dyn BufRead,
+        output: &'io mut dyn Write,
+    ) -> Self {
+        let fns = items
+            .iter()
+            .filter(|item| matches!(item.kind, ItemKind::FnDecl(_) | ItemKind::FnDefn(_)))
+            .map(|item| (item.id, item))
+            .collect();
+        Self {
+            items,
+            analysis,
+            layouts: Layouts::new(items, PTR_SIZE),
+            fns,
+            statics: session.statics,
+            strings: session.strings,
+            mem: session.mem,
+            frames: vec![Frame {
+                vars: session.vars,
+                ret: None,
+            }],
+            input,
+            output,
+        }
+    }
+
+    fn into_session(mut self) -> Session {
+        let frame = self.frames.swap_remove(0);
+        Session {
+            mem: self.mem,
+            statics: self.statics,
+            strings: self.strings,
+            vars: frame.vars,
+        }
+    }
+
+    fn run(mut self) -> Result<i32, Fault> {
+        let status = self.run_main();
+        // Output written before a fault must still appear.
+        let flushed = self.output.flush();
+        let status = status?;
+        flushed.map_err(|err| {
+            Fault::new(
+                "E0412",
+                SimpleSpan::from(0..0),
+                format!("could not write output: {err}"),
+            )
+        })?;
+        Ok(status)
+    }
+
+    /// Allocates and initializes every static and constant not allocated yet.
+    fn alloc_statics(&mut self) -> Result<(), Fault> {
+        for item in self.items {
+            if let ItemKind::Static(_) | ItemKind::Const(_) = item.kind
+                && !self.statics.contains_key(&item.id)
+            {
+                let ty = self.analysis.types.decls[&item.id].clone();
+                let layout = self.layouts.of(&ty);
+                let addr = self.mem.alloc_static(layout.size, layout.align);
+                if let Some(value) = self.analysis.consts.get(&item.id) {
+                    self.init(&ty, addr, value).at(item.span)?;
+                }
+                self.statics.insert(item.id, addr);
+            }
+        }
+        Ok(())
+    }
+
+    fn run_main(&mut self) -> Result<i32, Fault> {
+        self.alloc_statics()?;
+
+        let main = self.items.iter().find_map(|item| match &item.kind {
+            ItemKind::FnDefn(defn) if *defn.sig.name.name == "main" => Some((item, defn)),
+            _ => None,
+        });
+        let Some((item, defn)) = main else {
+            return Err(Fault::new(
+                "E0401",
+                SimpleSpan::from(0..0),
+                "no `main` function to run".into(),
+            ));
+        };
+        if !defn.sig.params.is_empty() {
+            return Err(Fault::new(
+                "E0402",
+                item.span,
+                "`main` cannot take parameters".into(),
+            ));
+        }
+        match self.invoke(item.id, vec![], item.span)? {
+            Val::Int(status) => Ok(status),
+            Val::Void => Ok(0),
+            _ => Err(Fault::new(
+                "E0403",
+                item.span,
+                "`main` must return `int` or `void`".into(),
+            )),
+        }
+    }
+
+    /// Runs `stmts` in the outermost frame, then writes the value of `result`, if any. Stops early
+    /// at a `return`.
+    fn eval_top(&mut self, stmts: &[Stmt], result: Option<&Expr>) -> Result<(), Fault> {
+        self.alloc_statics()?;
+        for stmt in stmts {
+            if let Flow::Return(_) = self.exec(stmt)? {
+                return Ok(());
+            }
+        }
+        let Some(result) = result else {
+            return Ok(());
+        };
+        let sp = self.mem.sp();
+        let value = self.eval(result)?;
+        let ty = self.ty(result);
+        if *ty.kind != TyKind::Void {
+            let rendered = self.render(&ty, value).at(result.span)?;
+            writeln!(self.output, "{rendered}").map_err(|err| {
+                Fault::new(
+                    "E0412",
+                    result.span,
+                    format!("could not write output: {err}"),
+                )
+            })?;
+        }
+        self.mem.free(sp);
+        self.output.flush().map_err(|err| {
+            Fault::new(
+                "E0412",
+                result.span,
+                format!("could not write output: {err}"),
+            )
+        })
+    }
+
+    /// Renders `value` of type `ty` as source code, other than pointers, which are addresses.
+    fn render(&self, ty: &Ty, value: Val) -> Result<String, MemError> {
+        Ok(match &*ty.kind {
+            TyKind::Primitive(Primitive::Int) => value.int().to_string(),
+            TyKind::Primitive(Primitive::Char) => Value::Char(value.char() as char).to_string(),
+            TyKind::Primitive(Primitive::Bool) => value.bool().to_string(),
+            TyKind::Pointer(_) => format!("{:#x}", value.addr()),
+            TyKind::Struct(name) => {
+                let decl = self.struct_decl(*name);
+                let mut fields = vec![];
+                for field in &decl.fields {
+                    let (offset, ty) = self.layouts.field(*name, field.name);
+                    let value = self.load(&ty, value.addr() + offset as u64)?;
+                    fields.push(format!("{}: {}", field.name, self.render(&ty, value)?));
+                }
+                format!("struct {name} {{ {} }}", fields.join(", "))
+            }
+            TyKind::Array(len, elem) => {
+                let size = self.layouts.size_of(elem) as u64;
+                let elems = (0..len.get() as u64)
+                    .map(|i| {
+                        let value = self.load(elem, value.addr() + i * size)?;
+                        self.render(elem, value)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", elems.join(", "))
+            }
+            TyKind::Void | TyKind::Infer => unreachable!("cannot render a value of type `{ty}`"),
+        })
+    }
+
+    fn struct_decl(&self, name: Ident) -> &'ast StructDecl {
+        self.items
+            .iter()
+            .find_map(|item| match &item.kind {
+                ItemKind::StructDecl(decl) if decl.name == name => Some(decl),
+                _ => None,
+            })
+            .expect("undeclared struct after semantic analysis")
+    }
+
+    fn ty(&self, expr: &Expr) -> Ty {
+        self.analysis.types.exprs[&expr.id].clone()
+    }
+
+    /// The address of the variable `binding` refers to.
+    fn var(&self, binding: Binding) -> u64 {
+        match binding {
+            Binding::Static(item) => self.statics[&item],
+            _ => self.frames.last().expect("no call in progress").vars[&binding],
+        }
+    }
+
+    fn declare(&mut self, binding: Binding, ty: &Ty, span: SimpleSpan) -> Result<u64, Fault> {
+        let layout = self.layouts.of(ty);
+        let addr = self.mem.alloc(layout.size, layout.align).at(span)?;
+        let frame = self.frames.last_mut().expect("no call in progress");
+        frame.vars.insert(binding, addr);
+        Ok(addr)
+    }
+
+    fn load(&self, ty: &Ty, addr: u64) -> Result<Val, MemError> {
+        Ok(match *ty.kind {
+            TyKind::Primitive(Primitive::Int) => Val::Int(i32::from_le_bytes(
+                self.mem.load(addr, 4)?.try_into().unwrap(),
+            )),
+            TyKind::Primitive(Primitive::Char) => Val::Char(self.mem.load(addr, 1)?[0]),
+            TyKind::Primitive(Primitive::Bool) => Val::Bool(self.mem.load(addr, 1)?[0] != 0),
+            TyKind::Pointer(_) => Val::Ptr(u64::from_le_bytes(
+                self.mem.load(addr, PTR_SIZE)?.try_into().unwrap(),
+            )),
+            TyKind::Struct(_) | TyKind::Array(..) => {
+                self.mem.load(addr, self.layouts.size_of(ty))?;
+                Val::Agg(addr)
+            }
+            TyKind::Void | TyKind::Infer => unreachable!("cannot load a value of type `{ty}`"),
+        })
+    }
+
+    fn store(&mut self, ty: &Ty, addr: u64, value: Val) -> Result<(), MemError> {
+        match value {
+            Val::Int(v) => self.mem.store(addr, &v.to_le_bytes()),
+            Val::Char(c) => self.mem.store(addr, &[c]),
+            Val::Bool(b) => self.mem.store(addr, &[b as u8]),
+            Val::Ptr(p) => self.mem.store(addr, &p.to_le_bytes()),
+            Val::Agg(from) => self.mem.copy(from, addr, self.layouts.size_of(ty)),
+            Val::Void => unreachable!("cannot store a `void` value"),
+        }
+    }
+
+    /// Allocates room for a value of type `ty` on the stack, freed along with the statement.
+    fn temp(&mut self, ty: &Ty, span: SimpleSpan) -> Result<u64, Fault> {
+        let layout = self.layouts.of(ty);
+        self.mem.alloc(layout.size, layout.align).at(span)
+    }
+
+    /// Stores `value` as the element `index` of the array of type `ty` at `addr`.
+    fn store_elem(&mut self, ty: &Ty, addr: u64, index: usize, value: Val) -> Result<(), MemError> {
+        let TyKind::Array(_, elem) = &*ty.kind else {
+            unreachable!("array literal of a non-array type");
+        };
+        let size = self.layouts.size_of(elem);
+        self.store(elem, addr + (index * size) as u64, value)
+    }
+
+    /// Stores the constant `value`, of type `ty`, at `addr`.
+    fn init(&mut self, ty: &Ty, addr: u64, value: &Const) -> Result<(), MemError> {
+        let value = match value {
+            Const::Int(v) => Val::Int(*v),
+            Const::Char(c) => Val::Char(*c),
+            Const::Bool(b) => Val::Bool(*b),
+            Const::Str(s) => self.literal(Value::Str(*s)),
+            Const::Struct(name, fields) => {
+                for (field, value) in fields {
+                    let (offset, field_ty) = self.layouts.field(*name, *field);
+                    self.init(&field_ty, addr + offset as u64, value)?;
+                }
+                return Ok(());
+            }
+            Const::Array(elems) => {
+                let TyKind::Array(_, elem_ty) = &*ty.kind else {
+                    unreachable!("array constant of a non-array type");
+                };
+                let size = self.layouts.size_of(elem_ty);
+                for (i, value) in elems.iter().enumerate() {
+                    self.init(elem_ty, addr + (i * size) as u64, value)?;
+                }
+                return Ok(());
+            }
+        };
+        self.store(ty, addr, value)
+    }
+
+    fn literal(&mut self, value: Value) -> Val {
+        match value {
+            Value::Int(v) => Val::Int(v as i32),
+            Value::Char(c) => Val::Char(c as u8),
+            Value::Bool(b) => Val::Bool(b),
+            Value::Str(s) => {
+                let addr = *self.strings.entry(s).or_insert_with(|| {
+                    let mut bytes = s.as_bytes().to_vec();
+                    bytes.push(0);
+                    self.mem.alloc_rodata(&bytes)
+                });
+                Val::Ptr(addr)
+            }
+            Value::Invalid => unreachable!("invalid literal after semantic analysis"),
+        }
+    }
+
+    /// Calls the function `item` with `args`. `span` is the call site.
+    fn invoke(&mut self, item: NodeId, args: Vec<Val>, span: SimpleSpan) -> Result<Val, Fault> {
+        let item = self.fns[&item];
+        let defn = match &item.kind {
+            ItemKind::FnDefn(defn) => defn,
+            ItemKind::FnDecl(decl) => {
+                if let Some(service) = Service::of(&decl.sig) {
+                    return self.service(service, args, span);
+                }
+                return Err(Fault::new(
+                    "E0404",
+                    span,
+                    format!("function `{}` is declared but never defined", decl.sig.name),
+                ));
+            }
+            _ => unreachable!("call to a non-function item"),
+        };
+        // The outermost frame holds top-level variables, and is not a call.
+        if self.frames.len() > MAX_DEPTH {
+            return Err(Fault::new("E0405", span, "stack overflow".into()));
+        }
+
+        let ret_ty = &defn.sig.ty;
+        let ret = match *ret_ty.kind {
+            TyKind::Struct(_) | TyKind::Array(..) => {
+                let layout = self.layouts.of(ret_ty);
+                let addr = self.mem.alloc(layout.size, layout.align).at(span)?;
+                Some((addr, layout.size))
+            }
+            _ => None,
+        };
+        let sp = self.mem.sp();
+        self.frames.push(Frame {
+            vars: HashMap::new(),
+            ret,
+        });
+        for (index, (param, arg)) in defn.sig.params.iter().zip(args).enumerate() {
+            let binding = Binding::Param {
+                item: item.id,
+                index,
+            };
+            let addr = self.declare(binding, &param.ty, span)?;
+            self.store(&param.ty, addr, arg).at(span)?;
+        }
+        let flow = if *ret_ty.kind == TyKind::Void {
+            self.exec(&defn.block)?
+        } else {
+            self.exec_tail(&defn.block)?
+        };
+        self.frames.pop();
+        self.mem.free(sp);
+
+        match flow {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal if *ret_ty.kind == TyKind::Void => Ok(Val::Void),
+            // Type checking rejects every function with such a path, with `E0330`.
+            Flow::Normal => unreachable!("path through `{}` without a return", defn.sig.name),
+            Flow::Break | Flow::Continue => unreachable!("`break` or `continue` outside of a loop"),
+        }
+    }
+
+    /// Performs the runtime `service` with `args`. `span` is the call site.
+    fn service(
+        &mut self,
+        service: Service,
+        args: Vec<Val>,
+        span: SimpleSpan,
+    ) -> Result<Val, Fault> {
+        let io_fault =
+            |err: io::Error| Fault::new("E0412", span, format!("{} failed: {err}", service.name()));
+        match service {
+            Service::PrintI => {
+                write!(self.output, "{}", args[0].int()).map_err(io_fault)?;
+            }
+            Service::PrintC => self.output.write_all(&[args[0].char()]).map_err(io_fault)?,
+            Service::PrintS => {
+                let mut bytes = vec![];
+                let mut addr = args[0].addr();
+                loop {
+                    match self.mem.load(addr, 1).at(span)?[0] {
+                        0 => break,
+                        b => bytes.push(b),
+                    }
+                    addr += 1;
+                }
+                self.output.write_all(&bytes).map_err(io_fault)?;
+            }
+            Service::ReadI => {
+                self.output.flush().map_err(io_fault)?;
+                return self.read_int().map(Val::Int).map_err(io_fault);
+            }
+            Service::ReadC => {
+                self.output.flush().map_err(io_fault)?;
+                let c = self.read_byte().map_err(io_fault)?;
+                return Ok(Val::Char(c.unwrap_or(u8::MAX)));
+            }
+            Service::Malloc => {
+                let addr = usize::try_from(args[0].int())
+                    .ok()
+                    .and_then(|len| self.mem.alloc_heap(len, PTR_SIZE))
+                    .unwrap_or(0);
+                return Ok(Val::Ptr(addr));
+            }
+        }
+        Ok(Val::Void)
+    }
+
+    fn peek_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek_byte()?;
+        if b.is_some() {
+            self.input.consume(1);
+        }
+        Ok(b)
+    }
+
+    /// Skips whitespace, then reads an optionally signed decimal number, which wraps on overflow.
+    fn read_int(&mut self) -> io::Result<i32> {
+        while self.peek_byte()?.is_some_and(|b| b.is_ascii_whitespace()) {
+            self.input.consume(1);
+        }
+        let negative = match self.peek_byte()? {
+            Some(sign @ (b'-' | b'+')) => {
+                self.input.consume(1);
+                sign == b'-'
+            }
+            _ => false,
+        };
+        let mut value = 0i32;
+        while let Some(digit @ b'0'..=b'9') = self.peek_byte()? {
+            self.input.consume(1);
+            value = value.wrapping_mul(10).wrapping_add((digit - b'0') as i32);
+        }
+        Ok(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        })
+    }
+
+    /// Prepares `value` to be returned from the current call, moving a returned struct or array
+    /// out of the call's stack frame.
+    fn returned(&mut self, value: Val, span: SimpleSpan) -> Result<Flow, Fault> {
+        let frame = self.frames.last().expect("no call in progress");
+        let value = match (value, frame.ret) {
+            (Val::Agg(from), Some((to, size))) => {
+                self.mem.copy(from, to, size).at(span)?;
+                Val::Agg(to)
+            }
+            (value, _) => value,
+        };
+        Ok(Flow::Return(value))
+    }
+
+    /// Runs `stmt`, then frees anything it allocated on the stack.
+    fn exec_scoped(&mut self, stmt: &Stmt) -> Result<Flow, Fault> {
+        let sp = self.mem.sp();
+        let flow = self.exec(stmt);
+        self.mem.free(sp);
+        flow
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<Flow, Fault> {
+        match &stmt.kind {
+            StmtKind::Invalid => unreachable!("invalid statement after semantic analysis"),
+            StmtKind::Block(block) => {
+                let sp = self.mem.sp();
+                for stmt in &block.stmts {
+                    let flow = self.exec(stmt)?;
+                    if !matches!(flow, Flow::Normal) {
+                        self.mem.free(sp);
+                        return Ok(flow);
+                    }
+                }
+                self.mem.free(sp);
+                Ok(Flow::Normal)
+            }
+            StmtKind::Local(local) => {
+                let ty = self.analysis.types.decls[&stmt.id].clone();
+                let addr = self.declare(Binding::Local(stmt.id), &ty, stmt.span)?;
+                if let Some(value) = &local.value {
+                    let sp = self.mem.sp();
+                    let value = self.eval(value)?;
+                    self.store(&ty, addr, value).at(stmt.span)?;
+                    self.mem.free(sp);
+                }
+                Ok(Flow::Normal)
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (start, end, skip_start, skip_end) = match range {
+                    Range::Inclusive(start, end) => (start, end, false, false),
+                    Range::Exclusive(start, end) => (start, end, true, true),
+                    Range::ExclusiveInclusive(start, end) => (start, end, true, false),
+                    Range::InclusiveExclusive(start, end) => (start, end, false, true),
+                };
+                let start = self.eval_temp(start)?.int() as i64 + skip_start as i64;
+                let end = self.eval_temp(end)?.int() as i64 - skip_end as i64;
+                let ty = self.analysis.types.decls[&pattern.id].clone();
+                let addr = self.declare(Binding::Pattern(pattern.id), &ty, pattern.span)?;
+                for i in start..=end {
+                    self.store(&ty, addr, Val::Int(i as i32)).at(pattern.span)?;
+                    match self.exec_scoped(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => (),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            StmtKind::While(cond, body) => {
+                while self.eval_temp(cond)?.bool() {
+                    match self.exec_scoped(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => (),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            StmtKind::If(cond, then, els) => {
+                if self.eval_temp(cond)?.bool() {
+                    self.exec_scoped(then)
+                } else if let Some(els) = els {
+                    self.exec_scoped(els)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.eval_temp(expr)?;
+                Ok(Flow::Normal)
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Val::Void,
+                };
+                self.returned(value, stmt.span)
+            }
+            StmtKind::Continue => Ok(Flow::Continue),
+            StmtKind::Break => Ok(Flow::Break),
+        }
+    }
+
+    /// Runs `stmt` as the final statement of a non-void function, returning the value of a final
+    /// expression statement.
+    fn exec_tail(&mut self, stmt: &Stmt) -> Result<Flow, Fault> {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                let Some((last, init)) = block.stmts.split_last() else {
+                    return Ok(Flow::Normal);
+                };
+                let sp = self.mem.sp();
+                for stmt in init {
+                    let flow = self.exec(stmt)?;
+                    if !matches!(flow, Flow::Normal) {
+                        self.mem.free(sp);
+                        return Ok(flow);
+                    }
+                }
+                let flow = self.exec_tail(last);
+                self.mem.free(sp);
+                flow
+            }
+            StmtKind::If(cond, then, Some(els)) => {
+                if self.eval_temp(cond)?.bool() {
+                    self.exec_tail(then)
+                } else {
+                    self.exec_tail(els)
+                }
+            }
+            StmtKind::Expr(expr) => {
+                let value = self.eval(expr)?;
+                self.returned(value, expr.span)
+            }
+            _ => self.exec(stmt),
+        }
+    }
+
+    /// Evaluates `expr`, then frees any temporaries it allocated. The value must not be a struct
+    /// or array, which could be stored in a freed temporary.
+    fn eval_temp(&mut self, expr: &Expr) -> Result<Val, Fault> {
+        let sp = self.mem.sp();
+        let value = self.eval(expr)?;
+        self.mem.free(sp);
+        Ok(value)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Val, Fault> {
+        match &expr.kind {
+            ExprKind::Invalid => unreachable!("invalid expression after semantic analysis"),
+            ExprKind::Literal(lit) => Ok(self.literal(*lit.value)),
+            ExprKind::Ident(_)
+            | ExprKind::Deref(_)
+            | ExprKind::Index(..)
+            | ExprKind::FieldAccess(..) => {
+                let addr = self.place(expr)?;
+                self.load(&self.ty(expr), addr).at(expr.span)
+            }
+            ExprKind::Assign(lhs, rhs) => {
+                let addr = self.place(lhs)?;
+                let value = self.eval(rhs)?;
+                self.store(&self.ty(lhs), addr, value).at(expr.span)?;
+                Ok(match value {
+                    Val::Agg(_) => Val::Agg(addr),
+                    value => value,
+                })
+            }
+            // The address of `lhs` is only computed once.
+            ExprKind::CompoundAssign(lhs, op, rhs) => {
+                let ty = self.ty(lhs);
+                let addr = self.place(lhs)?;
+                let rhs = self.eval(rhs)?;
+                let lhs = self.load(&ty, addr).at(expr.span)?;
+                let value = operate(expr, lhs, *op, rhs)?;
+                self.store(&ty, addr, value).at(expr.span)?;
+                Ok(value)
+            }
+            ExprKind::BinOp(lhs, op, rhs) => self.binop(expr, lhs, *op, rhs),
+            ExprKind::CallFn(call) => self.call(expr, call),
+            ExprKind::Typecast(_, inner) => {
+                let value = self.eval(inner)?;
+                Ok(match (value, &*self.ty(expr).kind) {
+                    (Val::Int(v), TyKind::Primitive(Primitive::Char)) => Val::Char(v as u8),
+                    (Val::Char(c), TyKind::Primitive(Primitive::Int)) => Val::Int(c as i32),
+                    (Val::Int(v), TyKind::Primitive(Primitive::Bool)) => Val::Bool(v != 0),
+                    (Val::Char(c), TyKind::Primitive(Primitive::Bool)) => Val::Bool(c != 0),
+                    (Val::Bool(b), TyKind::Primitive(Primitive::Int)) => Val::Int(b as i32),
+                    (Val::Bool(b), TyKind::Primitive(Primitive::Char)) => Val::Char(b as u8),
+                    (Val::Agg(addr), TyKind::Pointer(_)) => Val::Ptr(addr),
+                    (value, _) => value,
+                })
+            }
+            ExprKind::Ref(inner) => Ok(Val::Ptr(self.place(inner)?)),
+            ExprKind::StructLit(ty, fields) => {
+                let TyKind::Struct(name) = *ty.kind else {
+                    unreachable!("struct literal of a non-struct type");
+                };
+                let addr = self.temp(ty, expr.span)?;
+                for field in fields {
+                    let value = self.eval(&field.value)?;
+                    let (offset, field_ty) = self.layouts.field(name, field.name);
+                    self.store(&field_ty, addr + offset as u64, value)
+                        .at(field.value.span)?;
+                }
+                Ok(Val::Agg(addr))
+            }
+            ExprKind::ArrayLit(elems) => {
+                let ty = self.ty(expr);
+                let addr = self.temp(&ty, expr.span)?;
+                for (i, elem) in elems.iter().enumerate() {
+                    let value = self.eval(elem)?;
+                    self.store_elem(&ty, addr, i, value).at(elem.span)?;
+                }
+                Ok(Val::Agg(addr))
+            }
+            ExprKind::ArrayRepeat(elem, _) => {
+                let ty = self.ty(expr);
+                // The REPL runs statements as entered, with their lengths still unevaluated.
+                let TyKind::Array(len, _) = *ty.kind else {
+                    unreachable!("array literal of a non-array type");
+                };
+                let addr = self.temp(&ty, expr.span)?;
+                let value = self.eval(elem)?;
+                for i in 0..len.get() {
+                    self.store_elem(&ty, addr, i, value).at(elem.span)?;
+                }
+                Ok(Val::Agg(addr))
+            }
+            ExprKind::Unary(op, inner) => {
+                let value = self.eval(inner)?;
+                Ok(match op {
+                    UnaryOp::Neg => Val::Int(value.int().wrapping_neg()),
+                    UnaryOp::Plus => value,
+                    UnaryOp::Not => Val::Bool(!value.bool()),
+                    UnaryOp::BitNot => Val::Int(!value.int()),
+                })
+            }
+        }
+    }
+
+    /// The address of the lvalue `expr`.
+    fn place(&mut self, expr: &Expr) -> Result<u64, Fault> {
+        match &expr.kind {
+            ExprKind::Ident(_) => Ok(self.var(self.analysis.resolutions.values[&expr.id])),
+            ExprKind::Deref(inner) => Ok(self.eval(inner)?.addr()),
+            ExprKind::Index(base, index) => {
+                let len = match *self.ty(base).kind {
+                    TyKind::Array(len, _) => Some(len.get()),
+                    _ => None,
+                };
+                let base = self.eval(base)?.addr();
+                let i = self.eval(index)?.int();
+                if let Some(len) = len
+                    && (i < 0 || i as usize >= len)
+                {
+                    return Err(Fault::new(
+                        "E0407",
+                        index.span,
+                        format!("index out of bounds: the length is {len} but the index is {i}"),
+                    ));
+                }
+                let size = self.layouts.size_of(&self.ty(expr)) as i64;
+                Ok(base.wrapping_add_signed(i as i64 * size))
+            }
+            ExprKind::FieldAccess(base, field) => {
+                // A pointer holds the address of its struct, like the struct's own value does.
+                let Some(name) = self.ty(base).field_owner() else {
+                    unreachable!("field access on a non-struct after semantic analysis");
+                };
+                let (offset, _) = self.layouts.field(name, *field);
+                Ok(self.eval(base)?.addr() + offset as u64)
+            }
+            _ => unreachable!("`{expr}` is not an lvalue"),
+        }
+    }
+
+    fn binop(&mut self, expr: &Expr, lhs: &Expr, op: Operator, rhs: &Expr) -> Result<Val, Fault> {
+        // Logical operators short-circuit.
+        if let Operator::Or | Operator::And = op {
+            let lhs = self.eval(lhs)?.bool();
+            if lhs == matches!(op, Operator::Or) {
+                return Ok(Val::Bool(lhs));
+            }
+            return self.eval(rhs);
+        }
+
+        let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+        operate(expr, lhs, op, rhs)
+    }
+
+    fn call(&mut self, expr: &Expr, call: &CallFn) -> Result<Val, Fault> {
+        let Binding::Fn(item) = self.analysis.resolutions.values[&call.name.id] else {
+            unreachable!("call to a non-function after semantic analysis");
+        };
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.eval(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.invoke(item, args, expr.span)
+    }
+}
+
+/// Applies the non-logical `op` to `lhs` and `rhs`, faulting at `expr` on division by zero.
+fn operate(expr: &Expr, lhs: Val, op: Operator, rhs: Val) -> Result<Val, Fault> {
+    let ordering = match (lhs, rhs) {
+        (Val::Int(l), Val::Int(r)) => l.cmp(&r),
+        (Val::Char(l), Val::Char(r)) => l.cmp(&r),
+        (Val::Bool(l), Val::Bool(r)) => l.cmp(&r),
+        (Val::Ptr(l), Val::Ptr(r)) => l.cmp(&r),
+        _ => unreachable!("cannot apply `{op}` to {lhs:?} and {rhs:?}"),
+    };
+    let value = match op {
+        Operator::Lt => Val::Bool(ordering.is_lt()),
+        Operator::Le => Val::Bool(ordering.is_le()),
+        Operator::Gt => Val::Bool(ordering.is_gt()),
+        Operator::Ge => Val::Bool(ordering.is_ge()),
+        Operator::Eq => Val::Bool(ordering.is_eq()),
+        Operator::Ne => Val::Bool(ordering.is_ne()),
+        _ => {
+            let (l, r) = (lhs.int(), rhs.int());
+            Val::Int(match op {
+                Operator::Add => l.wrapping_add(r),
+                Operator::Minus => l.wrapping_sub(r),
+                Operator::Times => l.wrapping_mul(r),
+                Operator::Div | Operator::Mod if r == 0 => {
+                    return Err(Fault::new("E0408", expr.span, "division by zero".into()));
+                }
+                Operator::Div => l.wrapping_div(r),
+                Operator::Mod => l.wrapping_rem(r),
+                Operator::BitOr => l | r,
+                Operator::BitXor => l ^ r,
+                Operator::BitAnd => l & r,
+                // Like the hardware, only the low five bits of the shift amount count.
+                Operator::Shl => l.wrapping_shl(r as u32),
+                Operator::Shr => l.wrapping_shr(r as u32),
+                _ => unreachable!(),
+            })
+        }
+    };
+    Ok(value)
+}
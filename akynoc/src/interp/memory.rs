@@ -0,0 +1,169 @@
+//! Simulated memory for the [interpreter](super).
+//!
+//! Memory is split into four segments, each starting at a fixed address so that pointers into one
+//! can never reach another:
+//! - `data`, holding statics.
+//! - `rodata`, holding string literals, which cannot be written to.
+//! - `stack`, holding the parameters, locals and temporaries of each call, which is limited to
+//!   [`STACK_SIZE`] bytes.
+//! - `heap`, holding memory allocated by the program at runtime, which is never freed and is
+//!   limited to [`HEAP_SIZE`] bytes.
+//!
+//! Every address below the first segment is unmapped, so that accessing a null pointer faults.
+
+/// Address of the first byte of each segment.
+const DATA_BASE: u64 = 0x1000;
+const RODATA_BASE: u64 = 0x1000_0000;
+const STACK_BASE: u64 = 0x2000_0000;
+const HEAP_BASE: u64 = 0x3000_0000;
+
+/// Maximum size of the stack, in bytes.
+pub const STACK_SIZE: usize = 8 << 20;
+
+/// Maximum size of the heap, in bytes.
+pub const HEAP_SIZE: usize = 256 << 20;
+
+/// Reasons a memory access can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemError {
+    /// Access through a null pointer.
+    Null,
+    /// Access to an address outside every segment.
+    Unmapped(u64),
+    /// Write to a read-only segment.
+    ReadOnly(u64),
+    /// The stack ran out of space.
+    StackOverflow,
+}
+
+struct Segment {
+    base: u64,
+    bytes: Vec<u8>,
+    writable: bool,
+}
+
+impl Segment {
+    fn new(base: u64, writable: bool) -> Self {
+        Self {
+            base,
+            bytes: vec![],
+            writable,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.base + self.bytes.len() as u64
+    }
+
+    /// Appends `len` zeroed bytes aligned to `align`, returning the address of the first.
+    fn push(&mut self, len: usize, align: usize) -> u64 {
+        let start = self.bytes.len().next_multiple_of(align);
+        self.bytes.resize(start + len, 0);
+        self.base + start as u64
+    }
+}
+
+pub struct Memory {
+    data: Segment,
+    rodata: Segment,
+    stack: Segment,
+    heap: Segment,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            data: Segment::new(DATA_BASE, true),
+            rodata: Segment::new(RODATA_BASE, false),
+            stack: Segment::new(STACK_BASE, true),
+            heap: Segment::new(HEAP_BASE, true),
+        }
+    }
+
+    /// Allocates zeroed space for a static.
+    pub fn alloc_static(&mut self, len: usize, align: usize) -> u64 {
+        self.data.push(len, align)
+    }
+
+    /// Copies `bytes` into read-only memory, returning their address.
+    pub fn alloc_rodata(&mut self, bytes: &[u8]) -> u64 {
+        let addr = self.rodata.push(bytes.len(), 1);
+        let start = (addr - self.rodata.base) as usize;
+        self.rodata.bytes[start..].copy_from_slice(bytes);
+        addr
+    }
+
+    /// Allocates zeroed space on the heap, returning [`None`] if the heap is full.
+    pub fn alloc_heap(&mut self, len: usize, align: usize) -> Option<u64> {
+        let start = self.heap.bytes.len().next_multiple_of(align);
+        if start + len > HEAP_SIZE {
+            return None;
+        }
+        Some(self.heap.push(len, align))
+    }
+
+    /// Allocates zeroed space on top of the stack.
+    pub fn alloc(&mut self, len: usize, align: usize) -> Result<u64, MemError> {
+        let start = self.stack.bytes.len().next_multiple_of(align);
+        if start + len > STACK_SIZE {
+            return Err(MemError::StackOverflow);
+        }
+        Ok(self.stack.push(len, align))
+    }
+
+    /// The address of the top of the stack, to later [`free`](Self::free) everything allocated
+    /// after this point.
+    pub fn sp(&self) -> u64 {
+        self.stack.end()
+    }
+
+    /// Frees everything allocated on the stack since [`sp`](Self::sp) returned `sp`.
+    pub fn free(&mut self, sp: u64) {
+        self.stack.bytes.truncate((sp - self.stack.base) as usize);
+    }
+
+    fn segment(&self, addr: u64, len: usize) -> Result<&Segment, MemError> {
+        if addr < DATA_BASE {
+            return Err(MemError::Null);
+        }
+        [&self.data, &self.rodata, &self.stack, &self.heap]
+            .into_iter()
+            .find(|seg| seg.base <= addr && addr + len as u64 <= seg.end())
+            .ok_or(MemError::Unmapped(addr))
+    }
+
+    /// Reads `len` bytes starting at `addr`.
+    pub fn load(&self, addr: u64, len: usize) -> Result<&[u8], MemError> {
+        let seg = self.segment(addr, len)?;
+        let start = (addr - seg.base) as usize;
+        Ok(&seg.bytes[start..start + len])
+    }
+
+    /// Writes `bytes` starting at `addr`.
+    pub fn store(&mut self, addr: u64, bytes: &[u8]) -> Result<(), MemError> {
+        let seg = self.segment(addr, bytes.len())?;
+        if !seg.writable {
+            return Err(MemError::ReadOnly(addr));
+        }
+        let base = seg.base;
+        let seg = [&mut self.data, &mut self.stack, &mut self.heap]
+            .into_iter()
+            .find(|seg| seg.base == base)
+            .unwrap();
+        let start = (addr - base) as usize;
+        seg.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Copies `len` bytes from `from` to `to`. The two ranges may overlap.
+    pub fn copy(&mut self, from: u64, to: u64, len: usize) -> Result<(), MemError> {
+        let bytes = self.load(from, len)?.to_vec();
+        self.store(to, &bytes)
+    }
+}
@@ -0,0 +1,128 @@
+//! Memory layout of types.
+//!
+//! Types are laid out as in C: `int` takes 4 bytes, `char` and `bool` take 1, and pointers take
+//! the target's pointer size. Arrays store their elements contiguously, and struct fields are
+//! stored in declaration order, each aligned to its own alignment, with the struct padded to a
+//! multiple of its largest alignment.
+use std::collections::HashMap;
+
+use crate::ast::{
+    structs::StructDecl,
+    types::{Ident, Primitive, Ty, TyKind},
+    Item, ItemKind,
+};
+
+/// Size and alignment of a type, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub size: usize,
+    pub align: usize,
+}
+
+/// Layout of a single struct.
+pub struct StructLayout {
+    pub layout: Layout,
+    /// Maps each field to its offset from the start of the struct, and its type.
+    pub fields: HashMap<Ident, (usize, Ty)>,
+}
+
+/// Layouts of every type in a program, for a target with a given pointer size.
+pub struct Layouts {
+    ptr_size: usize,
+    structs: HashMap<Ident, StructLayout>,
+}
+
+impl Layouts {
+    /// Computes the layout of every struct declared in `items`. Semantic analysis must have
+    /// succeeded, so that every struct is declared once and none contains itself.
+    pub fn new(items: &[Item], ptr_size: usize) -> Self {
+        let decls = items
+            .iter()
+            .filter_map(|item| match &item.kind {
+                ItemKind::StructDecl(decl) => Some((decl.name, decl)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let mut layouts = Self {
+            ptr_size,
+            structs: HashMap::new(),
+        };
+        for name in decls.keys() {
+            layouts.compute(*name, &decls);
+        }
+        layouts
+    }
+
+    fn compute(&mut self, name: Ident, decls: &HashMap<Ident, &StructDecl>) -> Layout {
+        if let Some(layout) = self.structs.get(&name) {
+            return layout.layout;
+        }
+        let mut offset = 0;
+        let mut align = 1;
+        let mut fields = HashMap::new();
+        for field in &decls[&name].fields {
+            let field_layout = self.compute_ty(&field.ty, decls);
+            offset = align_to(offset, field_layout.align);
+            fields.insert(field.name, (offset, field.ty.clone()));
+            offset += field_layout.size;
+            align = align.max(field_layout.align);
+        }
+        let layout = Layout {
+            size: align_to(offset, align),
+            align,
+        };
+        self.structs.insert(name, StructLayout { layout, fields });
+        layout
+    }
+
+    fn compute_ty(&mut self, ty: &Ty, decls: &HashMap<Ident, &StructDecl>) -> Layout {
+        match &*ty.kind {
+            TyKind::Struct(name) => self.compute(*name, decls),
+            TyKind::Array(len, elem) => {
+                let elem = self.compute_ty(elem, decls);
+                Layout {
+                    size: elem.size * len.get(),
+                    align: elem.align,
+                }
+            }
+            _ => self.of(ty),
+        }
+    }
+
+    /// The layout of `ty`.
+    pub fn of(&self, ty: &Ty) -> Layout {
+        match &*ty.kind {
+            TyKind::Primitive(Primitive::Int) => Layout { size: 4, align: 4 },
+            TyKind::Primitive(Primitive::Char | Primitive::Bool) => Layout { size: 1, align: 1 },
+            TyKind::Pointer(_) => Layout {
+                size: self.ptr_size,
+                align: self.ptr_size,
+            },
+            TyKind::Void => Layout { size: 0, align: 1 },
+            TyKind::Struct(name) => self.structs[name].layout,
+            TyKind::Array(len, elem) => {
+                let elem = self.of(elem);
+                Layout {
+                    size: elem.size * len.get(),
+                    align: elem.align,
+                }
+            }
+            TyKind::Infer => panic!("placeholder `{ty}` has no layout"),
+        }
+    }
+
+    /// The size of `ty`, in bytes.
+    pub fn size_of(&self, ty: &Ty) -> usize {
+        self.of(ty).size
+    }
+
+    /// The offset and type of `field` within the struct `name`.
+    pub fn field(&self, name: Ident, field: Ident) -> (usize, Ty) {
+        self.structs[&name].fields[&field].clone()
+    }
+}
+
+/// Rounds `offset` up to the next multiple of `align`.
+pub fn align_to(offset: usize, align: usize) -> usize {
+    offset.next_multiple_of(align)
+}
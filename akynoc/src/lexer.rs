@@ -1,24 +1,48 @@
 use std::{fmt::Display, path::Path};
 
-use ariadne::{Cache, FileCache, Report, Source};
+use ariadne::{Cache, FileCache, Source};
 use chumsky::span::SimpleSpan;
 use logos::Logos;
 use serde::Serialize;
 
+use crate::{diagnostic::Diagnostic, util::CompilerPass};
+
 #[derive(Logos, Clone, Debug, PartialEq, Serialize)]
-#[logos(skip r#"\s+"#)]
-#[logos(skip r#"//([^\n]+)?\n?"#)]
-#[logos(skip r#"/\*[^*]*\*+([^/*][^*]*\*+)*/"#)]
-#[logos(subpattern alpha = r#"[a-zA-Z]"#)]
-#[logos(subpattern num = r#"[0-9]"#)]
-#[logos(subpattern alphanum = r#"(?&alpha)|(?&num)"#)]
 #[logos(utf8 = true)]
-#[logos(subpattern special = r#"[\x20\x21\x23-\x26\x28-\x2F\x3A-\x40\x5B\x5D-\x60\x7B-\x7E]"#)]
 pub enum Token<'a> {
+    /// Trivia: kept by [`lex_lossless`], but never seen by the parser.
+    #[regex(r#"\s+"#)]
+    Whitespace(&'a str),
+    /// Trivia: a `//` comment, including the newline that ends it.
+    #[regex(r#"//([^\n]+)?\n?"#)]
+    LineComment(&'a str),
+    /// Trivia: a `/* */` comment.
+    #[regex(r#"/\*[^*]*\*+([^/*][^*]*\*+)*/"#)]
+    BlockComment(&'a str),
     #[regex(r#"([a-zA-Z][_a-zA-Z0-9]*|[_][_a-zA-Z0-9]+)"#)]
     Identifier(&'a str),
     #[token("=")]
     Assign,
+    #[token("+=")]
+    AddAssign,
+    #[token("-=")]
+    SubAssign,
+    #[token("*=")]
+    MulAssign,
+    #[token("/=")]
+    DivAssign,
+    #[token("%=")]
+    RemAssign,
+    #[token("&=")]
+    AndAssign,
+    #[token("|=")]
+    OrAssign,
+    #[token("^=")]
+    XorAssign,
+    #[token("<<=")]
+    ShlAssign,
+    #[token(">>=")]
+    ShrAssign,
     #[token("{")]
     LBrace,
     #[token("}")]
@@ -45,6 +69,12 @@ pub enum Token<'a> {
     Void,
     #[token("char")]
     Char,
+    #[token("bool")]
+    Bool,
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
     #[token("if")]
     If,
     #[token("else")]
@@ -63,11 +93,16 @@ pub enum Token<'a> {
     Break,
     #[token("static")]
     Static,
-    #[regex(r#"'((?&alphanum)|(?&special)|"| |\\(["'ntr]|x[0-9]{2}))'"#)]
+    #[token("const")]
+    Const,
+    /// Escape sequences are only decoded by [`unescape_char`], so any backslash sequence lexes.
+    #[regex(r#"'([^'\\\n]|\\[^\n])*'"#)]
     CharLiteral(&'a str),
-    #[regex(r#""((?&alphanum)|(?&special)|'| |\\(["'ntr]|x[0-9]{2}))*""#)]
+    /// Escape sequences are only decoded by [`unescape`], so any backslash sequence lexes.
+    #[regex(r#""([^"\\\n]|\\[^\n])*""#)]
     StrLiteral(&'a str),
-    #[regex(r#"[0-9]+"#)]
+    /// Digits are only checked by [`parse_int`], so a letter after a digit continues the literal.
+    #[regex(r#"[0-9][0-9a-zA-Z_]*"#)]
     IntLiteral(&'a str),
     #[token("&&")]
     LogAnd,
@@ -85,6 +120,10 @@ pub enum Token<'a> {
     Le,
     #[token(">=")]
     Ge,
+    #[token("<<")]
+    Shl,
+    #[token(">>")]
+    Shr,
     #[token("+")]
     Plus,
     #[token("-")]
@@ -97,12 +136,22 @@ pub enum Token<'a> {
     Rem,
     #[token("&")]
     And,
+    #[token("|")]
+    Pipe,
+    #[token("^")]
+    Caret,
+    #[token("!")]
+    Bang,
+    #[token("~")]
+    Tilde,
     #[token(".")]
     Dot,
     #[token("=>")]
     FatArrow,
     #[token("let")]
     Let,
+    #[token("mut")]
+    Mut,
     #[token(":=")]
     Define,
     Invalid,
@@ -112,8 +161,19 @@ impl<'a> Display for Token<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Token::*;
         match self {
+            Whitespace(s) | LineComment(s) | BlockComment(s) => write!(f, "{s}"),
             Identifier(s) => write!(f, "{s}"),
             Assign => write!(f, "="),
+            AddAssign => write!(f, "+="),
+            SubAssign => write!(f, "-="),
+            MulAssign => write!(f, "*="),
+            DivAssign => write!(f, "/="),
+            RemAssign => write!(f, "%="),
+            AndAssign => write!(f, "&="),
+            OrAssign => write!(f, "|="),
+            XorAssign => write!(f, "^="),
+            ShlAssign => write!(f, "<<="),
+            ShrAssign => write!(f, ">>="),
             LBrace => write!(f, "{{"),
             RBrace => write!(f, "}}"),
             LPar => write!(f, "("),
@@ -127,6 +187,9 @@ impl<'a> Display for Token<'a> {
             Int => write!(f, "int"),
             Void => write!(f, "void"),
             Char => write!(f, "char"),
+            Bool => write!(f, "bool"),
+            True => write!(f, "true"),
+            False => write!(f, "false"),
             If => write!(f, "if"),
             Else => write!(f, "else"),
             While => write!(f, "while"),
@@ -136,6 +199,7 @@ impl<'a> Display for Token<'a> {
             Continue => write!(f, "continue"),
             Break => write!(f, "break"),
             Static => write!(f, "static"),
+            Const => write!(f, "const"),
             CharLiteral(c) => write!(f, "{c}"),
             StrLiteral(s) => write!(f, "{s}"),
             IntLiteral(i) => write!(f, "{i}"),
@@ -147,27 +211,52 @@ impl<'a> Display for Token<'a> {
             Lt => write!(f, "<"),
             Le => write!(f, "<="),
             Ge => write!(f, ">="),
+            Shl => write!(f, "<<"),
+            Shr => write!(f, ">>"),
             Plus => write!(f, "+"),
             Minus => write!(f, "-"),
             Asterisk => write!(f, "*"),
             Div => write!(f, "/"),
             Rem => write!(f, "%"),
             And => write!(f, "&"),
+            Pipe => write!(f, "|"),
+            Caret => write!(f, "^"),
+            Bang => write!(f, "!"),
+            Tilde => write!(f, "~"),
             Dot => write!(f, "."),
             FatArrow => write!(f, "=>"),
             Let => write!(f, "let"),
+            Mut => write!(f, "mut"),
             Define => write!(f, ":="),
             Invalid => write!(f, "INVALID"),
         }
     }
 }
 
+impl Token<'_> {
+    /// Whether this is whitespace or a comment, which the parser never sees.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            Token::Whitespace(_) | Token::LineComment(_) | Token::BlockComment(_)
+        )
+    }
+}
+
 pub struct SourceFile {
     pub name: String,
     pub source: Source,
 }
 
 impl SourceFile {
+    /// A source file that only exists in memory, such as a document open in an editor.
+    pub fn new(name: String, text: String) -> Self {
+        Self {
+            name,
+            source: Source::from(text),
+        }
+    }
+
     pub fn from_path(id: &Path, mut cache: FileCache) -> Self {
         let source = cache.fetch(id).unwrap().clone();
         Self {
@@ -177,64 +266,287 @@ impl SourceFile {
     }
 }
 
+/// Lexes `src` into the tokens the parser consumes, without trivia and with the quotes of char
+/// and string literals stripped.
 pub fn lex<'src>(
     src: &'src SourceFile,
+) -> impl std::iter::Iterator<Item = (Token<'src>, SimpleSpan)> {
+    lex_lossless(src)
+        .filter(|(tok, _)| !tok.is_trivia())
+        .map(|(tok, span)| match tok {
+            Token::CharLiteral(c) => (
+                Token::CharLiteral(c.strip_prefix("'").unwrap().strip_suffix("'").unwrap()),
+                span,
+            ),
+            Token::StrLiteral(s) => (
+                Token::StrLiteral(s.strip_prefix("\"").unwrap().strip_suffix("\"").unwrap()),
+                span,
+            ),
+            t => (t, span),
+        })
+}
+
+/// Lexes every byte of `src`, trivia included, so that the spans of the tokens tile the source
+/// exactly. Literals keep their quotes. This is the token stream [`cst`](crate::cst) is built from.
+pub fn lex_lossless<'src>(
+    src: &'src SourceFile,
 ) -> impl std::iter::Iterator<Item = (Token<'src>, SimpleSpan)> {
     Token::lexer(src.source.text())
         .spanned()
-        .map(|(tok, span)| -> (Token<'src>, SimpleSpan) {
-            match tok {
-                Ok(t) => match t {
-                    Token::CharLiteral(c) => (
-                        Token::CharLiteral(c.strip_prefix("'").unwrap().strip_suffix("'").unwrap()),
-                        span.into(),
-                    ),
-                    Token::StrLiteral(s) => (
-                        Token::StrLiteral(
-                            s.strip_prefix("\"").unwrap().strip_suffix("\"").unwrap(),
-                        ),
-                        span.into(),
-                    ),
-                    t => (t, span.into()),
-                },
-                Err(()) => {
-                    let span = Into::<SimpleSpan>::into(span);
-                    (Token::Invalid, span)
+        .map(|(tok, span)| (tok.unwrap_or(Token::Invalid), span.into()))
+}
+
+/// Describes a [`Token::Invalid`] at `span` in `text`.
+///
+/// A literal may hold any character other than a newline, so an invalid token that starts with a
+/// quote is a literal that was never closed on its line.
+pub fn invalid_token(text: &str, span: SimpleSpan) -> Diagnostic {
+    let invalid = &text[span.into_range()];
+    if invalid.starts_with('"') {
+        Diagnostic::error("E0002", "Unterminated string literal")
+            .with_primary(span, "Missing a closing `\"`")
+    } else if invalid.starts_with('\'') {
+        Diagnostic::error("E0003", "Unterminated character literal")
+            .with_primary(span, "Missing a closing `'`")
+    } else {
+        Diagnostic::error("E0001", "Lexing error").with_primary(span, "Unrecognized character")
+    }
+}
+
+/// Every error in `token`, as produced by [`lex`] at `span` of `text`: either the token is
+/// invalid, or it is a literal with escape sequences that cannot be decoded.
+pub fn token_errors(text: &str, token: &Token, span: SimpleSpan) -> Vec<Diagnostic> {
+    match token {
+        Token::Invalid => vec![invalid_token(text, span)],
+        Token::CharLiteral(body) => unescape_char(body, span.start).err().unwrap_or_default(),
+        Token::StrLiteral(body) => unescape(body, span.start).err().unwrap_or_default(),
+        Token::IntLiteral(literal) => parse_int(literal, span.start).err().into_iter().collect(),
+        _ => vec![],
+    }
+}
+
+/// Decodes the integer literal `literal` at offset `start` of the source. It has an optional `0x`,
+/// `0o` or `0b` prefix, and may separate its digits with `_`.
+pub fn parse_int(literal: &str, start: usize) -> Result<u32, Diagnostic> {
+    let (radix, prefix) = match literal.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0o") => (8, 2),
+        Some("0b") => (2, 2),
+        _ => (10, 0),
+    };
+    let rest = &literal[prefix..];
+    let end = rest
+        .find(|c: char| c != '_' && !c.is_digit(radix))
+        .unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(end);
+    let whole = SimpleSpan::from(start..start + literal.len());
+    // Where the digits end, and the suffix starts.
+    let end = start + prefix + end;
+    if suffix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(
+            Diagnostic::error("E0008", "Invalid integer literal").with_primary(
+                SimpleSpan::from(end..end + 1),
+                format!("Not a digit in base {radix}"),
+            ),
+        );
+    }
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return Err(Diagnostic::error("E0008", "Invalid integer literal")
+            .with_primary(whole, format!("Expected digits after `{}`", &literal[..2])));
+    }
+    if !suffix.is_empty() {
+        return Err(
+            Diagnostic::error("E0010", "Invalid suffix on integer literal")
+                .with_primary(
+                    SimpleSpan::from(end..whole.end),
+                    format!("`{suffix}` is not a suffix"),
+                )
+                .with_note("`int` is the only integer type, so integer literals take no suffix"),
+        );
+    }
+    // Only `-2147483648` needs the largest magnitude; type checking rejects it without the `-`.
+    let max = i32::MIN.unsigned_abs();
+    u32::from_str_radix(&digits, radix)
+        .ok()
+        .filter(|&value| value <= max)
+        .ok_or_else(|| {
+            Diagnostic::error("E0009", "Integer literal out of range").with_primary(
+                whole,
+                format!(
+                    "Larger than the maximum of {}, or {max} after `-`",
+                    i32::MAX
+                ),
+            )
+        })
+}
+
+/// Decodes the escape sequences in `body`, the text between the quotes of a literal whose opening
+/// quote is at offset `start` of the source.
+pub fn unescape(body: &str, start: usize) -> Result<String, Vec<Diagnostic>> {
+    let mut decoded = String::with_capacity(body.len());
+    let mut errors = vec![];
+    let mut i = 0;
+    while let Some(c) = body[i..].chars().next() {
+        if c == '\\' {
+            let (result, len) = unescape_one(&body[i + 1..], start + 1 + i);
+            match result {
+                Ok(c) => decoded.push(c),
+                Err(error) => errors.push(error),
+            }
+            i += len;
+        } else {
+            decoded.push(c);
+            i += c.len_utf8();
+        }
+    }
+    if errors.is_empty() {
+        Ok(decoded)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Decodes the body of a char literal, like [`unescape`], which must be a single ASCII character
+/// since a `char` is one byte.
+pub fn unescape_char(body: &str, start: usize) -> Result<char, Vec<Diagnostic>> {
+    let decoded = unescape(body, start)?;
+    let mut chars = decoded.chars();
+    let label = match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => return Ok(c),
+        (Some(_), None) => "Not an ASCII character",
+        (None, _) => "Empty character literal",
+        (Some(_), Some(_)) => "Expected a single character or escape",
+    };
+    let span = SimpleSpan::from(start..start + body.len() + 2);
+    Err(vec![Diagnostic::error(
+        "E0004",
+        "Invalid character literal",
+    )
+    .with_primary(span, label)])
+}
+
+/// Decodes the escape sequence whose backslash is at offset `start` of the source, and is followed
+/// by `escape`. Returns the length of the sequence, backslash included, even if it is invalid.
+fn unescape_one(escape: &str, start: usize) -> (Result<char, Diagnostic>, usize) {
+    let span = |len: usize| SimpleSpan::from(start..start + len);
+    let Some(c) = escape.chars().next() else {
+        let error = Diagnostic::error("E0005", "Unknown escape sequence")
+            .with_primary(span(1), "Expected a character after `\\`");
+        return (Err(error), 1);
+    };
+    let (decoded, len) = match c {
+        'n' => ('\n', 2),
+        't' => ('\t', 2),
+        'r' => ('\r', 2),
+        '"' | '\'' | '\\' => (c, 2),
+        'x' => {
+            let digits = escape[1..]
+                .chars()
+                .take(2)
+                .take_while(char::is_ascii_hexdigit)
+                .count();
+            let len = 2 + digits;
+            if digits < 2 {
+                let error = Diagnostic::error("E0006", "Malformed `\\x` escape")
+                    .with_primary(span(len), "Expected two hexadecimal digits");
+                return (Err(error), len);
+            }
+            match u8::from_str_radix(&escape[1..3], 16).unwrap() {
+                value @ 0..=0x7F => (value as char, len),
+                _ => {
+                    let error = Diagnostic::error("E0007", "Out of range `\\x` escape")
+                        .with_primary(span(len), "Must be at most `\\x7F`");
+                    return (Err(error), len);
                 }
             }
-        })
+        }
+        'u' => {
+            let Some(inner) = escape[1..].strip_prefix('{') else {
+                let error = Diagnostic::error("E0006", "Malformed `\\u` escape")
+                    .with_primary(span(2), "Expected `{` and hexadecimal digits");
+                return (Err(error), 2);
+            };
+            let digits = inner
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(inner.len());
+            let closed = inner[digits..].starts_with('}');
+            let len = 3 + digits + usize::from(closed);
+            if !closed || !(1..=6).contains(&digits) {
+                let error = Diagnostic::error("E0006", "Malformed `\\u` escape").with_primary(
+                    span(len),
+                    "Expected one to six hexadecimal digits in braces",
+                );
+                return (Err(error), len);
+            }
+            match char::from_u32(u32::from_str_radix(&inner[..digits], 16).unwrap()) {
+                Some(c) => (c, len),
+                None => {
+                    let error = Diagnostic::error("E0007", "Out of range `\\u` escape")
+                        .with_primary(span(len), "Not a Unicode scalar value");
+                    return (Err(error), len);
+                }
+            }
+        }
+        _ => {
+            let len = 1 + c.len_utf8();
+            let error = Diagnostic::error("E0005", "Unknown escape sequence")
+                .with_primary(span(len), format!("`\\{c}` is not an escape"));
+            return (Err(error), len);
+        }
+    };
+    (Ok(decoded), len)
 }
 
-pub fn print_errors<'src, I>(src: &'src SourceFile, token_iter: I)
-where
-    I: std::iter::Iterator<Item = (Token<'src>, SimpleSpan)>,
-{
-    token_iter.for_each(|(tok, span)| match tok {
-        Token::Invalid => Report::build(ariadne::ReportKind::Error, (&src.name, span.into_range()))
-            .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
-            .with_code(250)
-            .with_message("Lexing error")
-            .with_label(
-                ariadne::Label::new((&src.name, span.into_range()))
-                    .with_message("Unrecognized character")
-                    .with_color(ariadne::Color::Red),
-            )
-            .finish()
-            .eprint((&src.name, &src.source))
-            .unwrap(),
-        _ => (),
-    });
+/// The lexing stage of the pipeline.
+///
+/// Unlike [`lex`], this collects the whole token stream up front, reporting every
+/// [`Token::Invalid`] and every literal that cannot be decoded as it goes.
+pub struct LexerPass<'src> {
+    src: &'src SourceFile,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'src> LexerPass<'src> {
+    pub fn new(src: &'src SourceFile) -> Self {
+        Self {
+            src,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn lex(&mut self) -> Vec<(Token<'src>, SimpleSpan)> {
+        let tokens = lex(self.src).collect::<Vec<_>>();
+        for (token, span) in &tokens {
+            for diagnostic in token_errors(self.src.source.text(), token, *span) {
+                self.report(diagnostic);
+            }
+        }
+        tokens
+    }
+}
+
+impl CompilerPass for LexerPass<'_> {
+    const FAIL_CODE: u8 = 250;
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Write;
+    use std::{io::Write, ops::Range};
 
     use ariadne::FileCache;
     use rstest::{fixture, rstest};
     use tempfile::NamedTempFile;
 
-    use crate::lexer::SourceFile;
+    use crate::{lexer::SourceFile, util::CompilerPass};
 
     #[fixture]
     fn cache() -> FileCache {
@@ -250,8 +562,19 @@ mod tests {
     #[rstest]
     #[case::plus("+", "+")]
     #[case::minus("-", "-")]
+    #[case::bools("!true && false", "!true&&false")]
+    #[case::tilde("~x", "~x")]
+    #[case::bitwise("a|b^c&&d<<e>>f", "a|b^c&&d<<e>>f")]
+    #[case::compound_assign("x+=1;y<<=2>>=z", "x+=1;y<<=2>>=z")]
+    #[case::mutability("const N: int = 1; let mut x := N;", "constN:int=1;letmutx:=N;")]
     #[case::a("'a'", "a")]
-    #[case::newline(r"'\n'", r"\n")]
+    #[case::newline(r"'\n'", "\n")]
+    #[case::hex("0x1F", "31")]
+    #[case::octal("0o17", "15")]
+    #[case::binary("0b1010_0101", "165")]
+    #[case::separators("1_000_000", "1000000")]
+    #[case::max("0x8000_0000", "2147483648")]
+    #[case::escapes(r#""\t\"\x41\u{e9}\\""#, "\t\"Aé\\")]
     #[should_panic]
     #[case::question_mark("int x?;", "")]
     #[case::main_defn("void main() {\n\treturn;\n}", "voidmain(){return;}")]
@@ -261,14 +584,69 @@ mod tests {
         let src_file = src(input, cache);
         let token_iter = super::lex(&src_file);
         let recovered = token_iter
-            .map(|(t, _)| {
-                if t == super::Token::Invalid {
-                    panic!("Invalid token");
-                } else {
-                    t.to_string()
-                }
+            .map(|(t, _)| match t {
+                super::Token::Invalid => panic!("Invalid token"),
+                super::Token::CharLiteral(c) => super::unescape_char(c, 0).unwrap().to_string(),
+                super::Token::StrLiteral(s) => super::unescape(s, 0).unwrap(),
+                super::Token::IntLiteral(i) => super::parse_int(i, 0).unwrap().to_string(),
+                t => t.to_string(),
             })
             .collect::<String>();
         assert_eq!(recovered, expected);
     }
+
+    #[rstest]
+    #[case::unterminated_str("\"abc\n", vec![("E0002", 0..4)])]
+    #[case::unterminated_char("'a;", vec![("E0003", 0..3)])]
+    #[case::empty_char("''", vec![("E0004", 0..2)])]
+    #[case::long_char("'ab'", vec![("E0004", 0..4)])]
+    #[case::non_ascii_char("'é'", vec![("E0004", 0..4)])]
+    #[case::unicode_char(r"'\u{e9}'", vec![("E0004", 0..8)])]
+    #[case::unknown(r#""a\qb""#, vec![("E0005", 2..4)])]
+    #[case::short_hex(r#""\x4""#, vec![("E0006", 1..4)])]
+    #[case::unopened_unicode(r#""\u41""#, vec![("E0006", 1..3)])]
+    #[case::unclosed_unicode(r#""\u{41""#, vec![("E0006", 1..6)])]
+    #[case::long_unicode(r#""\u{1234567}""#, vec![("E0006", 1..12)])]
+    #[case::hex_range(r#""\x80""#, vec![("E0007", 1..5)])]
+    #[case::unicode_range(r#""\u{d800}""#, vec![("E0007", 1..9)])]
+    #[case::several(r#""\q\x""#, vec![("E0005", 1..3), ("E0006", 3..5)])]
+    #[case::binary_digit("0b102", vec![("E0008", 4..5)])]
+    #[case::octal_digit("0o78", vec![("E0008", 3..4)])]
+    #[case::no_digits("0x_", vec![("E0008", 0..3)])]
+    #[case::out_of_range("99999999999", vec![("E0009", 0..11)])]
+    #[case::hex_out_of_range("0x1_0000_0000", vec![("E0009", 0..13)])]
+    #[case::above_int_max("3000000000", vec![("E0009", 0..10)])]
+    #[case::suffix("10u8", vec![("E0010", 2..4)])]
+    #[case::hex_suffix("0xffg", vec![("E0010", 4..5)])]
+    fn test_token_errors(
+        #[case] input: String,
+        #[case] expected: Vec<(&str, Range<usize>)>,
+        cache: FileCache,
+    ) {
+        let src_file = src(input, cache);
+        let mut lexer = super::LexerPass::new(&src_file);
+        lexer.lex();
+        let found = lexer
+            .diagnostics()
+            .iter()
+            .map(|d| (d.code, d.span().unwrap().into_range()))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, found);
+    }
+
+    #[rstest]
+    #[case::whitespace(" \t\n", 1)]
+    #[case::line_comment("// a\n// b", 2)]
+    #[case::block_comment("x/* a\n */y", 3)]
+    #[case::invalid("int x?;", 5)]
+    fn test_lossless(#[case] input: String, #[case] expected: usize, cache: FileCache) {
+        let src_file = src(input.clone(), cache);
+        let tokens = super::lex_lossless(&src_file).collect::<Vec<_>>();
+        let recovered = tokens
+            .iter()
+            .map(|(_, span)| &input[span.into_range()])
+            .collect::<String>();
+        assert_eq!(recovered, input);
+        assert_eq!(tokens.len(), expected);
+    }
 }
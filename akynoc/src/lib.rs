@@ -1,5 +1,15 @@
 #![doc = include_str!("../README.md")]
 pub mod ast;
+pub mod codegen;
+pub mod cst;
+pub mod diagnostic;
+pub mod format;
+pub mod interp;
+pub mod layout;
 pub mod lexer;
+pub mod lsp;
 pub mod parser;
+pub mod repl;
+pub mod runtime;
+pub mod sem;
 pub mod util;
@@ -0,0 +1,232 @@
+//! Language server, speaking the Language Server Protocol over stdio (`akynoc lsp`).
+//!
+//! Documents are synchronised in full on every change, and re-analysed from scratch, which is
+//! cheap for programs of the size Akyno is used for. The server provides:
+//! - diagnostics for every lexing, syntax and semantic error, published on every change,
+//! - document symbols for functions, structs with their fields, statics and constants,
+//! - go-to-definition for names and struct types,
+//! - hover, showing the signature of a function or the type of a variable.
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chumsky::span::SimpleSpan;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait, PublishDiagnostics,
+    },
+    request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as RequestTrait},
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams,
+    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString,
+    OneOf, PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Uri,
+};
+
+use crate::diagnostic::{self, Severity};
+
+mod document;
+
+use document::{Document, Symbol};
+
+/// Serves a single client over stdin and stdout, until it asks the server to exit.
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Serves a client over `connection`, from the `initialize` request to the `exit` notification.
+pub fn serve(connection: &Connection) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+    };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                server.request(request)?;
+            }
+            Message::Notification(notification) => server.notify(notification)?,
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Uri, Document>,
+}
+
+impl Server<'_> {
+    fn request(&mut self, request: Request) -> Result<()> {
+        let response = match request.method.as_str() {
+            DocumentSymbolRequest::METHOD => {
+                let (id, params) =
+                    request.extract::<DocumentSymbolParams>(DocumentSymbolRequest::METHOD)?;
+                let symbols = self.documents.get(&params.text_document.uri).map(|doc| {
+                    let symbols = doc
+                        .symbols
+                        .iter()
+                        .map(|symbol| document_symbol(doc, symbol));
+                    DocumentSymbolResponse::Nested(symbols.collect())
+                });
+                Response::new_ok(id, symbols)
+            }
+            GotoDefinition::METHOD => {
+                let (id, params) =
+                    request.extract::<GotoDefinitionParams>(GotoDefinition::METHOD)?;
+                let position = params.text_document_position_params;
+                let uri = position.text_document.uri;
+                let location = self.documents.get(&uri).and_then(|doc| {
+                    let span = doc.definition(doc.offset(position.position))?;
+                    let location = Location::new(uri.clone(), doc.range(span));
+                    Some(GotoDefinitionResponse::Scalar(location))
+                });
+                Response::new_ok(id, location)
+            }
+            HoverRequest::METHOD => {
+                let (id, params) = request.extract::<HoverParams>(HoverRequest::METHOD)?;
+                let position = params.text_document_position_params;
+                let hover = self
+                    .documents
+                    .get(&position.text_document.uri)
+                    .and_then(|doc| {
+                        let (span, text) = doc.hover(doc.offset(position.position))?;
+                        Some(Hover {
+                            contents: HoverContents::Markup(MarkupContent {
+                                kind: MarkupKind::Markdown,
+                                value: format!("```akyno\n{text}\n```"),
+                            }),
+                            range: Some(doc.range(span)),
+                        })
+                    });
+                Response::new_ok(id, hover)
+            }
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request {method}"),
+            ),
+        };
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn notify(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)?;
+                let doc = params.text_document;
+                self.update(doc.uri, doc.text, Some(doc.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD)?;
+                // Changes are always the full text, since that is the only kind of sync offered.
+                match params.content_changes.into_iter().last() {
+                    Some(change) => {
+                        let doc = params.text_document;
+                        self.update(doc.uri, change.text, Some(doc.version))
+                    }
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.publish(PublishDiagnosticsParams::new(uri, vec![], None))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Analyses the new text of a document, and publishes its diagnostics.
+    fn update(&mut self, uri: Uri, text: String, version: Option<i32>) -> Result<()> {
+        let doc = Document::new(uri.as_str().to_string(), text);
+        let diagnostics = doc
+            .diagnostics
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(&doc, &uri, diagnostic))
+            .collect();
+        self.documents.insert(uri.clone(), doc);
+        self.publish(PublishDiagnosticsParams::new(uri, diagnostics, version))
+    }
+
+    fn publish(&self, params: PublishDiagnosticsParams) -> Result<()> {
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+}
+
+/// Converts a diagnostic, with its secondary labels as related information and its notes appended
+/// to its message.
+fn lsp_diagnostic(doc: &Document, uri: &Uri, diagnostic: &diagnostic::Diagnostic) -> Diagnostic {
+    let related = diagnostic
+        .labels
+        .iter()
+        .filter(|label| !label.primary)
+        .map(|label| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), doc.range(label.span)),
+            message: label.message.clone().unwrap_or_default(),
+        });
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+    };
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+    Diagnostic {
+        range: doc.range(diagnostic.span().unwrap_or(SimpleSpan::from(0..0))),
+        severity: Some(severity),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("akynoc".to_string()),
+        message,
+        related_information: Some(related.collect()),
+        ..Diagnostic::default()
+    }
+}
+
+// `deprecated` is itself deprecated, but has no default.
+#[allow(deprecated)]
+fn document_symbol(doc: &Document, symbol: &Symbol) -> DocumentSymbol {
+    DocumentSymbol {
+        name: symbol.name.clone(),
+        detail: Some(symbol.detail.clone()),
+        kind: symbol.kind,
+        tags: None,
+        deprecated: None,
+        range: doc.range(symbol.span),
+        selection_range: doc.range(symbol.name_span),
+        children: (!symbol.children.is_empty()).then(|| {
+            symbol
+                .children
+                .iter()
+                .map(|child| document_symbol(doc, child))
+                .collect()
+        }),
+    }
+}
@@ -0,0 +1,640 @@
+//! Analysis of a single open document, in terms of byte spans of its text.
+use std::collections::HashMap;
+
+use chumsky::{
+    input::{Input, Stream},
+    span::SimpleSpan,
+    Parser,
+};
+use lsp_types::{Position, Range, SymbolKind};
+
+use crate::{
+    ast::{
+        exprs::{Expr, ExprKind},
+        functions::FnSig,
+        pattern::{Pattern, PatternKind, Range as PatternRange},
+        statements::{Stmt, StmtKind},
+        types::{Ident, Ty, TyKind},
+        Item, ItemKind,
+    },
+    diagnostic::Diagnostic,
+    lexer::{lex, token_errors, SourceFile, Token},
+    parser,
+    sem::{
+        consteval::ConstEval,
+        infer::Inference,
+        resolve::{Binding, Resolutions, Resolver},
+        typeck::{TypeChecker, TypeInfo},
+        SemError,
+    },
+    util::{NodeId, Writable, Writer},
+};
+
+/// A named item, or a field of a struct.
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Signature or type of the symbol.
+    pub detail: String,
+    pub span: SimpleSpan,
+    pub name_span: SimpleSpan,
+    pub children: Vec<Symbol>,
+}
+
+/// A name appearing in the source, whether it declares something or refers to a declaration.
+struct Occurrence {
+    span: SimpleSpan,
+    /// Span of the name in the declaration referred to.
+    definition: Option<SimpleSpan>,
+    /// Source code describing what the name refers to.
+    hover: Option<String>,
+}
+
+/// One version of an open document, analysed as far as its errors allow.
+pub struct Document {
+    pub src: SourceFile,
+    /// Offset of the start of each line.
+    lines: Vec<usize>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<Symbol>,
+    /// Ordered by span.
+    occurrences: Vec<Occurrence>,
+}
+
+impl Document {
+    /// Runs every pass up to semantic analysis over `text`, collecting errors instead of printing
+    /// them. Parsing recovers from errors, so items are known even in a document with syntax
+    /// errors, but names and types only once it parses.
+    pub fn new(name: String, text: String) -> Self {
+        let src = SourceFile::new(name, text);
+        let text = src.source.text();
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let mut diagnostics = vec![];
+
+        // Invalid tokens are reported and then left out, so they are not reported again as syntax
+        // errors. Literals that cannot be decoded still parse.
+        let tokens = lex(&src).collect::<Vec<_>>();
+        for (token, span) in &tokens {
+            diagnostics.extend(token_errors(text, token, *span));
+        }
+        let tokens = tokens
+            .into_iter()
+            .filter(|(tok, _)| *tok != Token::Invalid)
+            .collect::<Vec<_>>();
+        let idents = tokens
+            .iter()
+            .filter(|(tok, _)| matches!(tok, Token::Identifier(_)))
+            .map(|(_, span)| *span)
+            .collect::<Vec<_>>();
+
+        let eoi = SimpleSpan::from(text.len()..text.len());
+        let stream = Stream::from_iter(tokens).map(eoi, |(t, s)| (t, s));
+        let (items, errs) = parser::parser().parse(stream).into_output_errors();
+        diagnostics.extend(errs.iter().map(parser::syntax_error));
+        let mut items = items.unwrap_or_default();
+
+        let (resolutions, types) = if diagnostics.is_empty() {
+            analyse(&mut items, &mut diagnostics)
+        } else {
+            (None, None)
+        };
+
+        let mut indexer = Indexer {
+            text,
+            idents,
+            resolutions: resolutions.as_ref(),
+            types: types.as_ref(),
+            fns: HashMap::new(),
+            names: HashMap::new(),
+            structs: HashMap::new(),
+            fields: HashMap::new(),
+            symbols: vec![],
+            occurrences: vec![],
+        };
+        indexer.index(&items);
+        let (symbols, mut occurrences) = (indexer.symbols, indexer.occurrences);
+        occurrences.sort_by_key(|occurrence| occurrence.span.start);
+        Self {
+            src,
+            lines,
+            diagnostics,
+            symbols,
+            occurrences,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let text = &self.src.source.text()[self.lines[line]..offset];
+        Position::new(line as u32, text.encode_utf16().count() as u32)
+    }
+
+    pub fn range(&self, span: SimpleSpan) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    /// The offset of `position`, which counts characters in UTF-16 code units. Positions past the
+    /// end of a line are clamped to it.
+    pub fn offset(&self, position: Position) -> usize {
+        let text = self.src.source.text();
+        let Some(&start) = self.lines.get(position.line as usize) else {
+            return text.len();
+        };
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+
+    /// The span of the declaration of the name at `offset`.
+    pub fn definition(&self, offset: usize) -> Option<SimpleSpan> {
+        self.occurrence(offset)?.definition
+    }
+
+    /// The span of the name at `offset`, and a description of what it refers to.
+    pub fn hover(&self, offset: usize) -> Option<(SimpleSpan, &str)> {
+        let occurrence = self.occurrence(offset)?;
+        Some((occurrence.span, occurrence.hover.as_deref()?))
+    }
+
+    /// The name at `offset`, or ending at `offset` if there is none, as when the cursor is just
+    /// after a name.
+    fn occurrence(&self, offset: usize) -> Option<&Occurrence> {
+        let at = |occurrence: &&Occurrence| {
+            occurrence.span.start <= offset && offset < occurrence.span.end
+        };
+        let ending = |occurrence: &&Occurrence| occurrence.span.end == offset;
+        self.occurrences
+            .iter()
+            .find(at)
+            .or_else(|| self.occurrences.iter().find(ending))
+    }
+}
+
+/// Runs the sub-passes of [`SemanticAnalysis`] in turn, stopping after the first to find errors,
+/// but keeping whatever tables were built.
+fn analyse(
+    items: &mut [Item],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (Option<Resolutions>, Option<TypeInfo>) {
+    let mut report = |errs: Vec<SemError>| {
+        let failed = !errs.is_empty();
+        diagnostics.extend(errs.into_iter().map(Diagnostic::from));
+        failed
+    };
+    let (resolutions, errs) = Resolver::resolve(items);
+    if report(errs) {
+        return (Some(resolutions), None);
+    }
+    let (_, errs) = ConstEval::evaluate(items, &resolutions);
+    if report(errs) {
+        return (Some(resolutions), None);
+    }
+    let (inferred, errs) = Inference::infer(items, &resolutions);
+    if report(errs) {
+        return (Some(resolutions), None);
+    }
+    let (types, errs) = TypeChecker::check(items, &resolutions, &inferred);
+    report(errs);
+    (Some(resolutions), Some(types))
+}
+
+/// Prints a node as it would be formatted.
+fn written<W: Writable>(node: &W) -> String {
+    let mut out = vec![];
+    node.write(&mut Writer::new(&mut out), false)
+        .expect("writing to memory");
+    String::from_utf8(out).expect("source code is UTF-8")
+}
+
+/// Walks the AST, finding the span of every name in it.
+struct Indexer<'a> {
+    text: &'a str,
+    /// Spans of every identifier token, in order.
+    idents: Vec<SimpleSpan>,
+    resolutions: Option<&'a Resolutions>,
+    types: Option<&'a TypeInfo>,
+    fns: HashMap<NodeId, &'a FnSig>,
+    /// Spans of the names of the declarations seen so far.
+    names: HashMap<Binding, SimpleSpan>,
+    /// Spans of the names of structs, by [`Item`].
+    structs: HashMap<NodeId, SimpleSpan>,
+    /// Span of the name and type of each field, by struct [`Item`] and field name.
+    fields: HashMap<(NodeId, Ident), (SimpleSpan, Ty)>,
+    symbols: Vec<Symbol>,
+    occurrences: Vec<Occurrence>,
+}
+
+impl<'a> Indexer<'a> {
+    fn index(&mut self, items: &'a [Item]) {
+        // Every global is visible throughout the file, so they are all declared before any body
+        // is walked.
+        for item in items {
+            self.declare(item);
+        }
+        // Like name resolution, binds every declaration of a function to its definition, or to
+        // its first declaration if there is none.
+        let mut bindings = HashMap::new();
+        for item in items {
+            match &item.kind {
+                ItemKind::FnDefn(defn) => {
+                    bindings.insert(defn.sig.name, item.id);
+                }
+                ItemKind::FnDecl(decl) => {
+                    bindings.entry(decl.sig.name).or_insert(item.id);
+                }
+                ItemKind::Invalid
+                | ItemKind::Static(_)
+                | ItemKind::Const(_)
+                | ItemKind::StructDecl(_) => (),
+            }
+        }
+        for item in items {
+            if let Some(&name) = self.names.get(&Binding::Fn(item.id)) {
+                let sig = self.fns[&item.id];
+                let definition = self.names.get(&Binding::Fn(bindings[&sig.name])).copied();
+                self.occur(name, definition, Some(written(sig)));
+            }
+        }
+        for item in items {
+            match &item.kind {
+                ItemKind::FnDefn(defn) => self.stmt(&defn.block),
+                ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                    if let Some(value) = &decl.value {
+                        self.expr(value);
+                    }
+                }
+                ItemKind::Invalid | ItemKind::StructDecl(_) | ItemKind::FnDecl(_) => (),
+            }
+        }
+    }
+
+    /// The first identifier `name` within `span`.
+    fn name(&self, span: SimpleSpan, name: Ident) -> Option<SimpleSpan> {
+        self.idents.iter().copied().find(|ident| {
+            span.start <= ident.start
+                && ident.end <= span.end
+                && self.text[ident.into_range()] == **name.name
+        })
+    }
+
+    fn declare(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Invalid => (),
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                let Some(name) = self.name(item.span, decl.ident) else {
+                    return;
+                };
+                let (keyword, kind) = match item.kind {
+                    ItemKind::Const(_) => ("const", SymbolKind::CONSTANT),
+                    _ => ("static", SymbolKind::VARIABLE),
+                };
+                let ty = self.decl_ty(item.id, &decl.ty);
+                let detail = format!("{keyword} {}: {}", decl.ident, written(&ty));
+                self.names.insert(Binding::Static(item.id), name);
+                self.occur(name, Some(name), Some(detail.clone()));
+                if !matches!(*decl.ty.kind, TyKind::Infer) {
+                    self.ty(&decl.ty);
+                }
+                self.symbol(kind, decl.ident, detail, item.span, name);
+            }
+            ItemKind::StructDecl(decl) => {
+                let Some(name) = self.name(item.span, decl.name) else {
+                    return;
+                };
+                let detail = format!("struct {}", decl.name);
+                self.structs.insert(item.id, name);
+                self.occur(name, Some(name), Some(detail.clone()));
+                self.symbol(SymbolKind::STRUCT, decl.name, detail, item.span, name);
+                let mut start = name.end;
+                for field in &decl.fields {
+                    let before = SimpleSpan::from(start..field.ty.span.start);
+                    let Some(field_name) = self.name(before, field.name) else {
+                        continue;
+                    };
+                    let detail = format!("{}: {}", field.name, written(&field.ty));
+                    self.fields
+                        .insert((item.id, field.name), (field_name, field.ty.clone()));
+                    self.occur(field_name, Some(field_name), Some(detail.clone()));
+                    self.ty(&field.ty);
+                    let span = SimpleSpan::from(field_name.start..field.ty.span.end);
+                    let symbol =
+                        Symbol::new(SymbolKind::FIELD, field.name, detail, span, field_name);
+                    self.symbols.last_mut().unwrap().children.push(symbol);
+                    start = field.ty.span.end;
+                }
+            }
+            ItemKind::FnDecl(decl) => self.declare_fn(item, &decl.sig),
+            ItemKind::FnDefn(defn) => self.declare_fn(item, &defn.sig),
+        }
+    }
+
+    fn declare_fn(&mut self, item: &'a Item, sig: &'a FnSig) {
+        let Some(name) = self.name(item.span, sig.name) else {
+            return;
+        };
+        self.fns.insert(item.id, sig);
+        self.names.insert(Binding::Fn(item.id), name);
+        let detail = written(sig);
+        self.symbol(SymbolKind::FUNCTION, sig.name, detail, item.span, name);
+        for (index, param) in sig.params.iter().enumerate() {
+            let Some(param_name) = self.name(param.span, param.name) else {
+                continue;
+            };
+            let detail = format!("{}: {}", param.name, written(&param.ty));
+            let binding = Binding::Param {
+                item: item.id,
+                index,
+            };
+            self.names.insert(binding, param_name);
+            self.occur(param_name, Some(param_name), Some(detail));
+            self.ty(&param.ty);
+        }
+        self.ty(&sig.ty);
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Invalid | StmtKind::Continue | StmtKind::Break => (),
+            StmtKind::Block(block) => block.stmts.iter().for_each(|stmt| self.stmt(stmt)),
+            StmtKind::Local(local) => {
+                // The initializer cannot refer to the local it initializes.
+                if let Some(value) = &local.value {
+                    self.expr(value);
+                }
+                if !matches!(*local.ty.kind, TyKind::Infer) {
+                    self.ty(&local.ty);
+                }
+                let Some(name) = self.name(stmt.span, local.name) else {
+                    return;
+                };
+                let ty = self.decl_ty(stmt.id, &local.ty);
+                let keyword = if local.mutable { "let mut" } else { "let" };
+                let detail = format!("{keyword} {}: {}", local.name, written(&ty));
+                self.names.insert(Binding::Local(stmt.id), name);
+                self.occur(name, Some(name), Some(detail));
+            }
+            StmtKind::For(pattern, body) => {
+                self.pattern(pattern);
+                self.stmt(body);
+            }
+            StmtKind::While(cond, body) => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.expr(cond);
+                self.stmt(then);
+                if let Some(els) = els {
+                    self.stmt(els);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.expr(expr),
+            StmtKind::Return(None) => (),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &'a Pattern) {
+        let PatternKind::RangePattern(id, range) = &pattern.kind;
+        let (PatternRange::Exclusive(start, end)
+        | PatternRange::Inclusive(start, end)
+        | PatternRange::ExclusiveInclusive(start, end)
+        | PatternRange::InclusiveExclusive(start, end)) = range;
+        self.expr(start);
+        self.expr(end);
+        if let Some(name) = self.name(pattern.span, *id) {
+            let ty = self.decl_ty(pattern.id, &TyKind::Infer.into());
+            self.names.insert(Binding::Pattern(pattern.id), name);
+            self.occur(name, Some(name), Some(format!("{id}: {}", written(&ty))));
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Invalid | ExprKind::Literal(_) => (),
+            ExprKind::Ident(id) => {
+                let binding = self
+                    .resolutions
+                    .and_then(|resolutions| resolutions.values.get(&expr.id));
+                let definition = binding.and_then(|binding| self.names.get(binding)).copied();
+                let hover = match binding {
+                    Some(Binding::Fn(item)) => self.fns.get(item).map(|sig| written(*sig)),
+                    _ => self
+                        .expr_ty(expr)
+                        .map(|ty| format!("{id}: {}", written(&ty))),
+                };
+                self.occur(expr.span, definition, hover);
+            }
+            ExprKind::Assign(lhs, rhs)
+            | ExprKind::CompoundAssign(lhs, _, rhs)
+            | ExprKind::BinOp(lhs, _, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::CallFn(call) => {
+                self.expr(&call.name);
+                call.args.iter().for_each(|arg| self.expr(arg));
+            }
+            ExprKind::Typecast(ty, expr) => {
+                self.ty(ty);
+                self.expr(expr);
+            }
+            ExprKind::Deref(expr)
+            | ExprKind::Ref(expr)
+            | ExprKind::Unary(_, expr)
+            | ExprKind::ArrayRepeat(expr, _) => self.expr(expr),
+            ExprKind::StructLit(ty, fields) => {
+                self.ty(ty);
+                let decl = self
+                    .resolutions
+                    .and_then(|resolutions| resolutions.structs.get(&ty.id));
+                for field in fields {
+                    let field_decl = decl.and_then(|decl| self.fields.get(&(*decl, field.name)));
+                    let definition = field_decl.map(|(span, _)| *span);
+                    let hover =
+                        field_decl.map(|(_, ty)| format!("{}: {}", field.name, written(ty)));
+                    self.occur(field.span, definition, hover);
+                    self.expr(&field.value);
+                }
+            }
+            ExprKind::ArrayLit(elems) => elems.iter().for_each(|elem| self.expr(elem)),
+            ExprKind::Index(arr, ind) => {
+                self.expr(arr);
+                self.expr(ind);
+            }
+            ExprKind::FieldAccess(base, field) => {
+                self.expr(base);
+                // The field name is the last identifier of the expression.
+                let Some(&name) = self
+                    .idents
+                    .iter()
+                    .rfind(|ident| base.span.end <= ident.start && ident.end <= expr.span.end)
+                else {
+                    return;
+                };
+                let decl = self
+                    .expr_ty(base)
+                    .and_then(|ty| self.resolutions?.struct_decls.get(&ty.field_owner()?));
+                let field_decl = decl.and_then(|decl| self.fields.get(&(*decl, *field)));
+                let definition = field_decl.map(|(span, _)| *span);
+                let hover = field_decl.map(|(_, ty)| format!("{field}: {}", written(ty)));
+                self.occur(name, definition, hover);
+            }
+        }
+    }
+
+    /// Records the struct named by `ty`, behind any pointers and arrays.
+    fn ty(&mut self, ty: &Ty) {
+        let Some(item) = self
+            .resolutions
+            .and_then(|resolutions| resolutions.structs.get(&ty.id))
+        else {
+            return;
+        };
+        let mut inner = ty;
+        while let TyKind::Pointer(next) | TyKind::Array(_, next) = &*inner.kind {
+            inner = next;
+        }
+        if let TyKind::Struct(name) = *inner.kind
+            && let Some(span) = self.name(ty.span, name)
+        {
+            let definition = self.structs.get(item).copied();
+            self.occur(span, definition, Some(format!("struct {name}")));
+        }
+    }
+
+    /// The type of the variable declared by the node `id`, once inferred.
+    fn decl_ty(&self, id: NodeId, declared: &Ty) -> Ty {
+        self.types
+            .and_then(|types| types.decls.get(&id))
+            .unwrap_or(declared)
+            .clone()
+    }
+
+    fn expr_ty(&self, expr: &Expr) -> Option<Ty> {
+        self.types?.exprs.get(&expr.id).cloned()
+    }
+
+    fn occur(&mut self, span: SimpleSpan, definition: Option<SimpleSpan>, hover: Option<String>) {
+        self.occurrences.push(Occurrence {
+            span,
+            definition,
+            hover,
+        });
+    }
+
+    fn symbol(
+        &mut self,
+        kind: SymbolKind,
+        name: Ident,
+        detail: String,
+        span: SimpleSpan,
+        name_span: SimpleSpan,
+    ) {
+        self.symbols
+            .push(Symbol::new(kind, name, detail, span, name_span));
+    }
+}
+
+impl Symbol {
+    fn new(
+        kind: SymbolKind,
+        name: Ident,
+        detail: String,
+        span: SimpleSpan,
+        name_span: SimpleSpan,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            detail,
+            span,
+            name_span,
+            children: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::Document;
+
+    /// Analyses `src`, with the cursor at the `$` it contains.
+    fn document(src: &str) -> (Document, usize) {
+        let offset = src.find('$').unwrap();
+        let doc = Document::new("test.akn".to_string(), src.replace('$', ""));
+        (doc, offset)
+    }
+
+    #[rstest]
+    #[case::static_use("static s: int; f(): int { $s }", Some("s: int"), Some(7))]
+    #[case::fn_use(
+        "f(x: int): int { x } g(): int { $f(1) }",
+        Some("f(x: int): int"),
+        Some(0)
+    )]
+    #[case::param("f(x: int): int { x$ }", Some("x: int"), Some(2))]
+    #[case::inferred_local("f(): int { let y := 'a'; $y; 0 }", Some("y: char"), Some(15))]
+    #[case::shadowed(
+        "f(): int { let y := 1; { let y := 2; $y } }",
+        Some("y: int"),
+        Some(29)
+    )]
+    #[case::field(
+        "struct p { x: int; } f(q: struct p): int { q.$x }",
+        Some("x: int"),
+        Some(11)
+    )]
+    #[case::pointer_field(
+        "struct p { x: int; } f(q: &struct p): int { q.$x }",
+        Some("x: int"),
+        Some(11)
+    )]
+    #[case::literal_field(
+        "struct p { x: int; } f(): struct p { struct p { $x: 1 } }",
+        Some("x: int"),
+        Some(11)
+    )]
+    #[case::struct_ty("struct p { x: int; } static q: struct $p;", Some("struct p"), Some(7))]
+    #[case::keyword("f(): int { $return 0; }", None, None)]
+    #[case::undeclared("f(): int { $z }", None, None)]
+    fn test_lookup(
+        #[case] src: &str,
+        #[case] hover: Option<&str>,
+        #[case] definition: Option<usize>,
+    ) {
+        let (doc, offset) = document(src);
+        assert_eq!(hover, doc.hover(offset).map(|(_, text)| text));
+        assert_eq!(definition, doc.definition(offset).map(|span| span.start));
+    }
+
+    #[rstest]
+    #[case::valid("f(): int { 0 }", vec![])]
+    #[case::lexer("f(): int { 0? }", vec!["E0001"])]
+    #[case::escape(r"f(): char { '\q' }", vec!["E0005"])]
+    #[case::parser("f(): int { 0 } g(: int {}", vec!["E0100"])]
+    #[case::sem("f(): int { a + b }", vec!["E0208", "E0208"])]
+    fn test_diagnostics(#[case] src: &str, #[case] codes: Vec<&str>) {
+        let doc = Document::new("test.akn".to_string(), src.to_string());
+        let found = doc.diagnostics.iter().map(|diagnostic| diagnostic.code);
+        assert_eq!(codes, found.collect::<Vec<_>>());
+    }
+
+    #[rstest]
+    #[case::ascii("ab\ncd", 4, (1, 1))]
+    #[case::utf16("/* é𝄞 */x", 10, (0, 7))]
+    fn test_position(#[case] src: &str, #[case] offset: usize, #[case] position: (u32, u32)) {
+        let doc = Document::new("test.akn".to_string(), src.to_string());
+        let found = doc.position(offset);
+        assert_eq!(position, (found.line, found.character));
+        assert_eq!(offset, doc.offset(found));
+    }
+}
@@ -1,68 +1,261 @@
 #![doc = include_str!("../README.md")]
-use std::path::PathBuf;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use akynoc::{
-    lexer::{self, lex, SourceFile},
-    parser::{self, parser},
+    codegen::{CodeGen, Emit, Target},
+    diagnostic::{self, codes, ErrorFormat},
+    format::format,
+    interp::Interpreter,
+    lexer::{LexerPass, SourceFile},
+    lsp,
+    parser::ParserPass,
+    repl,
+    sem::SemanticAnalysis,
+    util::CompilerPass,
 };
-use anyhow::Result;
 use ariadne::FileCache;
-use clap::{builder::PossibleValue, Parser, ValueEnum};
+use clap::{builder::PossibleValue, Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Clone, Copy)]
 enum Pass {
     All,
     Lexer,
     Parser,
+    Semantic,
+    Interpret,
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// The source .nky file to process
-    file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The source .akn file to process
+    #[arg(required_unless_present = "explain")]
+    file: Option<PathBuf>,
 
     /// Select a specific compiler pass to test
     #[arg(value_enum, short, long, default_value_t = Pass::All)]
     pass: Pass,
+
+    /// Compile to assembly, or to an executable linked by `cc`
+    #[arg(value_enum, long, default_value_t = Emit::Asm)]
+    emit: Emit,
+
+    /// Architecture to compile for
+    #[arg(value_enum, long, default_value_t = Target::X86_64)]
+    target: Target,
+
+    /// Output path of the compiled code [default: FILE's name, with .s for asm]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// How to print errors
+    #[arg(value_enum, long, global = true, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+
+    /// Explain an error code, such as E0208, instead of processing a file
+    #[arg(long, value_name = "CODE", exclusive = true)]
+    explain: Option<String>,
 }
 
-pub fn main() -> Result<()> {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Format a source file, printing the result unless --check or --in-place is given
+    Fmt(FmtArgs),
+    /// Run a language server, speaking the Language Server Protocol over stdio
+    Lsp,
+    /// Evaluate items, statements and expressions interactively
+    Repl,
+}
+
+#[derive(clap::Args, Debug)]
+struct FmtArgs {
+    /// The source .akn file to format
+    file: PathBuf,
+
+    /// Exit with status 1 if FILE is not formatted, without writing anything
+    #[arg(long, conflicts_with = "in_place")]
+    check: bool,
+
+    /// Overwrite FILE with its formatted source
+    #[arg(short, long)]
+    in_place: bool,
+}
+
+pub fn main() -> ExitCode {
     let args = Args::parse();
-    let cache = FileCache::default();
-    let src = SourceFile::from_path(args.file.as_path(), cache);
-
-    match args.pass {
-        Pass::All => unimplemented!(),
-        Pass::Lexer => {
-            let token_iter = lex(&src);
-            lexer::print_errors(&src, token_iter);
+    let result = match &args.command {
+        Some(Command::Fmt(fmt_args)) => fmt(fmt_args, args.error_format),
+        Some(Command::Lsp) => lsp::run().map(|()| 0).map_err(|err| {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }),
+        Some(Command::Repl) => repl::run(args.error_format).map(|()| 0).map_err(|err| {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }),
+        None if args.explain.is_some() => explain(args.explain.as_deref().unwrap_or_default()),
+        None => {
+            let file = args
+                .file
+                .as_deref()
+                .expect("FILE is required without a command");
+            let src = SourceFile::from_path(file, FileCache::default());
+            run(&src, file, &args)
+        }
+    };
+    match result {
+        Ok(status) => ExitCode::from(status),
+        Err(code) => code,
+    }
+}
+
+/// Runs every stage of the pipeline up to and including `last`, stopping at the first stage that
+/// reports an error. On success, returns the exit status of the interpreted program, or `0` if
+/// nothing was run.
+fn run(src: &SourceFile, file: &Path, args: &Args) -> Result<u8, ExitCode> {
+    let last = args.pass;
+    let format = args.error_format;
+    let mut lexer = LexerPass::new(src);
+    let tokens = lexer.lex();
+    check(src, &lexer, format)?;
+    if let Pass::Lexer = last {
+        return Ok(0);
+    }
+
+    let mut parser = ParserPass::new(src);
+    let ast = parser.parse(tokens);
+    check(src, &parser, format)?;
+    if let Pass::Parser = last {
+        return Ok(0);
+    }
+
+    let mut items = ast.unwrap_or_default();
+    let mut sem = SemanticAnalysis::new();
+    let analysis = sem.analyse(&mut items);
+    check(src, &sem, format)?;
+    let analysis = analysis.expect("analysis succeeded");
+    if let Pass::Semantic = last {
+        return Ok(0);
+    }
+
+    if let Pass::Interpret = last {
+        let mut interp = Interpreter::new();
+        let status = interp.run(&items, &analysis);
+        check(src, &interp, format)?;
+        // Like a process exit status, only the low byte is kept.
+        return Ok(status.unwrap_or_default() as u8);
+    }
+
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(file, args.emit));
+    let mut codegen = CodeGen::new();
+    codegen.emit(&items, &analysis, args.target, args.emit, &output);
+    check(src, &codegen, format)?;
+    Ok(0)
+}
+
+/// Formats a source file, which must lex and parse without errors. Returns `1` if `--check` finds
+/// the file is not formatted.
+fn fmt(args: &FmtArgs, error_format: ErrorFormat) -> Result<u8, ExitCode> {
+    let src = SourceFile::from_path(&args.file, FileCache::default());
+    let mut lexer = LexerPass::new(&src);
+    let tokens = lexer.lex();
+    check(&src, &lexer, error_format)?;
+    let mut parser = ParserPass::new(&src);
+    let items = parser.parse(tokens);
+    check(&src, &parser, error_format)?;
+
+    let formatted = format(&src, &items.unwrap_or_default()).expect("formatting to memory");
+    let unchanged = formatted == src.source.text();
+    if args.check {
+        if !unchanged {
+            eprintln!("{} is not formatted", args.file.display());
+            return Ok(1);
         }
-        Pass::Parser => {
-            use chumsky::Parser;
-            let token_stream = parser::token_stream(&src);
-            let parser = parser();
-            match parser.parse(token_stream).into_result() {
-                Ok(_) => (),
-                Err(errs) => {
-                    parser::print_errors(&src.source, errs);
-                    panic!("Parsing failed");
-                }
-            }
+    } else if args.in_place {
+        if !unchanged && let Err(err) = fs::write(&args.file, formatted) {
+            eprintln!("Failed to write {}: {err}", args.file.display());
+            return Err(ExitCode::FAILURE);
         }
+    } else {
+        print!("{formatted}");
+    }
+    Ok(0)
+}
+
+/// Prints the explanation of an error code.
+fn explain(code: &str) -> Result<u8, ExitCode> {
+    match codes::explain(code) {
+        Some(explanation) => {
+            print!("{explanation}");
+            Ok(0)
+        }
+        None => {
+            eprintln!("`{code}` is not an error code");
+            Err(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Names the output after the input file, in the current directory.
+fn default_output(file: &Path, emit: Emit) -> PathBuf {
+    let stem = PathBuf::from(file.file_stem().unwrap_or_default());
+    match emit {
+        Emit::Asm => stem.with_extension("s"),
+        Emit::Exe => stem,
+    }
+}
+
+/// Emits the diagnostics of a finished pass, and maps it to its exit code if it failed.
+fn check<P: CompilerPass>(src: &SourceFile, pass: &P, format: ErrorFormat) -> Result<(), ExitCode> {
+    let diagnostics = pass.diagnostics();
+    diagnostic::emit(src, diagnostics, format);
+    if format == ErrorFormat::Human
+        && let Some(error) = diagnostics.iter().find(|diagnostic| diagnostic.is_error())
+    {
+        eprintln!(
+            "For more information about an error, try `akynoc --explain {}`.",
+            error.code
+        );
+    }
+    if pass.has_error() {
+        Err(ExitCode::from(P::FAIL_CODE))
+    } else {
+        Ok(())
     }
-    Ok(())
 }
 
 impl ValueEnum for Pass {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Pass::Lexer, Pass::Parser, Pass::All]
+        &[
+            Pass::Lexer,
+            Pass::Parser,
+            Pass::Semantic,
+            Pass::Interpret,
+            Pass::All,
+        ]
     }
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
         let s = match self {
             Self::All => "all",
             Self::Lexer => "lexer",
             Self::Parser => "parser",
+            Self::Semantic => "sem",
+            Self::Interpret => "interpret",
         };
         Some(PossibleValue::new(s))
     }
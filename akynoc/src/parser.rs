@@ -1,6 +1,7 @@
 //! Parses [`Tokens`](Token) into [`Items`](Item).
 //!
 use chumsky::{
+    error::RichPattern,
     extra::Err,
     input::{Stream, ValueInput},
     pratt::*,
@@ -9,15 +10,17 @@ use chumsky::{
 
 use crate::{
     ast::{
-        exprs::{Expr, ExprKind, Literal, Operator},
+        exprs::{Expr, ExprKind, FieldInit, Literal, Operator, Value},
         functions::{FnDecl, FnDefn, FnSig, Param},
         pattern::Range,
         statements::{Stmt, StmtKind},
         structs::{Field, StructDecl},
-        types::{Ident, Primitive, Ty, TyKind},
+        types::{Ident, Length, Primitive, Ty, TyKind},
         Item, ItemKind,
     },
-    lexer::{lex, SourceFile, Token},
+    diagnostic::{self, Diagnostic, ErrorFormat},
+    lexer::{lex, parse_int, unescape, unescape_char, SourceFile, Token},
+    util::CompilerPass,
 };
 
 type Extras<'tok, 'src> = Err<Rich<'tok, Token<'src>>>;
@@ -30,24 +33,83 @@ where
     item().boxed().repeated().collect::<Vec<_>>()
 }
 
-pub fn print_errors<'tok, 'src: 'tok>(
-    source: &ariadne::Source,
-    errs: Vec<Rich<'tok, Token<'src>>>,
-) {
-    for err in errs {
-        let reason = err.reason().clone().map_token(|t| t.to_string());
-        ariadne::Report::build(ariadne::ReportKind::Error, ((), err.span().into_range()))
-            .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
-            .with_code(245)
-            .with_message(err.to_string())
-            .with_label(
-                ariadne::Label::new(((), err.span().into_range()))
-                    .with_message(reason.to_string())
-                    .with_color(ariadne::Color::Red),
-            )
-            .finish()
-            .eprint(source)
-            .unwrap();
+/// Describes a syntax error, labelling the types and statics it was found within. A static that
+/// could have ended where the error is, without a missing type, is taken to be missing its `;`.
+pub fn syntax_error(err: &Rich<'_, Token<'_>>) -> Diagnostic {
+    let reason = err
+        .reason()
+        .clone()
+        .map_token(|t| t.to_string())
+        .to_string();
+    let is_label =
+        |pattern: &RichPattern<_>, label| matches!(pattern, RichPattern::Label(l) if l == label);
+    let in_context = |label| err.contexts().any(|(pattern, _)| is_label(pattern, label));
+    let expected = err.expected().collect::<Vec<_>>();
+    let expects = |token| {
+        expected
+            .iter()
+            .any(|pattern| matches!(pattern, RichPattern::Token(t) if **t == token))
+    };
+    let code = if err.found().is_none() {
+        "E0101"
+    } else if in_context("type") || matches!(expected[..], [pattern] if is_label(pattern, "type")) {
+        "E0102"
+    } else if in_context("static") && expects(Token::Semi) && !expects(Token::Colon) {
+        "E0103"
+    } else {
+        "E0100"
+    };
+    err.contexts().fold(
+        Diagnostic::error(code, reason.clone()).with_primary(*err.span(), reason),
+        |diagnostic, (label, span)| {
+            diagnostic.with_label(*span, format!("while parsing this {label}"))
+        },
+    )
+}
+
+/// Writes syntax errors in `src` to stderr, in the human format.
+pub fn print_errors<'tok, 'src: 'tok>(src: &SourceFile, errs: Vec<Rich<'tok, Token<'src>>>) {
+    let diagnostics = errs.iter().map(syntax_error).collect::<Vec<_>>();
+    diagnostic::emit(src, &diagnostics, ErrorFormat::Human);
+}
+
+/// The parsing stage of the pipeline.
+pub struct ParserPass<'src> {
+    src: &'src SourceFile,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'src> ParserPass<'src> {
+    pub fn new(src: &'src SourceFile) -> Self {
+        Self {
+            src,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Parses the tokens produced by a [`LexerPass`](crate::lexer::LexerPass), recovering from
+    /// syntax errors so that every independent error is reported. Returns [`None`] if any errors
+    /// were reported.
+    pub fn parse(&mut self, tokens: Vec<(Token<'src>, SimpleSpan)>) -> Option<Vec<Item>> {
+        let eoi = (0..self.src.source.len()).into();
+        let token_stream = Stream::from_iter(tokens).map(eoi, |(t, s)| (t, s));
+        let (items, errs) = parser().parse(token_stream).into_output_errors();
+        for err in &errs {
+            self.report(syntax_error(err));
+        }
+        items.filter(|_| !self.has_error())
+    }
+}
+
+impl CompilerPass for ParserPass<'_> {
+    const FAIL_CODE: u8 = 245;
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
     }
 }
 
@@ -72,39 +134,134 @@ where
     I: ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>>,
 {
     select! {
-        Token::IntLiteral(i) => i.parse::<u32>().unwrap().into(),Token::CharLiteral(c) => any::<&str,Err<chumsky::error::EmptyErr>>().parse(c).into_result().unwrap().into(),Token::StrLiteral(s) => s.into()
+        // The lexer reports literals that cannot be decoded.
+        Token::IntLiteral(i) => parse_int(i, 0).map_or(Value::Invalid, Value::from).into(),
+        Token::CharLiteral(c) => unescape_char(c, 0).map_or(Value::Invalid, Value::from).into(),
+        Token::StrLiteral(s) => unescape(s, 0).map_or(Value::Invalid, Value::from).into(),
+        Token::True => true.into(),
+        Token::False => false.into(),
     }
 }
 
-fn expr<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, Expr, Extras<'tok, 'src>>
+/// Returns a parser for the length of an array type or array literal: a number, or the name of a
+/// static.
+fn length<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, Length, Extras<'tok, 'src>> + Clone
+where
+    I: ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>>,
+{
+    select! {
+        // The lexer reports lengths out of range.
+        Token::IntLiteral(i) => Length::Known(parse_int(i, 0).unwrap_or_default() as usize),
+        Token::Identifier(s) => Length::Named(s.into()),
+    }
+}
+
+/// Returns a parser for a single [`Expr`], as used within statements.
+pub fn expr<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, Expr, Extras<'tok, 'src>>
 where
     I: ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>>,
 {
     recursive(|expr| {
+        let field_init = ident()
+            .boxed()
+            .map_with(|name, e| (name, e.span()))
+            .then_ignore(just(Token::Colon))
+            .then(expr.clone())
+            .map(|((name, span), value)| FieldInit { name, span, value });
+        let struct_lit = just(Token::Struct)
+            .ignore_then(ident().boxed())
+            .map_with(|name, e| Ty::from((TyKind::Struct(name), e.span())))
+            .then(
+                field_init
+                    .separated_by(just(Token::Comma))
+                    .allow_trailing()
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            )
+            .map(|(ty, fields)| ExprKind::StructLit(ty, fields));
+        let array_lit = choice((
+            expr.clone()
+                .then_ignore(just(Token::Semi))
+                .then(length())
+                .map(|(elem, len)| ExprKind::ArrayRepeat(Box::new(elem), len)),
+            expr.clone()
+                .separated_by(just(Token::Comma))
+                .at_least(1)
+                .allow_trailing()
+                .collect::<Vec<_>>()
+                .map(ExprKind::ArrayLit),
+        ))
+        .delimited_by(just(Token::LBrack), just(Token::RBrack));
         let atom = choice((
-            literal().boxed().map(|l| l.into()),
-            ident().boxed().map(|id| id.into()),
+            literal().boxed().map_with(|l, e| (l, e.span()).into()),
+            ident().boxed().map_with(|id, e| (id, e.span()).into()),
+            struct_lit
+                .or(array_lit)
+                .map_with(|kind, e| (kind, e.span()).into()),
         ));
         let atom = choice((
             atom,
             expr.clone()
-                .delimited_by(just(Token::LPar), just(Token::RPar)),
+                .delimited_by(just(Token::LPar), just(Token::RPar))
+                .recover_with(via_parser(nested_delimiters(
+                    Token::LPar,
+                    Token::RPar,
+                    [
+                        (Token::LBrack, Token::RBrack),
+                        (Token::LBrace, Token::RBrace),
+                    ],
+                    |span| (ExprKind::Invalid, span).into(),
+                ))),
         ));
         atom.pratt((
-            infix(right(1), just(Token::Assign), |lhs, _, rhs, _| {
-                ExprKind::Assign(Box::new(lhs), Box::new(rhs)).into()
+            infix(right(1), just(Token::Assign), |lhs, _, rhs, e| {
+                (ExprKind::Assign(Box::new(lhs), Box::new(rhs)), e.span()).into()
             }),
-            infix(left(3), just(Token::LogOr), |lhs, _, rhs, _| {
-                ExprKind::BinOp(Box::new(lhs), Operator::Or, Box::new(rhs)).into()
+            infix(
+                right(1),
+                choice((
+                    just(Token::AddAssign),
+                    just(Token::SubAssign),
+                    just(Token::MulAssign),
+                    just(Token::DivAssign),
+                    just(Token::RemAssign),
+                    just(Token::AndAssign),
+                    just(Token::OrAssign),
+                    just(Token::XorAssign),
+                    just(Token::ShlAssign),
+                    just(Token::ShrAssign),
+                )),
+                |lhs, op: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::CompoundAssign(Box::new(lhs), op.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
+                },
+            ),
+            infix(left(3), just(Token::LogOr), |lhs, _, rhs, e| {
+                (
+                    ExprKind::BinOp(Box::new(lhs), Operator::Or, Box::new(rhs)),
+                    e.span(),
+                )
+                    .into()
             }),
-            infix(left(5), just(Token::LogAnd), |lhs, _, rhs, _| {
-                ExprKind::BinOp(Box::new(lhs), Operator::And, Box::new(rhs)).into()
+            infix(left(5), just(Token::LogAnd), |lhs, _, rhs, e| {
+                (
+                    ExprKind::BinOp(Box::new(lhs), Operator::And, Box::new(rhs)),
+                    e.span(),
+                )
+                    .into()
             }),
             infix(
                 left(7),
                 just(Token::Eq).or(just(Token::Ne)),
-                |lhs, op_token: Token<'_>, rhs, _| {
-                    ExprKind::BinOp(Box::new(lhs), op_token.into(), Box::new(rhs)).into()
+                |lhs, op_token: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::BinOp(Box::new(lhs), op_token.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
                 },
             ),
             infix(
@@ -115,67 +272,112 @@ where
                     just(Token::Le),
                     just(Token::Ge),
                 )),
-                |lhs, op: Token<'_>, rhs, _| {
-                    ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)).into()
+                |lhs, op: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
                 },
             ),
+            infix(left(11), just(Token::Pipe), |lhs, _, rhs, e| {
+                (
+                    ExprKind::BinOp(Box::new(lhs), Operator::BitOr, Box::new(rhs)),
+                    e.span(),
+                )
+                    .into()
+            }),
+            infix(left(13), just(Token::Caret), |lhs, _, rhs, e| {
+                (
+                    ExprKind::BinOp(Box::new(lhs), Operator::BitXor, Box::new(rhs)),
+                    e.span(),
+                )
+                    .into()
+            }),
+            // Only an operand can precede an infix `&`, so it never competes with a prefix `&`.
+            infix(left(15), just(Token::And), |lhs, _, rhs, e| {
+                (
+                    ExprKind::BinOp(Box::new(lhs), Operator::BitAnd, Box::new(rhs)),
+                    e.span(),
+                )
+                    .into()
+            }),
             infix(
-                left(11),
+                left(17),
+                just(Token::Shl).or(just(Token::Shr)),
+                |lhs, op: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
+                },
+            ),
+            infix(
+                left(19),
                 choice((just(Token::Plus), just(Token::Minus))),
-                |lhs, op: Token<'_>, rhs, _| {
-                    ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)).into()
+                |lhs, op: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
                 },
             ),
             infix(
-                left(13),
+                left(21),
                 choice((just(Token::Asterisk), just(Token::Div), just(Token::Rem))),
-                |lhs, op: Token<'_>, rhs, _| {
-                    ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)).into()
+                |lhs, op: Token<'_>, rhs, e| {
+                    (
+                        ExprKind::BinOp(Box::new(lhs), op.into(), Box::new(rhs)),
+                        e.span(),
+                    )
+                        .into()
                 },
             ),
             prefix(
-                15,
-                choice((just(Token::Plus), just(Token::Minus))),
-                |op: Token<'_>, rhs, _| {
-                    ExprKind::BinOp(
-                        Box::new(ExprKind::Literal(0.into()).into()),
-                        op.into(),
-                        Box::new(rhs),
-                    )
-                    .into()
+                23,
+                choice((
+                    just(Token::Plus),
+                    just(Token::Minus),
+                    just(Token::Bang),
+                    just(Token::Tilde),
+                )),
+                |op: Token<'_>, rhs, e| {
+                    (ExprKind::Unary(op.into(), Box::new(rhs)), e.span()).into()
                 },
             ),
-            prefix(15, just(Token::And), |_, rhs, _| {
-                ExprKind::Ref(Box::new(rhs)).into()
+            prefix(23, just(Token::And), |_, rhs, e| {
+                (ExprKind::Ref(Box::new(rhs)), e.span()).into()
             }),
-            prefix(15, just(Token::Asterisk), |_, rhs, _| {
-                ExprKind::Deref(Box::new(rhs)).into()
+            prefix(23, just(Token::Asterisk), |_, rhs, e| {
+                (ExprKind::Deref(Box::new(rhs)), e.span()).into()
             }),
             prefix(
-                15,
+                23,
                 typ()
                     .boxed()
                     .delimited_by(just(Token::LPar), just(Token::RPar)),
-                |cast_to: Ty, rhs, _| ExprKind::Typecast(cast_to, Box::new(rhs)).into(),
+                |cast_to: Ty, rhs, e| (ExprKind::Typecast(cast_to, Box::new(rhs)), e.span()).into(),
             ),
             postfix(
-                17,
+                25,
                 expr.clone()
                     .delimited_by(just(Token::LBrack), just(Token::RBrack)),
-                |arr, ind, _| ExprKind::Index(Box::new(arr), Box::new(ind)).into(),
+                |arr, ind, e| (ExprKind::Index(Box::new(arr), Box::new(ind)), e.span()).into(),
             ),
             postfix(
-                17,
+                25,
                 just(Token::Dot).ignore_then(ident().boxed()),
-                |str, field, _| ExprKind::FieldAccess(Box::new(str), field).into(),
+                |str, field, e| (ExprKind::FieldAccess(Box::new(str), field), e.span()).into(),
             ),
             postfix(
-                17,
+                25,
                 expr.clone()
                     .separated_by(just(Token::Comma))
                     .collect::<Vec<_>>()
                     .delimited_by(just(Token::LPar), just(Token::RPar)),
-                |fn_name, args, _| ExprKind::CallFn((fn_name, args).into()).into(),
+                |fn_name, args, e| (ExprKind::CallFn((fn_name, args).into()), e.span()).into(),
             ),
         ))
     })
@@ -191,13 +393,37 @@ where
             just(Token::Colon)
                 .ignore_then(typ().boxed())
                 .or_not()
-                .map(|ty| ty.unwrap_or(TyKind::Infer.into())),
+                .map_with(|ty, e| ty.unwrap_or((TyKind::Infer, e.span()).into())),
+        )
+        .then(just(Token::Assign).ignore_then(expr().boxed()).or_not())
+        .then_ignore(just(Token::Semi))
+        .map_with(|((ident, ty), value), e| {
+            (ItemKind::Static((ident, ty, value).into()), e.span()).into()
+        })
+        .labelled("static")
+        .as_context();
+    let const_var = just(Token::Const)
+        .ignore_then(ident().boxed())
+        .then(
+            just(Token::Colon)
+                .ignore_then(typ().boxed())
+                .or_not()
+                .map_with(|ty, e| ty.unwrap_or((TyKind::Infer, e.span()).into())),
         )
-        .then(just(Token::Assign).ignore_then(literal().boxed()).or_not())
+        .then_ignore(just(Token::Assign))
+        .then(expr().boxed())
         .then_ignore(just(Token::Semi))
-        .map(|((ident, ty), value)| ItemKind::Static((ident, ty, value).into()).into());
+        .map_with(|((ident, ty), value), e| {
+            (ItemKind::Const((ident, ty, Some(value)).into()), e.span()).into()
+        })
+        .labelled("const")
+        .as_context();
     let fn_params = group((ident().boxed(), just(Token::Colon).ignored(), typ().boxed()))
-        .map(|(name, _, ty)| Param { name, ty })
+        .map_with(|(name, _, ty), e| Param {
+            name,
+            ty,
+            span: e.span(),
+        })
         .separated_by(just(Token::Comma))
         .collect::<Vec<Param>>();
     let fn_sig = group((
@@ -212,19 +438,22 @@ where
     let fn_decl = fn_sig
         .clone()
         .then_ignore(just(Token::Semi))
-        .map(|sig| ItemKind::FnDecl(FnDecl { sig }).into());
+        .map_with(|sig, e| (ItemKind::FnDecl(FnDecl { sig }), e.span()).into());
     let fn_defn = group((
         fn_sig.clone(),
         just(Token::LBrace).rewind().ignored(),
         stmt().boxed(),
     ))
-    .map(|(sig, _, block)| {
-        ItemKind::FnDefn(FnDefn {
-            sig,
-            decl: None,
-            block,
-        })
-        .into()
+    .map_with(|(sig, _, block), e| {
+        (
+            ItemKind::FnDefn(FnDefn {
+                sig,
+                decl: None,
+                block,
+            }),
+            e.span(),
+        )
+            .into()
     });
     let struct_decl = group((
         just(Token::Struct).ignored(),
@@ -239,11 +468,42 @@ where
             .collect::<Vec<Field>>(),
         just(Token::RBrace).ignored(),
     ))
-    .map(|(_, name, _, fields, _)| ItemKind::StructDecl(StructDecl { name, fields }).into());
-    choice((static_var, fn_decl, fn_defn, struct_decl))
+    .map_with(|(_, name, _, fields, _), e| {
+        (ItemKind::StructDecl(StructDecl { name, fields }), e.span()).into()
+    });
+    // Skip at least one token, then resynchronise at whatever looks like the start of an item.
+    let item_start = choice((
+        just(Token::Static).ignored(),
+        just(Token::Const).ignored(),
+        group((just(Token::Struct), ident(), just(Token::LBrace))).ignored(),
+        ident().then(just(Token::LPar)).ignored(),
+    ));
+    let skip_item = token_tree()
+        .or(any().ignored())
+        .then(token_tree().and_is(item_start.not()).repeated())
+        .map_with(|_, e| (ItemKind::Invalid, e.span()).into());
+    choice((static_var, const_var, fn_decl, fn_defn, struct_decl))
+        .recover_with(via_parser(skip_item))
 }
 
-fn stmt<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, Stmt, Extras<'tok, 'src>>
+/// Parses a single token, or a balanced group delimited by braces. Used to skip input while
+/// recovering from errors.
+fn token_tree<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, (), Extras<'tok, 'src>> + Clone
+where
+    I: ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>>,
+{
+    recursive(|tt| {
+        choice((
+            tt.repeated()
+                .delimited_by(just(Token::LBrace), just(Token::RBrace)),
+            none_of([Token::LBrace, Token::RBrace]).ignored(),
+        ))
+    })
+}
+
+/// Returns a parser for a single [`Stmt`], as found in the body of a function, recovering from
+/// syntax errors within it.
+pub fn stmt<'tok, 'src: 'tok, I>() -> impl Parser<'tok, I, Stmt, Extras<'tok, 'src>>
 where
     I: ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>>,
 {
@@ -254,53 +514,76 @@ where
         just(Token::Semi).ignore_then(expr().boxed()),
         just(Token::RBrack).or(just(Token::RPar)),
     ))
-    .map(
-        |(id, open_delim, start, end, close_delim)| match (open_delim, close_delim) {
-            (Token::LBrack, Token::RBrack) => (id, Range::Inclusive(start, end)).into(),
-            (Token::LPar, Token::RPar) => (id, Range::Exclusive(start, end)).into(),
-            (Token::LPar, Token::RBrack) => (id, Range::ExclusiveInclusive(start, end)).into(),
-            (Token::LBrack, Token::RPar) => (id, Range::InclusiveExclusive(start, end)).into(),
+    .map_with(|(id, open_delim, start, end, close_delim), e| {
+        let range = match (open_delim, close_delim) {
+            (Token::LBrack, Token::RBrack) => Range::Inclusive(start, end),
+            (Token::LPar, Token::RPar) => Range::Exclusive(start, end),
+            (Token::LPar, Token::RBrack) => Range::ExclusiveInclusive(start, end),
+            (Token::LBrack, Token::RPar) => Range::InclusiveExclusive(start, end),
             _ => unreachable!(),
-        },
-    );
+        };
+        (id, range, e.span()).into()
+    });
     recursive(|stmt| {
         let block = group((
             just(Token::LBrace).ignored(),
             stmt.clone().repeated().collect::<Vec<_>>(),
             just(Token::RBrace).ignored(),
         ))
-        .map(|(_, stmts, _)| StmtKind::Block(stmts.into()).into());
+        .map_with(|(_, stmts, _), e| (StmtKind::Block(stmts.into()), e.span()).into())
+        .recover_with(via_parser(nested_delimiters(
+            Token::LBrace,
+            Token::RBrace,
+            [(Token::LPar, Token::RPar), (Token::LBrack, Token::RBrack)],
+            |span| (StmtKind::Invalid, span).into(),
+        )));
+        let let_kw = just(Token::Let).ignore_then(just(Token::Mut).or_not().map(|m| m.is_some()));
         let local_var_defn = choice((
-            just(Token::Let)
-                .ignore_then(ident().boxed())
+            let_kw
+                .clone()
+                .then(ident().boxed())
                 .then_ignore(just(Token::Colon))
                 .then(typ().boxed())
                 .then_ignore(just(Token::Assign))
                 .then(expr().boxed())
                 .then_ignore(just(Token::Semi))
-                .map(|((ident, ty), expr)| StmtKind::Local((ident, ty, Some(expr)).into()).into()),
-            just(Token::Let)
-                .ignore_then(ident().boxed())
-                .then_ignore(just(Token::Define))
+                .map_with(|(((mutable, ident), ty), expr), e| {
+                    (
+                        StmtKind::Local((mutable, ident, ty, Some(expr)).into()),
+                        e.span(),
+                    )
+                        .into()
+                }),
+            let_kw
+                .clone()
+                .then(ident().boxed())
+                .then(just(Token::Define).map_with(|_, e| e.span()))
                 .then(expr().boxed())
                 .then_ignore(just(Token::Semi))
-                .map(|(ident, expr)| {
-                    StmtKind::Local((ident, TyKind::Infer.into(), Some(expr)).into()).into()
+                .map_with(|(((mutable, ident), define), expr), e| {
+                    let ty = (TyKind::Infer, define).into();
+                    (
+                        StmtKind::Local((mutable, ident, ty, Some(expr)).into()),
+                        e.span(),
+                    )
+                        .into()
                 }),
         ));
-        let local_var_decl = just(Token::Let)
-            .ignore_then(ident().boxed())
+        let local_var_decl = let_kw
+            .then(ident().boxed())
             .then_ignore(just(Token::Colon))
             .then(typ().boxed())
             .then_ignore(just(Token::Semi))
-            .map(|(ident, ty)| StmtKind::Local((ident, ty, None).into()).into());
+            .map_with(|((mutable, ident), ty), e| {
+                (StmtKind::Local((mutable, ident, ty, None).into()), e.span()).into()
+            });
         let for_parser = group((
             just(Token::For)
                 .ignore_then(just(Token::LPar))
                 .ignore_then(range_pattern.clone()),
             just(Token::RPar).ignore_then(stmt.clone()),
         ))
-        .map(|(pat, statement)| StmtKind::For(pat, Box::new(statement)).into());
+        .map_with(|(pat, statement), e| (StmtKind::For(pat, Box::new(statement)), e.span()).into());
         let whl = group((
             just(Token::While).ignored(),
             just(Token::LPar).ignored(),
@@ -308,7 +591,9 @@ where
             just(Token::RPar).ignored(),
             stmt.clone(),
         ))
-        .map(|(_, _, expr, _, stmt)| StmtKind::While(expr, Box::new(stmt)).into());
+        .map_with(|(_, _, expr, _, stmt), e| {
+            (StmtKind::While(expr, Box::new(stmt)), e.span()).into()
+        });
         let if_parser = group((
             just(Token::If).ignored(),
             just(Token::LPar).ignored(),
@@ -317,22 +602,33 @@ where
             stmt.clone(),
             just(Token::Else).ignore_then(stmt.clone()).or_not(),
         ))
-        .map(|(_, _, expr, _, then, els)| match els {
-            Some(els) => StmtKind::If(expr, Box::new(then), Some(Box::new(els))).into(),
-            None => StmtKind::If(expr, Box::new(then), None).into(),
+        .map_with(|(_, _, cond, _, then, els), e| {
+            (
+                StmtKind::If(cond, Box::new(then), els.map(Box::new)),
+                e.span(),
+            )
+                .into()
         });
         let ret = group((
             just(Token::Return).ignored(),
             expr().boxed().or_not(),
             just(Token::Semi).ignored(),
         ))
-        .map(|(_, expr, _)| StmtKind::Return(expr).into());
+        .map_with(|(_, expr, _), e| (StmtKind::Return(expr), e.span()).into());
         let expr_stmt = expr()
             .boxed()
             .then_ignore(just(Token::Semi).or_not())
-            .map(|exp| StmtKind::Expr(exp).into());
-        let brk = group((just(Token::Break), just(Token::Semi))).to(StmtKind::Break.into());
-        let cnt = group((just(Token::Continue), just(Token::Semi))).to(StmtKind::Continue.into());
+            .map_with(|exp, e| (StmtKind::Expr(exp), e.span()).into());
+        let brk = group((just(Token::Break), just(Token::Semi)))
+            .map_with(|_, e| (StmtKind::Break, e.span()).into());
+        let cnt = group((just(Token::Continue), just(Token::Semi)))
+            .map_with(|_, e| (StmtKind::Continue, e.span()).into());
+        // Skip to the end of the statement, without leaving the enclosing block.
+        let skip_stmt = token_tree()
+            .and_is(just(Token::Semi).not())
+            .repeated()
+            .then(just(Token::Semi))
+            .map_with(|_, e| (StmtKind::Invalid, e.span()).into());
         choice((
             block,
             local_var_decl,
@@ -345,6 +641,7 @@ where
             brk,
             cnt,
         ))
+        .recover_with(via_parser(skip_stmt))
     })
 }
 
@@ -354,43 +651,46 @@ where
 {
     recursive(|typ| {
         let struct_type = just(Token::Struct).ignore_then(select! {
-            Token::Identifier(s) => TyKind::Struct(s.into()).into()
+            Token::Identifier(s) => TyKind::Struct(s.into())
         });
         let base_type = choice((
             select! {
-                Token::Int => TyKind::Primitive(Primitive::Int).into(),Token::Char => TyKind::Primitive(Primitive::Char).into(),Token::Void => TyKind::Void.into(),Token::Underscore => TyKind::Infer.into(),
+                Token::Int => TyKind::Primitive(Primitive::Int),Token::Char => TyKind::Primitive(Primitive::Char),Token::Bool => TyKind::Primitive(Primitive::Bool),Token::Void => TyKind::Void,Token::Underscore => TyKind::Infer,
             },
             struct_type,
-        ));
+        ))
+        .map_with(|kind, e| (kind, e.span()).into());
         let ptr_type = just(Token::And)
             .ignore_then(typ.clone().memoized())
-            .map(|ty| TyKind::Pointer(ty).into());
+            .map_with(|ty, e| (TyKind::Pointer(ty), e.span()).into());
         let arr_type = typ
             .clone().memoized()
             .then(
-                select! {
-                    Token::IntLiteral(i) => i.parse::<usize>().unwrap()
-                }
-                .delimited_by(just(Token::LBrack), just(Token::RBrack))
-                .repeated()
-                .collect::<Vec<_>>(),
+                length()
+                    .delimited_by(just(Token::LBrack), just(Token::RBrack))
+                    .repeated()
+                    .collect::<Vec<_>>(),
             )
-            .map(make_array_type);
+            .map_with(|(ty, sizes), e| make_array_type(ty, sizes, e.span()));
         choice((
             arr_type,
             ptr_type,
             base_type,
             typ.memoized().delimited_by(just(Token::LPar), just(Token::RPar)),
         ))
-    }).memoized()
+    })
+    // Not memoized as a whole: a memoized parser drops the errors of its first failure, which would
+    // lose the error in a type that turns out to be optional.
+    .labelled("type")
+    .as_context()
 }
 
-fn make_array_type((ty, sizes): (Ty, Vec<usize>)) -> Ty {
+/// Wraps `ty` in one array type per size, such that the first size is outermost. Every array type
+/// spans the whole of `span`.
+fn make_array_type(ty: Ty, mut sizes: Vec<Length>, span: SimpleSpan) -> Ty {
     let mut ty = ty;
-    let mut sizes = sizes.clone();
-    while !sizes.is_empty() {
-        let size = sizes.pop().unwrap();
-        ty = TyKind::Array(size, ty).into();
+    while let Some(size) = sizes.pop() {
+        ty = (TyKind::Array(size, ty), span).into();
     }
     ty
 }
@@ -405,7 +705,10 @@ mod tests {
     use rstest::{fixture, rstest};
     use tempfile::NamedTempFile;
 
-    use crate::lexer::SourceFile;
+    use crate::{
+        ast::{types::TyKind, ItemKind},
+        lexer::SourceFile,
+    };
 
     #[fixture]
     fn cache() -> FileCache {
@@ -422,6 +725,7 @@ mod tests {
     #[case::int("23", "23")]
     #[case::hello_world("\"Hello, world!\"", "\"Hello, world!\"")]
     #[case::char_a("'a'", "'a'")]
+    #[case::true_("true", "true")]
     fn test_literal(
         #[case] input: String,
         #[case] expected: String,
@@ -432,7 +736,7 @@ mod tests {
         match super::literal().parse(inputs).into_result() {
             Ok(recovered) => assert_eq!(recovered.to_string(), expected),
             Err(errs) => {
-                super::print_errors(&src_file.source, errs);
+                super::print_errors(&src_file, errs);
                 bail!("Parsing error");
             }
         };
@@ -443,18 +747,20 @@ mod tests {
     #[case::infer("_", "_")]
     #[case::int("int", "int")]
     #[case::char("char", "char")]
+    #[case::bool("bool", "bool")]
     #[case::int_ptr("&char", "(&char)")]
     #[case::int_arr("char[4]", "(char[4])")]
     #[case::ptr_arr("(&int)[10]", "((&int)[10])")]
     #[case::arr_ptr("&int[10]", "(&(int[10]))")]
     #[case::multidim_arr("int[4][5]", "(int[4][5])")]
+    #[case::named_arr("int[SIZE][2]", "(int[SIZE][2])")]
     fn test_type(#[case] input: String, #[case] expected: String, cache: FileCache) {
         let src_file = src(input, cache);
         let inputs = super::token_stream(&src_file);
         match super::typ().parse(inputs).into_result() {
             Ok(recovered) => assert_eq!(recovered.to_string(), expected),
             Err(errs) => {
-                super::print_errors(&src_file.source, errs);
+                super::print_errors(&src_file, errs);
                 panic!("Parsing error");
             }
         }
@@ -465,23 +771,103 @@ mod tests {
     #[case::ident("foo", "foo")]
     #[case::lit_assign("foo = 42", "(foo = 42)")]
     #[case::double_assign("foo = bar = 42", "(foo = (bar = 42))")]
+    #[case::compound_assign("a[i] += b <<= 1 + 2", "((a[i]) += (b <<= (1 + 2)))")]
     #[case::log_or("foo = 42 || 24", "(foo = (42 || 24))")]
     #[case::log_ops("foo = 42 || 24 && 24 || 42", "(foo = ((42 || (24 && 24)) || 42))")]
     #[case::plus_minus("3 + 4 - 6 + 7 + 8", "((((3 + 4) - 6) + 7) + 8)")]
     #[case::arithmetic("3 + 4 * 7 - 6 + 3 * 4", "(((3 + (4 * 7)) - 6) + (3 * 4))")]
     #[case::plus_parens("3 + (4 + 6) + 7", "((3 + (4 + 6)) + 7)")]
     #[case::deref("x * y + *z", "((x * y) + (*z))")]
+    #[case::not("!a && !(b == c)", "((!a) && (!(b == c)))")]
+    #[case::negation("-x * +y", "((-x) * (+y))")]
+    #[case::double_negation("- -x", "(-(-x))")]
+    #[case::bit_not("~a - -1", "((~a) - (-1))")]
+    #[case::bitwise("a | b ^ c & d", "(a | (b ^ (c & d)))")]
+    #[case::shifts("1 << n + 1 >> 2", "((1 << (n + 1)) >> 2)")]
+    #[case::bitwise_compare("a & 1 == 0", "((a & 1) == 0)")]
+    #[case::bit_and_ref("a & &b & *c", "((a & (&b)) & (*c))")]
     #[case::cast_char_to_int("x = (int)'c'", "(x = ((int) 'c'))")]
     #[case::simple_fn_call("x(1, 2, 3)", "(x(1, 2, 3))")]
+    #[case::array_lit("[1, 2 + 3, f(x)][0]", "([1, (2 + 3), (f(x))][0])")]
+    #[case::array_repeat("[[0; 4]; 2]", "[[0; 4]; 2]")]
+    #[case::named_repeat("[0; N]", "[0; N]")]
+    #[case::struct_lit("struct p { x: 1, y: a = b, }.x", "(struct p { x: 1, y: (a = b) }.x)")]
+    #[case::empty_struct_lit("struct e {}", "struct e {}")]
     fn test_expr(#[case] input: String, #[case] expected: String, cache: FileCache) {
         let src_file = src(input, cache);
         let inputs = super::token_stream(&src_file);
         match super::expr().parse(inputs).into_result() {
             Ok(recovered) => assert_eq!(recovered.to_string(), expected),
             Err(errs) => {
-                super::print_errors(&src_file.source, errs);
+                super::print_errors(&src_file, errs);
                 panic!("Parsing error");
             }
         }
     }
+
+    #[rstest]
+    #[case::ident("foo", 0..3)]
+    #[case::binop("3 + 4 * 7", 0..9)]
+    #[case::parens("(3 + 4)", 1..6)]
+    #[case::call("f(1, 2)", 0..7)]
+    #[case::deref("*x", 0..2)]
+    fn test_expr_span(
+        #[case] input: String,
+        #[case] expected: std::ops::Range<usize>,
+        cache: FileCache,
+    ) {
+        let src_file = src(input, cache);
+        let inputs = super::token_stream(&src_file);
+        let recovered = super::expr().parse(inputs).into_result().unwrap();
+        assert_eq!(recovered.span.into_range(), expected);
+    }
+
+    /// A nested type keeps its own span and id, even when an equivalent type was parsed before.
+    #[rstest]
+    fn test_nested_ty_span(cache: FileCache) {
+        let src_file = src("f(a: &int, b: &int): void;".into(), cache);
+        let inputs = super::token_stream(&src_file);
+        let items = super::parser().parse(inputs).into_result().unwrap();
+        let ItemKind::FnDecl(decl) = &items[0].kind else {
+            panic!("expected a function declaration");
+        };
+        let pointees = decl.sig.params.iter().map(|param| match &*param.ty.kind {
+            TyKind::Pointer(pointee) => pointee,
+            _ => panic!("expected a pointer"),
+        });
+        let [first, second] = &pointees.collect::<Vec<_>>()[..] else {
+            panic!("expected two parameters");
+        };
+        assert_eq!(first.span.into_range(), 6..9);
+        assert_eq!(second.span.into_range(), 15..18);
+        assert_ne!(first.id, second.id);
+    }
+
+    #[rstest]
+    #[case::missing_semi("f(): int { return 1 }", 1)]
+    #[case::stmts("f(): void { let x := (1 + ); x = ; g(x); }", 2)]
+    #[case::items("struct s { x int; } static = 4; f(): void {}", 2)]
+    #[case::blocks("f(): void { if (x { y; } } g(): int { 1 +; }", 2)]
+    fn test_recovery(#[case] input: String, #[case] expected: usize, cache: FileCache) {
+        let src_file = src(input, cache);
+        let inputs = super::token_stream(&src_file);
+        let (items, errs) = super::parser().parse(inputs).into_output_errors();
+        assert!(items.is_some());
+        assert_eq!(errs.len(), expected);
+    }
+
+    #[rstest]
+    #[case::unexpected("f(): int { return 1 + ; }", "E0100")]
+    #[case::end_of_input("f(): int { return 0;", "E0101")]
+    #[case::in_type("f(x: &[int): int;", "E0102")]
+    #[case::at_type("f(): int { let x: 3 = 3; }", "E0102")]
+    #[case::static_semi("static x: int = 0\nf(): int { 0 }", "E0103")]
+    #[case::static_colon("static x int;", "E0100")]
+    #[case::const_value("const N;", "E0100")]
+    fn test_syntax_error(#[case] input: String, #[case] code: &str, cache: FileCache) {
+        let src_file = src(input, cache);
+        let inputs = super::token_stream(&src_file);
+        let (_, errs) = super::parser().parse(inputs).into_output_errors();
+        assert_eq!(code, super::syntax_error(&errs[0]).code);
+    }
 }
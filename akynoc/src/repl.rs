@@ -0,0 +1,396 @@
+//! Interactive read-eval-print loop (`akynoc repl`).
+//!
+//! Each entry is either a sequence of items, such as statics, structs and functions, or a sequence
+//! of statements, which may end in an expression without a semicolon, whose value is printed. An
+//! entry continues over as many lines as it takes to close its parentheses, braces and brackets.
+//!
+//! Everything entered stays defined for the rest of the session. Items behave as if they were all
+//! in one file. Statements run as if each entry opened a block nested in the block of the entry
+//! before, so that an entry may shadow the variables declared by earlier ones. Every entry is
+//! checked along with everything entered before it, and one with errors is discarded.
+//!
+//! Lines starting with `:` are commands:
+//! - `:type <expr>` prints the type of an expression, without evaluating it.
+//! - `:ast <expr>` prints an expression as parsed, fully parenthesized.
+//! - `:tokens <src>` prints the tokens of some source code, with their spans.
+//! - `:help` lists the commands, and `:quit` ends the session.
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    thread,
+};
+
+use anyhow::Result;
+use chumsky::{
+    error::Rich,
+    input::{Input, Stream, ValueInput},
+    span::SimpleSpan,
+    IterParser, Parser,
+};
+
+use crate::{
+    ast::{
+        exprs::Expr,
+        functions::{FnDefn, FnSig},
+        statements::{Stmt, StmtKind},
+        types::{Ty, TyKind},
+        Item, ItemKind,
+    },
+    diagnostic::{self, Diagnostic, ErrorFormat},
+    interp::{Interpreter, Session, HOST_STACK_SIZE},
+    lexer::{lex, LexerPass, SourceFile, Token},
+    parser,
+    sem::SemanticAnalysis,
+    util::CompilerPass,
+};
+
+/// Name of the session's source, in error reports.
+const NAME: &str = "<repl>";
+
+/// Name of the function the statements of the session are checked as the body of.
+const BODY: &str = "<repl>";
+
+const HELP: &str = "\
+Enter items, statements, or an expression to print its value.
+:type <expr>    Print the type of an expression
+:ast <expr>     Print an expression as parsed
+:tokens <src>   Print the tokens of some source code
+:help           Print this message
+:quit           End the session";
+
+/// Runs a session over stdin and stdout, until stdin ends or `:quit` is entered. Prompts are only
+/// shown if stdin is a terminal, and diagnostics are written to stderr in `format`.
+pub fn run(format: ErrorFormat) -> Result<()> {
+    // Deeply recursive functions need more stack than the main thread has.
+    thread::Builder::new()
+        .stack_size(HOST_STACK_SIZE)
+        .spawn(move || {
+            let stdin = io::stdin();
+            let prompt = stdin.is_terminal();
+            Repl::new(format).run(&mut stdin.lock(), &mut io::stdout().lock(), prompt)
+        })?
+        .join()
+        .expect("the REPL panicked")?;
+    Ok(())
+}
+
+/// A parsed entry.
+enum Entry {
+    Items(Vec<Item>),
+    Stmts(Vec<Stmt>),
+}
+
+/// The state of a session.
+#[derive(Default)]
+pub struct Repl {
+    format: ErrorFormat,
+    /// Every entry accepted so far, each ending in a newline. Spans are offsets into it.
+    text: String,
+    items: Vec<Item>,
+    /// The statements of each entry accepted so far.
+    blocks: Vec<Vec<Stmt>>,
+    session: Session,
+}
+
+impl Repl {
+    pub fn new(format: ErrorFormat) -> Self {
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    /// Reads entries from `input` and writes their results to `output`, until `input` ends or
+    /// `:quit` is entered. Programs read from the same `input`. Errors are reported on stderr.
+    pub fn run(
+        &mut self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+        prompt: bool,
+    ) -> io::Result<()> {
+        let mut entry = String::new();
+        loop {
+            if prompt {
+                write!(output, "{}", if entry.is_empty() { "> " } else { "... " })?;
+                output.flush()?;
+            }
+            let mut line = String::new();
+            let ended = input.read_line(&mut line)? == 0;
+            if !line.ends_with('\n') {
+                line.push('\n');
+            }
+            entry.push_str(&line);
+            if !ended && !complete(&entry) {
+                continue;
+            }
+            if !entry.trim().is_empty() && !self.enter(&entry, input, output)? {
+                break;
+            }
+            entry.clear();
+            if ended {
+                break;
+            }
+        }
+        if prompt {
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates a complete entry or command. Returns `false` if the session should end.
+    fn enter(
+        &mut self,
+        entry: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<bool> {
+        let Some(command) = entry.trim().strip_prefix(':') else {
+            self.eval(entry, input, output);
+            return Ok(true);
+        };
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        match name {
+            "type" => {
+                if let Some(ty) = self.type_of(arg) {
+                    writeln!(output, "{ty}")?;
+                }
+            }
+            "ast" => {
+                if let Some(expr) = self.expr(&self.source(arg)) {
+                    writeln!(output, "{expr}")?;
+                }
+            }
+            "tokens" => {
+                let src = SourceFile::new(NAME.into(), arg.into());
+                for (token, span) in lex(&src) {
+                    writeln!(output, "{token:?} {span}")?;
+                }
+            }
+            "help" => writeln!(output, "{HELP}")?,
+            "quit" => return Ok(false),
+            _ => eprintln!("Unknown command `:{name}`, see `:help`"),
+        }
+        Ok(true)
+    }
+
+    /// Checks and runs `entry` after everything entered before, keeping it if it succeeds.
+    fn eval(&mut self, entry: &str, input: &mut dyn BufRead, output: &mut dyn Write) {
+        let src = self.source(entry);
+        let Some(tokens) = self.tokens(&src) else {
+            return;
+        };
+        let entry = match parse_entry(&src, tokens) {
+            Ok(entry) => entry,
+            Err(diagnostics) => return self.emit(&src, &diagnostics),
+        };
+        let (items, stmts) = match entry {
+            Entry::Items(items) => (items, vec![]),
+            Entry::Stmts(stmts) => (vec![], stmts),
+        };
+        let mut program = self.items.clone();
+        program.extend(items.iter().cloned());
+        program.push(self.body(stmts.clone()));
+        let mut sem = SemanticAnalysis::new();
+        let analysis = sem.analyse(&mut program);
+        self.emit(&src, sem.diagnostics());
+        let Some(analysis) = analysis else {
+            return;
+        };
+        if !stmts.is_empty() {
+            // A final expression without a semicolon is the result of the entry.
+            let text = src.source.text();
+            let (init, result) = match stmts.split_last() {
+                Some((last, init)) if !text[last.span.into_range()].ends_with(';') => {
+                    match &last.kind {
+                        StmtKind::Expr(expr) => (init, Some(expr)),
+                        _ => (&stmts[..], None),
+                    }
+                }
+                _ => (&stmts[..], None),
+            };
+            let mut interp = Interpreter::new();
+            let session = &mut self.session;
+            let evaluated = interp.eval(session, &program, &analysis, init, result, input, output);
+            self.emit(&src, interp.diagnostics());
+            if evaluated.is_none() {
+                return;
+            }
+            self.blocks.push(stmts);
+        }
+        self.items.extend(items);
+        self.text = src.source.text().to_string();
+    }
+
+    /// The type of the expression `src`, were it entered next.
+    fn type_of(&self, src: &str) -> Option<Ty> {
+        let src = self.source(src);
+        let expr = self.expr(&src)?;
+        let id = expr.id;
+        let span = expr.span;
+        let mut program = self.items.clone();
+        program.push(self.body(vec![(StmtKind::Expr(expr), span).into()]));
+        let mut sem = SemanticAnalysis::new();
+        let analysis = sem.analyse(&mut program);
+        self.emit(&src, sem.diagnostics());
+        Some(analysis?.types.exprs[&id].clone())
+    }
+
+    /// Parses the expression in `src` after the text of the session. Returns [`None`] if it is not
+    /// one, after reporting why.
+    fn expr(&self, src: &SourceFile) -> Option<Expr> {
+        let (expr, errs) = parser::expr()
+            .parse(stream(src, self.tokens(src)?))
+            .into_output_errors();
+        let diagnostics = errs.iter().map(parser::syntax_error).collect::<Vec<_>>();
+        self.emit(src, &diagnostics);
+        expr.filter(|_| diagnostics.is_empty())
+    }
+
+    fn emit(&self, src: &SourceFile, diagnostics: &[Diagnostic]) {
+        diagnostic::emit(src, diagnostics, self.format);
+    }
+
+    /// The text of the session followed by `entry`.
+    fn source(&self, entry: &str) -> SourceFile {
+        SourceFile::new(NAME.into(), format!("{}{entry}", self.text))
+    }
+
+    /// The tokens of `src` after the text of the session. Returns [`None`] if any are invalid,
+    /// after reporting them.
+    fn tokens<'src>(&self, src: &'src SourceFile) -> Option<Vec<(Token<'src>, SimpleSpan)>> {
+        let mut lexer = LexerPass::new(src);
+        let tokens = lexer.lex();
+        self.emit(src, lexer.diagnostics());
+        if lexer.has_error() {
+            return None;
+        }
+        let start = self.text.len();
+        Some(
+            tokens
+                .into_iter()
+                .filter(|(_, span)| span.start >= start)
+                .collect(),
+        )
+    }
+
+    /// A function whose body runs the statements of every entry so far and then `stmts`, each
+    /// entry in a block nested in that of the one before.
+    fn body(&self, stmts: Vec<Stmt>) -> Item {
+        let span = SimpleSpan::from(0..self.text.len());
+        let block = self.blocks.iter().rev().fold(stmts, |inner, outer| {
+            let mut stmts = outer.clone();
+            stmts.push((StmtKind::Block(inner.into()), span).into());
+            stmts
+        });
+        let sig = FnSig {
+            name: BODY.into(),
+            params: vec![],
+            ty: TyKind::Void.into(),
+        };
+        let defn = FnDefn {
+            sig,
+            decl: None,
+            block: (StmtKind::Block(block.into()), span).into(),
+        };
+        (ItemKind::FnDefn(defn), span).into()
+    }
+}
+
+/// Whether `entry` closes every parenthesis, brace and bracket it opens, or is a command.
+fn complete(entry: &str) -> bool {
+    if entry.trim_start().starts_with(':') {
+        return true;
+    }
+    let src = SourceFile::new(NAME.into(), entry.into());
+    let depth = lex(&src).fold(0, |depth, (token, _)| match token {
+        Token::LPar | Token::LBrace | Token::LBrack => depth + 1,
+        Token::RPar | Token::RBrace | Token::RBrack => depth - 1,
+        _ => depth,
+    });
+    depth <= 0
+}
+
+fn stream<'tok, 'src: 'tok>(
+    src: &SourceFile,
+    tokens: Vec<(Token<'src>, SimpleSpan)>,
+) -> impl ValueInput<'tok, Span = SimpleSpan, Token = Token<'src>> {
+    let end = src.source.text().len();
+    Stream::from_iter(tokens).map((end..end).into(), |(t, s)| (t, s))
+}
+
+/// Parses `tokens` as either items or statements. If they are neither, returns the errors of
+/// whichever reading got further.
+fn parse_entry(
+    src: &SourceFile,
+    tokens: Vec<(Token<'_>, SimpleSpan)>,
+) -> Result<Entry, Vec<Diagnostic>> {
+    let (items, item_errs) = parser::parser()
+        .parse(stream(src, tokens.clone()))
+        .into_output_errors();
+    if let (Some(items), true) = (items, item_errs.is_empty()) {
+        return Ok(Entry::Items(items));
+    }
+    let (stmts, stmt_errs) = parser::stmt()
+        .repeated()
+        .collect::<Vec<_>>()
+        .parse(stream(src, tokens))
+        .into_output_errors();
+    if let (Some(stmts), true) = (stmts, stmt_errs.is_empty()) {
+        return Ok(Entry::Stmts(stmts));
+    }
+    let reach = |errs: &[Rich<'_, Token<'_>>]| errs.first().map(|err| err.span().start);
+    let errs = if reach(&item_errs) > reach(&stmt_errs) {
+        item_errs
+    } else {
+        stmt_errs
+    };
+    Err(errs.iter().map(parser::syntax_error).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::Repl;
+    use crate::diagnostic::ErrorFormat;
+
+    #[rstest]
+    #[case::empty("", "")]
+    #[case::expr("1 + 2 * 3\n", "7\n")]
+    #[case::semicolon("1 + 2;\n", "")]
+    #[case::char("'a'\n", "'a'\n")]
+    #[case::locals("let x := 2;\nlet y := x * x;\ny\n", "4\n")]
+    #[case::shadowing("let x := 1;\nlet x := 'x';\nx\n", "'x'\n")]
+    #[case::multi_line("sq(n: int): int {\n    n * n\n}\nsq(\n    7\n)\n", "49\n")]
+    #[case::statics("static s: int = 1;\ns = s + 1;\ns\n", "2\n")]
+    #[case::structs(
+        "struct p { x: int; y: char; }\nlet mut q: struct p;\nq.x = 1;\nq.y = 'y';\nq\n",
+        "struct p { x: 1, y: 'y' }\n"
+    )]
+    #[case::arrays(
+        "let mut a: int[3];\nfor (i: [0; 3)) { a[i] = i * i; }\na\n",
+        "[0, 1, 4]\n"
+    )]
+    #[case::void("f(): void {}\nf()\n", "")]
+    #[case::errors_discarded("let x := y;\nlet y := 1;\ny\n", "1\n")]
+    #[case::fault_discarded("let z := 1 / 0;\nz\nlet z := 2;\nz\n", "2\n")]
+    #[case::services("print_i(n: int): void;\nprint_i(4);\nprint_i(2);\n", "42")]
+    #[case::shared_input("read_i(): int;\nread_i() + 1\n41\n", "42\n")]
+    #[case::no_newline("3", "3\n")]
+    #[case::type_of("let c := 'c';\n:type &c\n", "(&char)\n")]
+    #[case::type_error(":type y\n", "")]
+    #[case::ast(":ast 1 + 2 * -x\n", "(1 + (2 * (-x)))\n")]
+    #[case::tokens(
+        ":tokens x = 1;\n",
+        "Identifier(\"x\") 0..1\nAssign 2..3\nIntLiteral(\"1\") 4..5\nSemi 5..6\n"
+    )]
+    #[case::quit("1\n:quit\n2\n", "1\n")]
+    fn test_repl(#[case] input: &str, #[case] expected: &str) {
+        let mut output = vec![];
+        Repl::new(ErrorFormat::Human)
+            .run(&mut input.as_bytes(), &mut output, false)
+            .unwrap();
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
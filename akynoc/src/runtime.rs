@@ -0,0 +1,106 @@
+//! Services provided to programs by the runtime: printing, reading and allocating memory.
+//!
+//! A program uses a service by declaring, without defining, a function with the service's name and
+//! signature:
+//!
+//! | Service    | Signature                      | Behaviour                                      |
+//! |------------|--------------------------------|------------------------------------------------|
+//! | `print_i`  | `print_i(i: int): void`        | Prints `i` in decimal.                         |
+//! | `print_c`  | `print_c(c: char): void`       | Prints the byte `c`.                           |
+//! | `print_s`  | `print_s(s: &char): void`      | Prints the bytes from `s` up to a NUL byte.    |
+//! | `read_i`   | `read_i(): int`                | Skips whitespace, then reads a decimal `int`, or `0` if there is none. |
+//! | `read_c`   | `read_c(): char`               | Reads a single byte, or `(char) 255` at the end of input. |
+//! | `mcmalloc` | `mcmalloc(size: int): &void`   | Allocates `size` bytes on the heap, returning null if it cannot. |
+//!
+//! A declaration with a service's name but a different signature is an ordinary external function.
+//! Each backend implements the services its own way: the [interpreter](crate::interp) directly,
+//! [x86-64](crate::codegen::x86_64) on top of the C library, and [MIPS](crate::codegen::mips)
+//! with syscalls.
+use crate::ast::{
+    functions::FnSig,
+    types::{Ident, Primitive, TyKind},
+    Item, ItemKind,
+};
+
+/// A service provided by the runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Service {
+    PrintI,
+    PrintC,
+    PrintS,
+    ReadI,
+    ReadC,
+    Malloc,
+}
+
+const INT: TyKind = TyKind::Primitive(Primitive::Int);
+const CHAR: TyKind = TyKind::Primitive(Primitive::Char);
+
+impl Service {
+    pub const ALL: [Service; 6] = [
+        Service::PrintI,
+        Service::PrintC,
+        Service::PrintS,
+        Service::ReadI,
+        Service::ReadC,
+        Service::Malloc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::PrintI => "print_i",
+            Service::PrintC => "print_c",
+            Service::PrintS => "print_s",
+            Service::ReadI => "read_i",
+            Service::ReadC => "read_c",
+            Service::Malloc => "mcmalloc",
+        }
+    }
+
+    /// The service a function declared with `sig`, but never defined, refers to.
+    pub fn of(sig: &FnSig) -> Option<Service> {
+        let service = Self::ALL
+            .into_iter()
+            .find(|service| *sig.name.name == service.name())?;
+        let params = sig
+            .params
+            .iter()
+            .map(|param| (*param.ty.kind).clone())
+            .collect::<Vec<_>>();
+        let ret = (*sig.ty.kind).clone();
+        let pointer_to =
+            |kind: &TyKind, to: TyKind| matches!(kind, TyKind::Pointer(ty) if *ty.kind == to);
+        let matches = match service {
+            Service::PrintI => params == [INT] && ret == TyKind::Void,
+            Service::PrintC => params == [CHAR] && ret == TyKind::Void,
+            Service::PrintS => {
+                matches!(&params[..], [s] if pointer_to(s, CHAR)) && ret == TyKind::Void
+            }
+            Service::ReadI => params.is_empty() && ret == INT,
+            Service::ReadC => params.is_empty() && ret == CHAR,
+            Service::Malloc => params == [INT] && pointer_to(&ret, TyKind::Void),
+        };
+        matches.then_some(service)
+    }
+
+    /// The services `items` declare without defining, in the order of [`ALL`](Self::ALL).
+    pub fn declared(items: &[Item]) -> Vec<Service> {
+        let defined = |name: Ident| {
+            items.iter().any(|item| match &item.kind {
+                ItemKind::FnDefn(defn) => defn.sig.name == name,
+                _ => false,
+            })
+        };
+        let declared = items
+            .iter()
+            .filter_map(|item| match &item.kind {
+                ItemKind::FnDecl(decl) if !defined(decl.sig.name) => Service::of(&decl.sig),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        Self::ALL
+            .into_iter()
+            .filter(|service| declared.contains(service))
+            .collect()
+    }
+}
@@ -0,0 +1,134 @@
+//! Semantic analysis over the [AST](crate::ast), run once parsing succeeds.
+//!
+//! Analysis is split into sub-passes, each building a side table keyed by the
+//! [`NodeId`](crate::util::NodeId) of the nodes it annotates:
+//! 1. [Name resolution](resolve), binding every name to its declaration.
+//! 2. [Constant evaluation](consteval), computing every static, constant and named length.
+//! 3. [Type inference](infer), resolving every `_` placeholder in a type.
+//! 4. [Type checking](typeck), giving every expression a type.
+use std::collections::HashMap;
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    ast::Item,
+    diagnostic::Diagnostic,
+    util::{CompilerPass, NodeId},
+};
+
+pub mod consteval;
+pub mod infer;
+pub mod resolve;
+pub mod typeck;
+
+use consteval::{Const, ConstEval};
+use infer::Inference;
+use resolve::{Resolutions, Resolver};
+use typeck::{TypeChecker, TypeInfo};
+
+/// A single error found during semantic analysis.
+pub struct SemError {
+    pub code: &'static str,
+    /// The source the error is about.
+    pub span: SimpleSpan,
+    pub message: String,
+    /// Secondary locations relevant to the error, such as a previous definition.
+    pub labels: Vec<(SimpleSpan, String)>,
+}
+
+impl SemError {
+    pub fn new(code: &'static str, span: SimpleSpan, message: String) -> Self {
+        Self {
+            code,
+            span,
+            message,
+            labels: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, span: SimpleSpan, message: String) -> Self {
+        self.labels.push((span, message));
+        self
+    }
+}
+
+impl From<SemError> for Diagnostic {
+    fn from(err: SemError) -> Self {
+        let diagnostic = Diagnostic::error(err.code, err.message).at(err.span);
+        err.labels
+            .into_iter()
+            .fold(diagnostic, |diagnostic, (span, message)| {
+                diagnostic.with_label(span, message)
+            })
+    }
+}
+
+/// Results of a successful [`SemanticAnalysis`].
+pub struct Analysis {
+    pub resolutions: Resolutions,
+    pub types: TypeInfo,
+    /// Maps the [`Item`] of each static with an initial value to that value.
+    pub consts: HashMap<NodeId, Const>,
+}
+
+/// The semantic analysis stage of the pipeline.
+#[derive(Default)]
+pub struct SemanticAnalysis {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl SemanticAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Analyses a parsed program, reporting every error found. Returns [`None`] if any errors were
+    /// reported.
+    ///
+    /// Array lengths naming a static are replaced in `items` with the static's value.
+    pub fn analyse(&mut self, items: &mut [Item]) -> Option<Analysis> {
+        let (resolutions, errs) = Resolver::resolve(items);
+        self.report_all(errs);
+        if self.has_error() {
+            return None;
+        }
+        let (consts, errs) = ConstEval::evaluate(items, &resolutions);
+        self.report_all(errs);
+        if self.has_error() {
+            return None;
+        }
+        let (inferred, errs) = Inference::infer(items, &resolutions);
+        self.report_all(errs);
+        if self.has_error() {
+            return None;
+        }
+        let (types, errs) = TypeChecker::check(items, &resolutions, &inferred);
+        self.report_all(errs);
+        if self.has_error() {
+            return None;
+        }
+        Some(Analysis {
+            resolutions,
+            types,
+            consts,
+        })
+    }
+
+    fn report_all(&mut self, errs: Vec<SemError>) {
+        for err in errs {
+            self.report(err.into());
+        }
+    }
+}
+
+impl CompilerPass for SemanticAnalysis {
+    const FAIL_CODE: u8 = 240;
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
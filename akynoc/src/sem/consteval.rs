@@ -0,0 +1,552 @@
+//! Constant evaluation.
+//!
+//! Evaluates the value of every static and constant at compile time, and replaces every array
+//! length naming one with its value. Runs after [name resolution](super::resolve), whose
+//! [`Resolutions`] bind the names in initial values, and before [type inference](super::infer),
+//! which needs the length of every array type.
+//!
+//! Constant expressions are:
+//! - Literals, constants, and statics with a constant initial value, declared before or after
+//!   their use.
+//! - Unary and binary operators applied to constants, with `&&` and `||` short-circuiting as they
+//!   do at run time.
+//! - Casts between `int`, `char` and `bool`, and from a string literal to another pointer type.
+//! - Struct and array literals of constants.
+//!
+//! Unlike at run time, arithmetic that overflows and division by zero are errors. Operands of the
+//! wrong type are left for the [type checker](super::typeck) to report.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use chumsky::span::SimpleSpan;
+use internment::Intern;
+
+use crate::{
+    ast::{
+        exprs::{Expr, ExprKind, Operator, UnaryOp, Value},
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        types::{Ident, Length, Primitive, Ty, TyKind},
+        Item, ItemKind,
+    },
+    sem::{
+        resolve::{Binding, Resolutions},
+        SemError,
+    },
+    util::NodeId,
+};
+
+/// The value of a constant expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Int(i32),
+    Char(u8),
+    Bool(bool),
+    /// A string literal, which points to read-only data.
+    Str(Intern<String>),
+    /// A struct, with its fields in the order the literal gave them.
+    Struct(Ident, Vec<(Ident, Const)>),
+    Array(Vec<Const>),
+}
+
+/// A static or constant seen while collecting globals.
+struct StaticEntry {
+    /// `"static"` or `"constant"`, for error messages.
+    what: &'static str,
+    name: Ident,
+    span: SimpleSpan,
+    value: Option<Expr>,
+}
+
+pub struct ConstEval<'ast> {
+    resolutions: &'ast Resolutions,
+    /// Statics and constants by [`Item`], with their initial values copied out so that the AST can be updated
+    /// once they are evaluated.
+    statics: HashMap<NodeId, StaticEntry>,
+    /// Statics, constants and functions by name, for looking up named lengths.
+    globals: HashMap<Ident, Binding>,
+    /// Value of each static evaluated so far, or [`None`] if it has no constant value.
+    values: HashMap<NodeId, Option<Const>>,
+    /// Statics whose values are being evaluated, to detect cycles.
+    active: HashSet<NodeId>,
+    /// Value of each named length evaluated so far, so that each is reported at most once.
+    lengths: HashMap<Ident, Option<usize>>,
+    errors: Vec<SemError>,
+}
+
+impl<'ast> ConstEval<'ast> {
+    /// Evaluates every static and constant in `items`, and replaces every named length with its
+    /// value. Returns the value of each one with an initial value, keyed by its [`Item`], along
+    /// with any errors.
+    pub fn evaluate(
+        items: &mut [Item],
+        resolutions: &'ast Resolutions,
+    ) -> (HashMap<NodeId, Const>, Vec<SemError>) {
+        let mut eval = Self {
+            resolutions,
+            statics: HashMap::new(),
+            globals: HashMap::new(),
+            values: HashMap::new(),
+            active: HashSet::new(),
+            lengths: HashMap::new(),
+            errors: vec![],
+        };
+        for item in items.iter() {
+            match &item.kind {
+                ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                    let what = match item.kind {
+                        ItemKind::Const(_) => "constant",
+                        _ => "static",
+                    };
+                    let entry = StaticEntry {
+                        what,
+                        name: decl.ident,
+                        span: item.span,
+                        value: decl.value.clone(),
+                    };
+                    eval.statics.insert(item.id, entry);
+                    eval.globals.insert(decl.ident, Binding::Static(item.id));
+                }
+                ItemKind::FnDecl(decl) => {
+                    eval.globals.insert(decl.sig.name, Binding::Fn(item.id));
+                }
+                ItemKind::FnDefn(defn) => {
+                    eval.globals.insert(defn.sig.name, Binding::Fn(item.id));
+                }
+                ItemKind::StructDecl(_) | ItemKind::Invalid => (),
+            }
+        }
+        for item in items.iter() {
+            if let ItemKind::Static(_) | ItemKind::Const(_) = item.kind {
+                eval.static_value(item.id);
+            }
+        }
+        for item in items.iter_mut() {
+            eval.fill_item(item);
+        }
+
+        let values = eval
+            .values
+            .into_iter()
+            .filter_map(|(item, value)| Some((item, value?)))
+            .collect();
+        (values, eval.errors)
+    }
+
+    fn error(&mut self, code: &'static str, span: SimpleSpan, message: String) {
+        self.errors.push(SemError::new(code, span, message));
+    }
+
+    /// The value of the static `item`, evaluating it if needed. Reports a cycle if the static is
+    /// already being evaluated.
+    fn static_value(&mut self, item: NodeId) -> Option<Const> {
+        if let Some(value) = self.values.get(&item) {
+            return value.clone();
+        }
+        let entry = &self.statics[&item];
+        let value = entry.value.clone()?;
+        self.active.insert(item);
+        let result = self.eval(&value);
+        self.active.remove(&item);
+        self.values.insert(item, result.clone());
+        result
+    }
+
+    /// The value of the static `item`, used at `span` within a constant expression.
+    fn use_static(&mut self, item: NodeId, span: SimpleSpan) -> Option<Const> {
+        let entry = &self.statics[&item];
+        let (what, name, decl_span) = (entry.what, entry.name, entry.span);
+        if entry.value.is_none() {
+            self.errors.push(
+                SemError::new(
+                    "E0326",
+                    span,
+                    format!(
+                        "static `{name}` has no initial value, so cannot be used in a constant"
+                    ),
+                )
+                .with_label(decl_span, "declared here".into()),
+            );
+            return None;
+        }
+        if self.active.contains(&item) {
+            self.errors.push(
+                SemError::new(
+                    "E0327",
+                    span,
+                    format!("the value of {what} `{name}` depends on itself"),
+                )
+                .with_label(decl_span, "declared here".into()),
+            );
+            return None;
+        }
+        self.static_value(item)
+    }
+
+    /// The number of elements given by `len`, written at `span`.
+    fn length(&mut self, len: Length, span: SimpleSpan) -> Option<usize> {
+        let name = match len {
+            Length::Known(len) => return Some(len),
+            Length::Named(name) => name,
+        };
+        if let Some(&len) = self.lengths.get(&name) {
+            return len;
+        }
+        let len = match self.globals.get(&name) {
+            Some(&Binding::Static(item)) => match self.use_static(item, span) {
+                Some(Const::Int(len)) if len >= 0 => Some(len as usize),
+                Some(Const::Int(len)) => {
+                    self.error(
+                        "E0328",
+                        span,
+                        format!("array length `{name}` is negative: {len}"),
+                    );
+                    None
+                }
+                Some(_) => {
+                    self.error(
+                        "E0328",
+                        span,
+                        format!("array length `{name}` is not an `int`"),
+                    );
+                    None
+                }
+                None => None,
+            },
+            Some(_) => {
+                self.error(
+                    "E0326",
+                    span,
+                    format!("`{name}` is a function, not a constant"),
+                );
+                None
+            }
+            // Reported by name resolution.
+            None => None,
+        };
+        self.lengths.insert(name, len);
+        len
+    }
+
+    /// Reports `expr` as not being constant.
+    fn not_const(&mut self, expr: &Expr, what: &str) -> Option<Const> {
+        self.error(
+            "E0326",
+            expr.span,
+            format!("{what} cannot be used in a constant"),
+        );
+        None
+    }
+
+    /// Evaluates `expr`, returning [`None`] if it has no constant value.
+    fn eval(&mut self, expr: &Expr) -> Option<Const> {
+        match &expr.kind {
+            ExprKind::Invalid => None,
+            ExprKind::Literal(lit) => match *lit.value {
+                Value::Int(v) => Some(Const::Int(v as i32)),
+                Value::Char(c) => Some(Const::Char(c as u8)),
+                Value::Bool(b) => Some(Const::Bool(b)),
+                Value::Str(s) => Some(Const::Str(s)),
+                Value::Invalid => None,
+            },
+            ExprKind::Ident(name) => match self.resolutions.values.get(&expr.id) {
+                Some(&Binding::Static(item)) => self.use_static(item, expr.span),
+                Some(Binding::Fn(_)) => self.not_const(expr, &format!("the function `{name}`")),
+                // Statics are only evaluated outside of functions, where there are no locals.
+                Some(_) | None => None,
+            },
+            ExprKind::Unary(op, inner) => {
+                // A negated literal folds as it does at run time, so that `-2147483648` is valid.
+                if let (UnaryOp::Neg, ExprKind::Literal(_)) = (op, &inner.kind) {
+                    return expr.fold_int().map(Const::Int);
+                }
+                let value = self.eval(inner)?;
+                match (op, value) {
+                    (UnaryOp::Neg, Const::Int(v)) => match v.checked_neg() {
+                        Some(v) => Some(Const::Int(v)),
+                        None => {
+                            self.error(
+                                "E0324",
+                                expr.span,
+                                format!("attempt to compute `-({v})`, which would overflow"),
+                            );
+                            None
+                        }
+                    },
+                    (UnaryOp::Plus, Const::Int(v)) => Some(Const::Int(v)),
+                    (UnaryOp::BitNot, Const::Int(v)) => Some(Const::Int(!v)),
+                    (UnaryOp::Not, Const::Bool(b)) => Some(Const::Bool(!b)),
+                    _ => None,
+                }
+            }
+            ExprKind::BinOp(lhs, op, rhs) => self.binop(expr, lhs, *op, rhs),
+            ExprKind::Typecast(ty, inner) => {
+                let value = self.eval(inner)?;
+                match (value, &*ty.kind) {
+                    (Const::Int(v), TyKind::Primitive(Primitive::Char)) => {
+                        Some(Const::Char(v as u8))
+                    }
+                    (Const::Int(v), TyKind::Primitive(Primitive::Bool)) => {
+                        Some(Const::Bool(v != 0))
+                    }
+                    (Const::Char(c), TyKind::Primitive(Primitive::Int)) => {
+                        Some(Const::Int(c as i32))
+                    }
+                    (Const::Char(c), TyKind::Primitive(Primitive::Bool)) => {
+                        Some(Const::Bool(c != 0))
+                    }
+                    (Const::Bool(b), TyKind::Primitive(Primitive::Int)) => {
+                        Some(Const::Int(b as i32))
+                    }
+                    (Const::Bool(b), TyKind::Primitive(Primitive::Char)) => {
+                        Some(Const::Char(b as u8))
+                    }
+                    (value @ Const::Int(_), TyKind::Primitive(Primitive::Int))
+                    | (value @ Const::Char(_), TyKind::Primitive(Primitive::Char))
+                    | (value @ Const::Bool(_), TyKind::Primitive(Primitive::Bool))
+                    | (value @ Const::Str(_), TyKind::Pointer(_)) => Some(value),
+                    (Const::Array(_), TyKind::Pointer(_)) => {
+                        self.not_const(expr, "casts from arrays to pointers")
+                    }
+                    (_, TyKind::Infer) => self.not_const(expr, "casts to `_`"),
+                    _ => None,
+                }
+            }
+            ExprKind::StructLit(ty, fields) => {
+                let TyKind::Struct(name) = *ty.kind else {
+                    return None;
+                };
+                let mut values = vec![];
+                for field in fields {
+                    values.push((field.name, self.eval(&field.value)?));
+                }
+                Some(Const::Struct(name, values))
+            }
+            ExprKind::ArrayLit(elems) => {
+                let mut values = vec![];
+                for elem in elems {
+                    values.push(self.eval(elem)?);
+                }
+                Some(Const::Array(values))
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                let value = self.eval(elem)?;
+                let len = self.length(*len, expr.span)?;
+                Some(Const::Array(vec![value; len]))
+            }
+            ExprKind::CallFn(_) => self.not_const(expr, "function calls"),
+            ExprKind::Assign(..) | ExprKind::CompoundAssign(..) => {
+                self.not_const(expr, "assignments")
+            }
+            ExprKind::Ref(_) => self.not_const(expr, "references"),
+            ExprKind::Deref(_) => self.not_const(expr, "dereferences"),
+            ExprKind::Index(..) => self.not_const(expr, "indexing"),
+            ExprKind::FieldAccess(..) => self.not_const(expr, "field accesses"),
+        }
+    }
+
+    fn binop(&mut self, expr: &Expr, lhs: &Expr, op: Operator, rhs: &Expr) -> Option<Const> {
+        let l = self.eval(lhs)?;
+        // Only evaluate the right-hand side of `&&` and `||` if it is needed, as at run time.
+        match (op, &l) {
+            (Operator::And, Const::Bool(false)) | (Operator::Or, Const::Bool(true)) => {
+                return Some(l);
+            }
+            (Operator::And | Operator::Or, Const::Bool(_)) => {
+                return match self.eval(rhs)? {
+                    r @ Const::Bool(_) => Some(r),
+                    _ => None,
+                };
+            }
+            _ => (),
+        }
+        let r = self.eval(rhs)?;
+        let ordering = match (&l, &r) {
+            (Const::Int(l), Const::Int(r)) => l.cmp(r),
+            (Const::Char(l), Const::Char(r)) => l.cmp(r),
+            (Const::Bool(l), Const::Bool(r)) => l.cmp(r),
+            (Const::Str(_), Const::Str(_)) => {
+                return self.not_const(expr, "comparisons of pointers");
+            }
+            _ => return None,
+        };
+        let value = match op {
+            Operator::Lt => Const::Bool(ordering.is_lt()),
+            Operator::Le => Const::Bool(ordering.is_le()),
+            Operator::Gt => Const::Bool(ordering.is_gt()),
+            Operator::Ge => Const::Bool(ordering.is_ge()),
+            Operator::Eq => Const::Bool(ordering.is_eq()),
+            Operator::Ne => Const::Bool(ordering.is_ne()),
+            _ => {
+                let (Const::Int(l), Const::Int(r)) = (l, r) else {
+                    return None;
+                };
+                let value = match op {
+                    Operator::Add => l.checked_add(r),
+                    Operator::Minus => l.checked_sub(r),
+                    Operator::Times => l.checked_mul(r),
+                    Operator::Div | Operator::Mod if r == 0 => {
+                        self.error(
+                            "E0325",
+                            expr.span,
+                            format!("attempt to compute `{l} {op} 0`, which divides by zero"),
+                        );
+                        return None;
+                    }
+                    Operator::Div => l.checked_div(r),
+                    Operator::Mod => l.checked_rem(r),
+                    Operator::BitOr => Some(l | r),
+                    Operator::BitXor => Some(l ^ r),
+                    Operator::BitAnd => Some(l & r),
+                    // As at run time, only the low five bits of the shift amount count.
+                    Operator::Shl => Some(l.wrapping_shl(r as u32)),
+                    Operator::Shr => Some(l.wrapping_shr(r as u32)),
+                    _ => return None,
+                };
+                match value {
+                    Some(value) => Const::Int(value),
+                    None => {
+                        self.error(
+                            "E0324",
+                            expr.span,
+                            format!("attempt to compute `{l} {op} {r}`, which would overflow"),
+                        );
+                        return None;
+                    }
+                }
+            }
+        };
+        Some(value)
+    }
+
+    /// Replaces every named length in `item`.
+    fn fill_item(&mut self, item: &mut Item) {
+        match &mut item.kind {
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                self.fill_ty(&mut decl.ty);
+                if let Some(value) = &mut decl.value {
+                    self.fill_expr(value);
+                }
+            }
+            ItemKind::StructDecl(decl) => {
+                for field in &mut decl.fields {
+                    self.fill_ty(&mut field.ty);
+                }
+            }
+            ItemKind::FnDecl(decl) => {
+                self.fill_ty(&mut decl.sig.ty);
+                for param in &mut decl.sig.params {
+                    self.fill_ty(&mut param.ty);
+                }
+            }
+            ItemKind::FnDefn(defn) => {
+                self.fill_ty(&mut defn.sig.ty);
+                for param in &mut defn.sig.params {
+                    self.fill_ty(&mut param.ty);
+                }
+                self.fill_stmt(&mut defn.block);
+            }
+            ItemKind::Invalid => (),
+        }
+    }
+
+    /// Replaces every named length in `ty`, keeping its id and span. Lengths that have no value
+    /// become zero, since their errors stop analysis before the lengths are used.
+    fn fill_ty(&mut self, ty: &mut Ty) {
+        let kind = match (*ty.kind).clone() {
+            TyKind::Pointer(mut inner) => {
+                self.fill_ty(&mut inner);
+                TyKind::Pointer(inner)
+            }
+            TyKind::Array(len, mut inner) => {
+                let len = self.length(len, ty.span).unwrap_or_default();
+                self.fill_ty(&mut inner);
+                TyKind::Array(len.into(), inner)
+            }
+            TyKind::Primitive(_) | TyKind::Void | TyKind::Struct(_) | TyKind::Infer => return,
+        };
+        ty.kind = Arc::new(kind);
+    }
+
+    fn fill_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Block(block) => {
+                for stmt in &mut block.stmts {
+                    self.fill_stmt(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                self.fill_ty(&mut local.ty);
+                if let Some(value) = &mut local.value {
+                    self.fill_expr(value);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &mut pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                self.fill_expr(start);
+                self.fill_expr(end);
+                self.fill_stmt(body);
+            }
+            StmtKind::While(cond, body) => {
+                self.fill_expr(cond);
+                self.fill_stmt(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.fill_expr(cond);
+                self.fill_stmt(then);
+                if let Some(els) = els {
+                    self.fill_stmt(els);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.fill_expr(expr),
+            StmtKind::Return(None) | StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn fill_expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Invalid | ExprKind::Literal(_) | ExprKind::Ident(_) => (),
+            ExprKind::Assign(lhs, rhs)
+            | ExprKind::CompoundAssign(lhs, _, rhs)
+            | ExprKind::BinOp(lhs, _, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.fill_expr(lhs);
+                self.fill_expr(rhs);
+            }
+            ExprKind::Unary(_, inner)
+            | ExprKind::Deref(inner)
+            | ExprKind::Ref(inner)
+            | ExprKind::FieldAccess(inner, _) => self.fill_expr(inner),
+            ExprKind::CallFn(call) => {
+                self.fill_expr(&mut call.name);
+                for arg in &mut call.args {
+                    self.fill_expr(arg);
+                }
+            }
+            ExprKind::Typecast(ty, inner) => {
+                self.fill_ty(ty);
+                self.fill_expr(inner);
+            }
+            ExprKind::StructLit(_, fields) => {
+                for field in fields {
+                    self.fill_expr(&mut field.value);
+                }
+            }
+            ExprKind::ArrayLit(elems) => {
+                for elem in elems {
+                    self.fill_expr(elem);
+                }
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                *len = self.length(*len, expr.span).unwrap_or_default().into();
+                self.fill_expr(elem);
+            }
+        }
+    }
+}
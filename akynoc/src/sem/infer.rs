@@ -0,0 +1,548 @@
+//! Local type inference.
+//!
+//! Resolves every [`TyKind::Infer`] placeholder left by the parser, whether a whole type
+//! (`let x := 1;`, `static x = 1;`) or part of one (`let a: _[4];`, `let p: &_ = &x;`). Each
+//! placeholder becomes a type variable, and the program is walked once, unifying the types of
+//! declarations with those of their initializers and uses. Unification failures are left for the
+//! [type checker](super::typeck) to report, so this pass only reports placeholders that remain
+//! ambiguous.
+//!
+//! Placeholders are not allowed in function signatures or struct fields, which are the types
+//! inference starts from.
+use std::collections::HashMap;
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    ast::{
+        exprs::{Expr, ExprKind, Operator, UnaryOp, Value},
+        functions::FnSig,
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        structs::StructDecl,
+        types::{Ident, Primitive, Ty, TyKind},
+        Item, ItemKind,
+    },
+    sem::{
+        resolve::{Binding, Resolutions},
+        SemError,
+    },
+    util::NodeId,
+};
+
+/// A type that may contain type variables.
+#[derive(Clone, Debug)]
+enum InferTy {
+    Var(usize),
+    Primitive(Primitive),
+    Void,
+    Struct(Ident),
+    Pointer(Box<InferTy>),
+    Array(usize, Box<InferTy>),
+}
+
+/// A constraint that can only be solved once the shape of `base` is known.
+enum Deferred {
+    /// `base[_]` has type `elem`.
+    Index { base: InferTy, elem: InferTy },
+    /// `base.field` has type `ty`.
+    Field {
+        base: InferTy,
+        field: Ident,
+        ty: InferTy,
+    },
+}
+
+pub struct Inference<'ast> {
+    resolutions: &'ast Resolutions,
+    structs: HashMap<NodeId, &'ast StructDecl>,
+    fns: HashMap<NodeId, &'ast FnSig>,
+    /// Substitution for each type variable, indexed by variable.
+    vars: Vec<Option<InferTy>>,
+    /// Types of locals, statics and loop variables, keyed as in [`Binding`].
+    decls: HashMap<NodeId, InferTy>,
+    /// Every node whose type contains a placeholder, along with a description of the node and its
+    /// (partially) inferred type.
+    sites: Vec<(NodeId, SimpleSpan, String, InferTy)>,
+    deferred: Vec<Deferred>,
+    errors: Vec<SemError>,
+    /// Return type of the function being walked.
+    ret: InferTy,
+}
+
+impl<'ast> Inference<'ast> {
+    /// Infers every placeholder in `items`. Returns a map from the [`NodeId`] of each declaration
+    /// and cast containing a placeholder to its inferred [`Ty`], along with any errors.
+    ///
+    /// Locals are keyed by their [`Stmt`], statics by their [`Item`], and casts by their [`Expr`].
+    pub fn infer(
+        items: &'ast [Item],
+        resolutions: &'ast Resolutions,
+    ) -> (HashMap<NodeId, Ty>, Vec<SemError>) {
+        let mut inf = Self {
+            resolutions,
+            structs: HashMap::new(),
+            fns: HashMap::new(),
+            vars: vec![],
+            decls: HashMap::new(),
+            sites: vec![],
+            deferred: vec![],
+            errors: vec![],
+            ret: InferTy::Void,
+        };
+        for item in items {
+            match &item.kind {
+                ItemKind::StructDecl(decl) => {
+                    for field in &decl.fields {
+                        inf.forbid_placeholder(&field.ty, "struct fields");
+                    }
+                    inf.structs.insert(item.id, decl);
+                }
+                ItemKind::FnDecl(decl) => {
+                    inf.check_sig(&decl.sig);
+                    inf.fns.insert(item.id, &decl.sig);
+                }
+                ItemKind::FnDefn(defn) => {
+                    inf.check_sig(&defn.sig);
+                    inf.fns.insert(item.id, &defn.sig);
+                }
+                ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                    let what = match item.kind {
+                        ItemKind::Const(_) => "constant",
+                        _ => "static",
+                    };
+                    let ty = inf.declare(
+                        item.id,
+                        item.span,
+                        &format!("{what} `{}`", decl.ident),
+                        &decl.ty,
+                    );
+                    inf.decls.insert(item.id, ty);
+                }
+                ItemKind::Invalid => (),
+            }
+        }
+        // Initial values may use statics declared after them.
+        for item in items {
+            if let ItemKind::Static(decl) | ItemKind::Const(decl) = &item.kind
+                && let Some(value) = &decl.value
+            {
+                let value_ty = inf.walk_expr(value);
+                let ty = inf.decls[&item.id].clone();
+                inf.unify(&ty, &value_ty);
+            }
+        }
+        for item in items {
+            if let ItemKind::FnDefn(defn) = &item.kind {
+                inf.ret = inf.instantiate(&defn.sig.ty);
+                inf.walk_stmt(&defn.block);
+                if !matches!(inf.ret, InferTy::Void) {
+                    inf.walk_tail(&defn.block);
+                }
+            }
+        }
+        inf.solve_deferred();
+
+        let mut inferred = HashMap::new();
+        for (node, span, what, ty) in std::mem::take(&mut inf.sites) {
+            match inf.zonk(&ty) {
+                Some(ty) => {
+                    inferred.insert(node, ty);
+                }
+                None => inf.errors.push(SemError::new(
+                    "E0319",
+                    span,
+                    format!("type annotations needed for {what}"),
+                )),
+            }
+        }
+        (inferred, inf.errors)
+    }
+
+    fn check_sig(&mut self, sig: &FnSig) {
+        self.forbid_placeholder(&sig.ty, "function signatures");
+        for param in &sig.params {
+            self.forbid_placeholder(&param.ty, "function signatures");
+        }
+    }
+
+    fn forbid_placeholder(&mut self, ty: &Ty, place: &str) {
+        if ty.has_infer() {
+            self.errors.push(SemError::new(
+                "E0320",
+                ty.span,
+                format!("the placeholder `_` is not allowed in {place}"),
+            ));
+        }
+    }
+
+    /// Converts `ty`, recording `node` as an inference site if it contains a placeholder.
+    fn declare(&mut self, node: NodeId, span: SimpleSpan, what: &str, ty: &Ty) -> InferTy {
+        let ty = self.instantiate(ty);
+        if self.has_vars(&ty) {
+            self.sites.push((node, span, what.to_string(), ty.clone()));
+        }
+        ty
+    }
+
+    fn has_vars(&self, ty: &InferTy) -> bool {
+        match ty {
+            InferTy::Var(_) => true,
+            InferTy::Pointer(inner) | InferTy::Array(_, inner) => self.has_vars(inner),
+            _ => false,
+        }
+    }
+
+    fn fresh(&mut self) -> InferTy {
+        self.vars.push(None);
+        InferTy::Var(self.vars.len() - 1)
+    }
+
+    fn instantiate(&mut self, ty: &Ty) -> InferTy {
+        match &*ty.kind {
+            TyKind::Primitive(p) => InferTy::Primitive(*p),
+            TyKind::Void => InferTy::Void,
+            TyKind::Struct(name) => InferTy::Struct(*name),
+            TyKind::Pointer(inner) => InferTy::Pointer(Box::new(self.instantiate(inner))),
+            TyKind::Array(size, inner) => {
+                InferTy::Array(size.get(), Box::new(self.instantiate(inner)))
+            }
+            TyKind::Infer => self.fresh(),
+        }
+    }
+
+    /// The declared type of the field `field` of the struct `name`, if both exist.
+    fn field_ty(&self, name: Ident, field: Ident) -> Option<Ty> {
+        let item = self.resolutions.struct_decls.get(&name)?;
+        let decl = self.structs.get(item)?;
+        decl.fields
+            .iter()
+            .find(|f| f.name == field)
+            .map(|f| f.ty.clone())
+    }
+
+    /// Follows variable substitutions until reaching an unbound variable or a type constructor.
+    fn shallow(&self, ty: &InferTy) -> InferTy {
+        let mut ty = ty.clone();
+        while let InferTy::Var(v) = ty {
+            match &self.vars[v] {
+                Some(sub) => ty = sub.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn occurs(&self, var: usize, ty: &InferTy) -> bool {
+        match self.shallow(ty) {
+            InferTy::Var(v) => v == var,
+            InferTy::Pointer(inner) | InferTy::Array(_, inner) => self.occurs(var, &inner),
+            _ => false,
+        }
+    }
+
+    /// Unifies two types, returning whether they could be made equal.
+    fn unify(&mut self, a: &InferTy, b: &InferTy) -> bool {
+        match (self.shallow(a), self.shallow(b)) {
+            (InferTy::Var(x), InferTy::Var(y)) if x == y => true,
+            (InferTy::Var(x), ty) | (ty, InferTy::Var(x)) => {
+                if self.occurs(x, &ty) {
+                    return false;
+                }
+                self.vars[x] = Some(ty);
+                true
+            }
+            (InferTy::Primitive(p1), InferTy::Primitive(p2)) => p1 == p2,
+            (InferTy::Void, InferTy::Void) => true,
+            (InferTy::Struct(s1), InferTy::Struct(s2)) => s1 == s2,
+            (InferTy::Pointer(t1), InferTy::Pointer(t2)) => self.unify(&t1, &t2),
+            (InferTy::Array(n1, t1), InferTy::Array(n2, t2)) => n1 == n2 && self.unify(&t1, &t2),
+            _ => false,
+        }
+    }
+
+    /// Converts a fully inferred type back into a [`Ty`], or [`None`] if a variable is unbound.
+    fn zonk(&self, ty: &InferTy) -> Option<Ty> {
+        let kind = match self.shallow(ty) {
+            InferTy::Var(_) => return None,
+            InferTy::Primitive(p) => TyKind::Primitive(p),
+            InferTy::Void => TyKind::Void,
+            InferTy::Struct(name) => TyKind::Struct(name),
+            InferTy::Pointer(inner) => TyKind::Pointer(self.zonk(&inner)?),
+            InferTy::Array(size, inner) => TyKind::Array(size.into(), self.zonk(&inner)?),
+        };
+        Some(kind.into())
+    }
+
+    fn literal(&self, value: Value) -> InferTy {
+        match value {
+            Value::Int(_) | Value::Invalid => InferTy::Primitive(Primitive::Int),
+            Value::Char(_) => InferTy::Primitive(Primitive::Char),
+            Value::Str(_) => InferTy::Pointer(Box::new(InferTy::Primitive(Primitive::Char))),
+            Value::Bool(_) => InferTy::Primitive(Primitive::Bool),
+        }
+    }
+
+    fn walk_tail(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                if let Some(last) = block.stmts.last() {
+                    self.walk_tail(last);
+                }
+            }
+            StmtKind::If(_, then, Some(els)) => {
+                self.walk_tail(then);
+                self.walk_tail(els);
+            }
+            StmtKind::Expr(expr) => {
+                let ty = self.walk_expr(expr);
+                let ret = self.ret.clone();
+                self.unify(&ret, &ty);
+            }
+            _ => (),
+        }
+    }
+
+    fn walk_stmt(&mut self, stmt: &Stmt) {
+        let int = InferTy::Primitive(Primitive::Int);
+        let bool = InferTy::Primitive(Primitive::Bool);
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.walk_stmt(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                let ty = self.declare(
+                    stmt.id,
+                    stmt.span,
+                    &format!("local `{}`", local.name),
+                    &local.ty,
+                );
+                if let Some(value) = &local.value {
+                    let value_ty = self.walk_expr(value);
+                    self.unify(&ty, &value_ty);
+                }
+                self.decls.insert(stmt.id, ty);
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                for bound in [start, end] {
+                    let ty = self.walk_expr(bound);
+                    self.unify(&ty, &int);
+                }
+                self.decls.insert(pattern.id, int);
+                self.walk_stmt(body);
+            }
+            StmtKind::While(cond, body) => {
+                let ty = self.walk_expr(cond);
+                self.unify(&ty, &bool);
+                self.walk_stmt(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                let ty = self.walk_expr(cond);
+                self.unify(&ty, &bool);
+                self.walk_stmt(then);
+                if let Some(els) = els {
+                    self.walk_stmt(els);
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.walk_expr(expr);
+            }
+            StmtKind::Return(Some(value)) => {
+                let ty = self.walk_expr(value);
+                let ret = self.ret.clone();
+                self.unify(&ret, &ty);
+            }
+            StmtKind::Return(None) | StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn walk_expr(&mut self, expr: &Expr) -> InferTy {
+        let int = InferTy::Primitive(Primitive::Int);
+        let bool = InferTy::Primitive(Primitive::Bool);
+        match &expr.kind {
+            ExprKind::Invalid => self.fresh(),
+            ExprKind::Literal(lit) => self.literal(*lit.value),
+            ExprKind::Ident(_) => match self.resolutions.values.get(&expr.id) {
+                Some(Binding::Local(id) | Binding::Static(id) | Binding::Pattern(id)) => {
+                    match self.decls.get(id) {
+                        Some(ty) => ty.clone(),
+                        None => self.fresh(),
+                    }
+                }
+                Some(Binding::Param { item, index }) => {
+                    let ty = self.fns[item].params[*index].ty.clone();
+                    self.instantiate(&ty)
+                }
+                Some(Binding::Fn(_)) | None => self.fresh(),
+            },
+            ExprKind::Assign(lhs, rhs) => {
+                let lhs_ty = self.walk_expr(lhs);
+                let rhs_ty = self.walk_expr(rhs);
+                self.unify(&lhs_ty, &rhs_ty);
+                lhs_ty
+            }
+            ExprKind::CompoundAssign(lhs, _, rhs) => {
+                let lhs_ty = self.walk_expr(lhs);
+                let rhs_ty = self.walk_expr(rhs);
+                self.unify(&lhs_ty, &int);
+                self.unify(&rhs_ty, &int);
+                int
+            }
+            ExprKind::BinOp(lhs, op, rhs) => {
+                let lhs_ty = self.walk_expr(lhs);
+                let rhs_ty = self.walk_expr(rhs);
+                match op {
+                    Operator::Lt
+                    | Operator::Le
+                    | Operator::Gt
+                    | Operator::Ge
+                    | Operator::Eq
+                    | Operator::Ne => {
+                        self.unify(&lhs_ty, &rhs_ty);
+                        bool
+                    }
+                    Operator::Or | Operator::And => {
+                        self.unify(&lhs_ty, &bool);
+                        self.unify(&rhs_ty, &bool);
+                        bool
+                    }
+                    _ => {
+                        self.unify(&lhs_ty, &int);
+                        self.unify(&rhs_ty, &int);
+                        int
+                    }
+                }
+            }
+            ExprKind::Unary(op, inner) => {
+                let ty = self.walk_expr(inner);
+                let operand = match op {
+                    UnaryOp::Not => bool,
+                    UnaryOp::Neg | UnaryOp::Plus | UnaryOp::BitNot => int,
+                };
+                self.unify(&ty, &operand);
+                operand
+            }
+            ExprKind::CallFn(call) => {
+                let arg_tys = call
+                    .args
+                    .iter()
+                    .map(|arg| self.walk_expr(arg))
+                    .collect::<Vec<_>>();
+                let Some(&Binding::Fn(item)) = self.resolutions.values.get(&call.name.id) else {
+                    return self.fresh();
+                };
+                let sig = self.fns[&item];
+                for (param, arg_ty) in sig.params.iter().zip(arg_tys) {
+                    let param_ty = self.instantiate(&param.ty);
+                    self.unify(&param_ty, &arg_ty);
+                }
+                self.instantiate(&sig.ty)
+            }
+            ExprKind::Typecast(to, inner) => {
+                self.walk_expr(inner);
+                self.declare(expr.id, expr.span, &format!("cast to `{to}`"), to)
+            }
+            ExprKind::Deref(inner) => {
+                let ty = self.walk_expr(inner);
+                let pointee = self.fresh();
+                self.unify(&ty, &InferTy::Pointer(Box::new(pointee.clone())));
+                pointee
+            }
+            ExprKind::Ref(inner) => InferTy::Pointer(Box::new(self.walk_expr(inner))),
+            ExprKind::Index(arr, ind) => {
+                let base = self.walk_expr(arr);
+                let ind_ty = self.walk_expr(ind);
+                self.unify(&ind_ty, &int);
+                let elem = self.fresh();
+                self.deferred.push(Deferred::Index {
+                    base,
+                    elem: elem.clone(),
+                });
+                elem
+            }
+            ExprKind::StructLit(ty, fields) => {
+                let TyKind::Struct(name) = *ty.kind else {
+                    unreachable!("struct literal of a non-struct type");
+                };
+                for field in fields {
+                    let value_ty = self.walk_expr(&field.value);
+                    if let Some(field_ty) = self.field_ty(name, field.name) {
+                        let field_ty = self.instantiate(&field_ty);
+                        self.unify(&field_ty, &value_ty);
+                    }
+                }
+                InferTy::Struct(name)
+            }
+            ExprKind::ArrayLit(elems) => {
+                let elem_ty = self.fresh();
+                for elem in elems {
+                    let ty = self.walk_expr(elem);
+                    self.unify(&elem_ty, &ty);
+                }
+                InferTy::Array(elems.len(), Box::new(elem_ty))
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                let elem_ty = self.walk_expr(elem);
+                InferTy::Array(len.get(), Box::new(elem_ty))
+            }
+            ExprKind::FieldAccess(inner, field) => {
+                let base = self.walk_expr(inner);
+                let ty = self.fresh();
+                self.deferred.push(Deferred::Field {
+                    base,
+                    field: *field,
+                    ty: ty.clone(),
+                });
+                ty
+            }
+        }
+    }
+
+    /// Solves deferred constraints until no more progress can be made.
+    fn solve_deferred(&mut self) {
+        loop {
+            let pending = std::mem::take(&mut self.deferred);
+            let before = pending.len();
+            for constraint in pending {
+                match constraint {
+                    Deferred::Index { base, elem } => match self.shallow(&base) {
+                        InferTy::Pointer(inner) | InferTy::Array(_, inner) => {
+                            self.unify(&inner, &elem);
+                        }
+                        InferTy::Var(_) => self.deferred.push(Deferred::Index { base, elem }),
+                        _ => (),
+                    },
+                    Deferred::Field { base, field, ty } => {
+                        // A pointer to a struct is dereferenced automatically.
+                        let owner = match self.shallow(&base) {
+                            InferTy::Pointer(inner) => self.shallow(&inner),
+                            owner => owner,
+                        };
+                        match owner {
+                            InferTy::Struct(name) => {
+                                if let Some(field_ty) = self.field_ty(name, field) {
+                                    let field_ty = self.instantiate(&field_ty);
+                                    self.unify(&field_ty, &ty);
+                                }
+                            }
+                            InferTy::Var(_) => {
+                                self.deferred.push(Deferred::Field { base, field, ty })
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            if self.deferred.len() == before {
+                return;
+            }
+        }
+    }
+}
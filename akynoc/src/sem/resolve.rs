@@ -0,0 +1,420 @@
+//! Name resolution.
+//!
+//! Binds every [`ExprKind::Ident`] (including the name of every [`CallFn`]) to the declaration it
+//! refers to, and every [`TyKind::Struct`] to its [`StructDecl`]. Also checks that every
+//! [`Length::Named`] names a global, which [constant evaluation](super::consteval) later reads.
+//!
+//! Scoping rules:
+//! - Statics, constants and functions share a single global scope, and are visible throughout the
+//!   file. Array lengths and the values of statics and constants only see this scope.
+//!   Structs live in a separate namespace, also visible throughout the file.
+//! - Parameters are scoped to their function, and each [`Block`] and `for` loop opens a new scope.
+//! - Names must be unique within a scope. The only exception is a function, which may be declared
+//!   any number of times and defined once, as long as every [`FnSig`] agrees.
+//! - Locals may shadow globals and locals from enclosing blocks, but not the parameters of the
+//!   enclosing function.
+//!
+//! [`CallFn`]: crate::ast::exprs::CallFn
+//! [`Block`]: crate::ast::statements::Block
+use std::collections::{HashMap, HashSet};
+
+use chumsky::span::SimpleSpan;
+use serde::Serialize;
+
+use crate::{
+    ast::{
+        exprs::{Expr, ExprKind},
+        functions::FnSig,
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        structs::StructDecl,
+        types::{Ident, Length, Ty, TyKind},
+        Item, ItemKind,
+    },
+    sem::SemError,
+    util::NodeId,
+};
+
+/// The declaration a name is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Binding {
+    /// A [`Local`](crate::ast::statements::Local), identified by its [`Stmt`].
+    Local(NodeId),
+    /// The `index`th [`Param`](crate::ast::functions::Param) of the function [`Item`] `item`.
+    Param { item: NodeId, index: usize },
+    /// The variable bound by a `for` loop, identified by its
+    /// [`Pattern`](crate::ast::pattern::Pattern).
+    Pattern(NodeId),
+    /// A [`StaticDecl`](crate::ast::StaticDecl) of a `static` or `const` item, identified by its
+    /// [`Item`].
+    Static(NodeId),
+    /// A function, identified by the [`Item`] defining it, or by its first declaration if it is
+    /// never defined.
+    Fn(NodeId),
+}
+
+/// Side tables produced by name resolution.
+#[derive(Default)]
+pub struct Resolutions {
+    /// Maps the [`NodeId`] of each [`ExprKind::Ident`] to its [`Binding`].
+    pub values: HashMap<NodeId, Binding>,
+    /// Maps the [`NodeId`] of each [`Ty`] naming a struct (possibly behind pointers or arrays) to
+    /// the [`Item`] declaring that struct.
+    pub structs: HashMap<NodeId, NodeId>,
+    /// Maps each struct name to the [`Item`] declaring it.
+    pub struct_decls: HashMap<Ident, NodeId>,
+    /// Maps each [`Binding`] to the span of its declaration.
+    pub decl_spans: HashMap<Binding, SimpleSpan>,
+}
+
+/// A function signature seen while collecting globals.
+struct FnEntry<'ast> {
+    sig: &'ast FnSig,
+    item: NodeId,
+    span: SimpleSpan,
+    defined: bool,
+}
+
+pub struct Resolver<'ast> {
+    resolutions: Resolutions,
+    errors: Vec<SemError>,
+    /// `scopes[0]` is the global scope. While resolving a function body, `scopes[1]` holds its
+    /// parameters.
+    scopes: Vec<HashMap<Ident, (Binding, SimpleSpan)>>,
+    fns: HashMap<Ident, FnEntry<'ast>>,
+    struct_spans: HashMap<Ident, SimpleSpan>,
+}
+
+impl<'ast> Resolver<'ast> {
+    /// Resolves every name in `items`, returning the resolutions and any errors found.
+    pub fn resolve(items: &'ast [Item]) -> (Resolutions, Vec<SemError>) {
+        let mut resolver = Self {
+            resolutions: Resolutions::default(),
+            errors: vec![],
+            scopes: vec![HashMap::new()],
+            fns: HashMap::new(),
+            struct_spans: HashMap::new(),
+        };
+        for item in items {
+            resolver.collect_global(item);
+        }
+        for item in items {
+            resolver.resolve_item(item);
+        }
+        (resolver.resolutions, resolver.errors)
+    }
+
+    fn error(&mut self, code: &'static str, span: SimpleSpan, message: String) {
+        self.errors.push(SemError::new(code, span, message));
+    }
+
+    fn collect_global(&mut self, item: &'ast Item) {
+        match &item.kind {
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                self.declare(decl.ident, Binding::Static(item.id), item.span)
+            }
+            ItemKind::StructDecl(decl) => {
+                if let Some(prev) = self.struct_spans.insert(decl.name, item.span) {
+                    self.errors.push(
+                        SemError::new(
+                            "E0201",
+                            item.span,
+                            format!("struct `{}` is defined multiple times", decl.name),
+                        )
+                        .with_label(prev, "previously defined here".into()),
+                    );
+                } else {
+                    self.resolutions.struct_decls.insert(decl.name, item.id);
+                }
+            }
+            ItemKind::FnDecl(decl) => self.collect_fn(&decl.sig, item, false),
+            ItemKind::FnDefn(defn) => self.collect_fn(&defn.sig, item, true),
+            ItemKind::Invalid => (),
+        }
+    }
+
+    fn collect_fn(&mut self, sig: &'ast FnSig, item: &Item, defined: bool) {
+        let span = item.span;
+        let Some(prev) = self.fns.get_mut(&sig.name) else {
+            let entry = FnEntry {
+                sig,
+                item: item.id,
+                span,
+                defined,
+            };
+            self.fns.insert(sig.name, entry);
+            self.declare(sig.name, Binding::Fn(item.id), span);
+            return;
+        };
+        let prev_span = prev.span;
+        if prev.sig != sig {
+            self.errors.push(
+                SemError::new(
+                    "E0202",
+                    span,
+                    format!(
+                        "function `{}` does not match its previous declaration",
+                        sig.name
+                    ),
+                )
+                .with_label(prev_span, "previously declared here".into()),
+            );
+        } else if prev.defined && defined {
+            self.errors.push(
+                SemError::new(
+                    "E0203",
+                    span,
+                    format!("function `{}` is defined multiple times", sig.name),
+                )
+                .with_label(prev_span, "previously defined here".into()),
+            );
+        } else if defined {
+            let binding = Binding::Fn(item.id);
+            prev.item = item.id;
+            prev.span = span;
+            prev.defined = true;
+            self.scopes[0].insert(sig.name, (binding, span));
+            self.resolutions.decl_spans.insert(binding, span);
+        }
+    }
+
+    /// Declares `name` in the innermost scope, reporting duplicates and shadowed parameters.
+    fn declare(&mut self, name: Ident, binding: Binding, span: SimpleSpan) {
+        let depth = self.scopes.len() - 1;
+        if depth >= 2
+            && let Some(&(_, param)) = self.scopes[1].get(&name)
+        {
+            self.errors.push(
+                SemError::new(
+                    "E0204",
+                    span,
+                    format!("`{name}` shadows a parameter of the enclosing function"),
+                )
+                .with_label(param, "parameter declared here".into()),
+            );
+        }
+        if let Some((_, prev)) = self.scopes[depth].insert(name, (binding, span)) {
+            self.errors.push(
+                SemError::new(
+                    "E0205",
+                    span,
+                    format!("`{name}` is defined multiple times in the same scope"),
+                )
+                .with_label(prev, "previously defined here".into()),
+            );
+        }
+        self.resolutions.decl_spans.insert(binding, span);
+    }
+
+    fn lookup(&self, name: &Ident) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).map(|&(binding, _)| binding))
+    }
+
+    fn resolve_item(&mut self, item: &Item) {
+        match &item.kind {
+            ItemKind::Static(decl) | ItemKind::Const(decl) => {
+                self.resolve_ty(&decl.ty);
+                if let Some(value) = &decl.value {
+                    self.resolve_expr(value);
+                }
+            }
+            ItemKind::StructDecl(decl) => self.resolve_struct(decl, item.span),
+            ItemKind::FnDecl(decl) => {
+                self.resolve_sig(&decl.sig, item.id);
+                self.scopes.pop();
+            }
+            ItemKind::FnDefn(defn) => {
+                self.resolve_sig(&defn.sig, item.id);
+                self.resolve_stmt(&defn.block);
+                self.scopes.pop();
+            }
+            ItemKind::Invalid => (),
+        }
+    }
+
+    fn resolve_struct(&mut self, decl: &StructDecl, span: SimpleSpan) {
+        let mut seen = HashSet::new();
+        for field in &decl.fields {
+            if !seen.insert(field.name) {
+                self.error(
+                    "E0206",
+                    span,
+                    format!(
+                        "field `{}` is declared multiple times in struct `{}`",
+                        field.name, decl.name
+                    ),
+                );
+            }
+            self.resolve_ty(&field.ty);
+        }
+    }
+
+    /// Resolves a signature, opening a scope holding its parameters.
+    fn resolve_sig(&mut self, sig: &FnSig, item: NodeId) {
+        self.resolve_ty(&sig.ty);
+        self.scopes.push(HashMap::new());
+        for (index, param) in sig.params.iter().enumerate() {
+            self.resolve_ty(&param.ty);
+            self.declare(param.name, Binding::Param { item, index }, param.span);
+        }
+    }
+
+    fn resolve_ty(&mut self, ty: &Ty) {
+        let mut kind = &*ty.kind;
+        loop {
+            match kind {
+                TyKind::Pointer(inner) => kind = &inner.kind,
+                TyKind::Array(len, inner) => {
+                    self.resolve_length(*len, ty.span);
+                    kind = &inner.kind;
+                }
+                TyKind::Struct(name) => {
+                    match self.resolutions.struct_decls.get(name) {
+                        Some(&decl) => {
+                            self.resolutions.structs.insert(ty.id, decl);
+                        }
+                        None => {
+                            self.error("E0207", ty.span, format!("cannot find struct `{name}`"))
+                        }
+                    }
+                    return;
+                }
+                TyKind::Primitive(_) | TyKind::Void | TyKind::Infer => return,
+            }
+        }
+    }
+
+    /// Checks that a named length refers to a global, ignoring any locals in scope.
+    fn resolve_length(&mut self, len: Length, span: SimpleSpan) {
+        if let Length::Named(name) = len
+            && !self.scopes[0].contains_key(&name)
+        {
+            self.error(
+                "E0208",
+                span,
+                format!("cannot find value `{name}` in this scope"),
+            );
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                self.scopes.push(HashMap::new());
+                for stmt in &block.stmts {
+                    self.resolve_stmt(stmt);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::Local(local) => {
+                self.resolve_ty(&local.ty);
+                if let Some(value) = &local.value {
+                    self.resolve_expr(value);
+                }
+                self.declare(local.name, Binding::Local(stmt.id), stmt.span);
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(name, range) = &pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+                self.scopes.push(HashMap::new());
+                self.declare(*name, Binding::Pattern(pattern.id), pattern.span);
+                self.resolve_stmt(body);
+                self.scopes.pop();
+            }
+            StmtKind::While(cond, body) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(then);
+                if let Some(els) = els {
+                    self.resolve_stmt(els);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.resolve_expr(expr),
+            StmtKind::Return(None) | StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Invalid | ExprKind::Literal(_) => (),
+            ExprKind::Ident(name) => match self.lookup(name) {
+                Some(binding) => {
+                    self.resolutions.values.insert(expr.id, binding);
+                }
+                None => self.error(
+                    "E0208",
+                    expr.span,
+                    format!("cannot find value `{name}` in this scope"),
+                ),
+            },
+            ExprKind::CallFn(call) => {
+                match &call.name.kind {
+                    ExprKind::Ident(name) => match self.lookup(name) {
+                        Some(binding @ Binding::Fn(_)) => {
+                            self.resolutions.values.insert(call.name.id, binding);
+                        }
+                        Some(_) => self.error(
+                            "E0209",
+                            call.name.span,
+                            format!("`{name}` is not a function"),
+                        ),
+                        None => self.error(
+                            "E0210",
+                            call.name.span,
+                            format!("cannot find function `{name}` in this scope"),
+                        ),
+                    },
+                    _ => self.error(
+                        "E0209",
+                        call.name.span,
+                        format!("`{}` is not a function", call.name),
+                    ),
+                }
+                for arg in &call.args {
+                    self.resolve_expr(arg);
+                }
+            }
+            ExprKind::Assign(lhs, rhs)
+            | ExprKind::CompoundAssign(lhs, _, rhs)
+            | ExprKind::BinOp(lhs, _, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            ExprKind::Typecast(ty, expr) => {
+                self.resolve_ty(ty);
+                self.resolve_expr(expr);
+            }
+            ExprKind::Deref(expr)
+            | ExprKind::Ref(expr)
+            | ExprKind::Unary(_, expr)
+            | ExprKind::FieldAccess(expr, _) => self.resolve_expr(expr),
+            ExprKind::ArrayRepeat(elem, len) => {
+                self.resolve_expr(elem);
+                self.resolve_length(*len, expr.span);
+            }
+            ExprKind::StructLit(ty, fields) => {
+                self.resolve_ty(ty);
+                for field in fields {
+                    self.resolve_expr(&field.value);
+                }
+            }
+            ExprKind::ArrayLit(elems) => {
+                for elem in elems {
+                    self.resolve_expr(elem);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,792 @@
+//! Type checking.
+//!
+//! Gives every [`Expr`] a [`Ty`], and checks that every expression, statement and item is well
+//! typed. Runs after [name resolution](super::resolve), whose [`Resolutions`] it relies on to find
+//! the declaration behind each name.
+//!
+//! Typing rules:
+//! - Integer literals are `int`, character literals `char`, string literals `&char`, and `true`
+//!   and `false` are `bool`. Integer literals are at most `2147483647`, or `2147483648` directly
+//!   under prefix `-`.
+//! - Arithmetic and bitwise operators, prefix `-`, `+` and `~` included, take `int` operands and
+//!   produce `int`. Shifts only use the low five bits of their right operand, and `>>` keeps the
+//!   sign.
+//! - Logical operators, `!` included, take `bool` operands. Ordering comparisons take two `int`s
+//!   or two `char`s, and equality also accepts two `bool`s or two pointers of the same type.
+//!   Logical operators and comparisons produce `bool`.
+//! - `&void` is compatible with every pointer type when assigning, passing arguments or
+//!   returning. Otherwise, types must match exactly.
+//! - Only names, dereferences, indexing and field accesses are lvalues, and only lvalues may be
+//!   assigned to or have their address taken. Arrays cannot be assigned as a whole.
+//! - Constants and locals declared without `mut` cannot be assigned to, nor can their fields or
+//!   elements. Statics and parameters are always mutable.
+//! - Compound assignments such as `x += y` apply to an `int` lvalue and an `int`.
+//! - Fields are accessed on a struct, or through a single pointer to one, which is dereferenced
+//!   automatically.
+//! - A struct literal gives every field of its struct exactly once. An array literal's elements
+//!   all have the type of the first, and its length is part of its type.
+//! - Casts may convert between any two of `int`, `char` and `bool`, between any two pointer types,
+//!   and from an array to a pointer to its element type. Casting to `bool` tests for non-zero.
+//! - Conditions must be `bool`, and range bounds and indices `int`.
+//! - `break` and `continue` may only appear inside a loop.
+//! - A struct may not contain itself, other than behind a pointer.
+//! - In a non-void function, a final expression statement is an implicit return. A final block or
+//!   `if`/`else` returns through its own final statements. Every path through the function must
+//!   end in a `return` or an implicit return.
+use std::collections::{HashMap, HashSet};
+
+use chumsky::span::SimpleSpan;
+
+use crate::{
+    ast::{
+        exprs::{CallFn, Expr, ExprKind, FieldInit, Operator, UnaryOp, Value},
+        functions::FnSig,
+        pattern::{PatternKind, Range},
+        statements::{Stmt, StmtKind},
+        structs::StructDecl,
+        types::{Ident, Primitive, Ty, TyKind},
+        Item, ItemKind, StaticDecl,
+    },
+    sem::{
+        resolve::{Binding, Resolutions},
+        SemError,
+    },
+    util::NodeId,
+};
+
+/// Side tables produced by type checking.
+#[derive(Default)]
+pub struct TypeInfo {
+    /// Maps the [`NodeId`] of each [`Expr`] to its type.
+    pub exprs: HashMap<NodeId, Ty>,
+    /// Maps the [`NodeId`] of each variable declaration to the variable's type. Variables are
+    /// identified as in [`Binding`]: locals by their [`Stmt`], statics by their [`Item`], and
+    /// loop variables by their [`Pattern`](crate::ast::pattern::Pattern).
+    pub decls: HashMap<NodeId, Ty>,
+}
+
+pub struct TypeChecker<'ast> {
+    resolutions: &'ast Resolutions,
+    inferred: &'ast HashMap<NodeId, Ty>,
+    structs: HashMap<NodeId, &'ast StructDecl>,
+    fns: HashMap<NodeId, &'ast FnSig>,
+    info: TypeInfo,
+    errors: Vec<SemError>,
+    /// Return type of the function being checked.
+    ret: Option<Ty>,
+    /// Number of loops enclosing the statement being checked.
+    loops: u32,
+    /// Constants and locals declared without `mut`, identified as in [`Binding`].
+    immutable: HashSet<NodeId>,
+}
+
+fn int() -> Ty {
+    TyKind::Primitive(Primitive::Int).into()
+}
+
+fn char() -> Ty {
+    TyKind::Primitive(Primitive::Char).into()
+}
+
+fn bool() -> Ty {
+    TyKind::Primitive(Primitive::Bool).into()
+}
+
+fn is_int(ty: &Ty) -> bool {
+    *ty.kind == TyKind::Primitive(Primitive::Int)
+}
+
+fn is_bool(ty: &Ty) -> bool {
+    *ty.kind == TyKind::Primitive(Primitive::Bool)
+}
+
+/// Whether a value of type `found` may be stored where `expected` is required.
+fn compatible(expected: &Ty, found: &Ty) -> bool {
+    match (&*expected.kind, &*found.kind) {
+        (TyKind::Pointer(e), TyKind::Pointer(f)) => {
+            e == f || *e.kind == TyKind::Void || *f.kind == TyKind::Void
+        }
+        _ => expected == found,
+    }
+}
+
+fn is_lvalue(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Ident(_) | ExprKind::Deref(_) | ExprKind::Index(..) | ExprKind::FieldAccess(..)
+    )
+}
+
+impl<'ast> TypeChecker<'ast> {
+    /// Type checks `items`, returning the types found and any errors. Every placeholder must
+    /// already have been resolved by [inference](super::infer), with the results in `inferred`.
+    pub fn check(
+        items: &'ast [Item],
+        resolutions: &'ast Resolutions,
+        inferred: &'ast HashMap<NodeId, Ty>,
+    ) -> (TypeInfo, Vec<SemError>) {
+        let mut checker = Self {
+            resolutions,
+            inferred,
+            structs: HashMap::new(),
+            fns: HashMap::new(),
+            info: TypeInfo::default(),
+            errors: vec![],
+            ret: None,
+            loops: 0,
+            immutable: HashSet::new(),
+        };
+        for item in items {
+            match &item.kind {
+                ItemKind::StructDecl(decl) => {
+                    checker.structs.insert(item.id, decl);
+                }
+                ItemKind::FnDecl(decl) => {
+                    checker.fns.insert(item.id, &decl.sig);
+                }
+                ItemKind::FnDefn(defn) => {
+                    checker.fns.insert(item.id, &defn.sig);
+                }
+                ItemKind::Const(_) => {
+                    checker.immutable.insert(item.id);
+                }
+                ItemKind::Static(_) | ItemKind::Invalid => (),
+            }
+        }
+        // Statics may be used before they are declared, so their types are needed up front.
+        for item in items {
+            if let ItemKind::Static(decl) | ItemKind::Const(decl) = &item.kind {
+                let ty = checker.declared(item.id, &decl.ty);
+                checker.info.decls.insert(item.id, ty);
+            }
+        }
+        for item in items {
+            if let ItemKind::Static(decl) | ItemKind::Const(decl) = &item.kind {
+                checker.check_static(item, decl);
+            }
+        }
+        for item in items {
+            checker.check_item(item);
+        }
+        (checker.info, checker.errors)
+    }
+
+    fn error(&mut self, code: &'static str, span: SimpleSpan, message: String) {
+        self.errors.push(SemError::new(code, span, message));
+    }
+
+    fn check_static(&mut self, item: &Item, decl: &StaticDecl) {
+        let ty = self.info.decls[&item.id].clone();
+        let value_ty = decl.value.as_ref().and_then(|value| self.check_expr(value));
+        if let Some(value_ty) = value_ty
+            && !compatible(&ty, &value_ty)
+        {
+            self.mismatch(item.span, &ty, &value_ty);
+        }
+        let what = match item.kind {
+            ItemKind::Const(_) => "constant",
+            _ => "static",
+        };
+        self.check_storable(item.span, &ty, &format!("{what} `{}`", decl.ident));
+    }
+
+    /// The type written at `node`, with any placeholders replaced by their inferred types.
+    fn declared(&self, node: NodeId, ty: &Ty) -> Ty {
+        self.inferred.get(&node).unwrap_or(ty).clone()
+    }
+
+    /// Reports variables, parameters and fields that cannot hold a value.
+    fn check_storable(&mut self, span: SimpleSpan, ty: &Ty, what: &str) {
+        if *ty.kind == TyKind::Void {
+            self.error("E0302", span, format!("{what} cannot have type `void`"));
+        }
+    }
+
+    fn mismatch(&mut self, span: SimpleSpan, expected: &Ty, found: &Ty) {
+        if let (TyKind::Array(expected_len, expected), TyKind::Array(found_len, found)) =
+            (&*expected.kind, &*found.kind)
+            && expected == found
+        {
+            self.error(
+                "E0323",
+                span,
+                format!(
+                    "mismatched lengths: expected an array of {expected_len} elements, found {found_len}"
+                ),
+            );
+            return;
+        }
+        self.error(
+            "E0301",
+            span,
+            format!("mismatched types: expected `{expected}`, found `{found}`"),
+        );
+    }
+
+    fn check_item(&mut self, item: &Item) {
+        match &item.kind {
+            ItemKind::Static(_) | ItemKind::Const(_) | ItemKind::Invalid => (),
+            ItemKind::StructDecl(decl) => {
+                for field in &decl.fields {
+                    self.check_storable(item.span, &field.ty, &format!("field `{}`", field.name));
+                }
+                if decl
+                    .fields
+                    .iter()
+                    .any(|field| self.contains_struct(&field.ty, decl.name, &mut HashSet::new()))
+                {
+                    self.error(
+                        "E0303",
+                        item.span,
+                        format!(
+                            "struct `{}` contains itself, and so has infinite size",
+                            decl.name
+                        ),
+                    );
+                }
+            }
+            ItemKind::FnDecl(decl) => self.check_sig(&decl.sig),
+            ItemKind::FnDefn(defn) => {
+                self.check_sig(&defn.sig);
+                self.ret = Some(defn.sig.ty.clone());
+                self.check_stmt(&defn.block);
+                if *defn.sig.ty.kind != TyKind::Void && !self.check_tail(&defn.block, &defn.sig.ty)
+                {
+                    self.error(
+                        "E0330",
+                        defn.block.span,
+                        format!("not all paths of `{}` return a value", defn.sig.name),
+                    );
+                }
+                self.ret = None;
+            }
+        }
+    }
+
+    fn check_sig(&mut self, sig: &FnSig) {
+        for param in &sig.params {
+            self.check_storable(
+                param.span,
+                &param.ty,
+                &format!("parameter `{}`", param.name),
+            );
+        }
+    }
+
+    /// Checks the implicit return value of a non-void function, returning whether every path
+    /// through `stmt` ends in a `return` or an implicit return.
+    fn check_tail(&mut self, stmt: &Stmt, ret: &Ty) -> bool {
+        match &stmt.kind {
+            StmtKind::Block(block) => block
+                .stmts
+                .last()
+                .is_some_and(|last| self.check_tail(last, ret)),
+            StmtKind::If(_, then, Some(els)) => {
+                let then = self.check_tail(then, ret);
+                self.check_tail(els, ret) && then
+            }
+            StmtKind::Expr(expr) => {
+                if let Some(found) = self.info.exprs.get(&expr.id).cloned()
+                    && !compatible(ret, &found)
+                {
+                    self.mismatch(expr.span, ret, &found);
+                }
+                true
+            }
+            StmtKind::Return(_) => true,
+            _ => false,
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(block) => {
+                for stmt in &block.stmts {
+                    self.check_stmt(stmt);
+                }
+            }
+            StmtKind::Local(local) => {
+                let value_ty = local
+                    .value
+                    .as_ref()
+                    .and_then(|value| self.check_expr(value));
+                let ty = self.declared(stmt.id, &local.ty);
+                if let Some(value_ty) = value_ty
+                    && !compatible(&ty, &value_ty)
+                {
+                    self.mismatch(stmt.span, &ty, &value_ty);
+                }
+                self.check_storable(stmt.span, &ty, &format!("local `{}`", local.name));
+                self.info.decls.insert(stmt.id, ty);
+                if !local.mutable {
+                    self.immutable.insert(stmt.id);
+                }
+            }
+            StmtKind::For(pattern, body) => {
+                let PatternKind::RangePattern(_, range) = &pattern.kind;
+                let (Range::Exclusive(start, end)
+                | Range::Inclusive(start, end)
+                | Range::ExclusiveInclusive(start, end)
+                | Range::InclusiveExclusive(start, end)) = range;
+                self.expect(start, &int(), "range bounds");
+                self.expect(end, &int(), "range bounds");
+                self.info.decls.insert(pattern.id, int());
+                self.check_loop(body);
+            }
+            StmtKind::While(cond, body) => {
+                self.expect(cond, &bool(), "conditions");
+                self.check_loop(body);
+            }
+            StmtKind::If(cond, then, els) => {
+                self.expect(cond, &bool(), "conditions");
+                self.check_stmt(then);
+                if let Some(els) = els {
+                    self.check_stmt(els);
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.check_expr(expr);
+            }
+            StmtKind::Return(value) => {
+                let ret = self.ret.clone().expect("return outside of a function");
+                match value {
+                    Some(value) => {
+                        let Some(found) = self.check_expr(value) else {
+                            return;
+                        };
+                        if *ret.kind == TyKind::Void {
+                            self.error(
+                                "E0304",
+                                stmt.span,
+                                "cannot return a value from a `void` function".into(),
+                            );
+                        } else if !compatible(&ret, &found) {
+                            self.mismatch(value.span, &ret, &found);
+                        }
+                    }
+                    None if *ret.kind != TyKind::Void => {
+                        self.error(
+                            "E0305",
+                            stmt.span,
+                            format!("expected a return value of type `{ret}`"),
+                        );
+                    }
+                    None => (),
+                }
+            }
+            StmtKind::Continue | StmtKind::Break if self.loops == 0 => {
+                let what = if let StmtKind::Break = stmt.kind {
+                    "break"
+                } else {
+                    "continue"
+                };
+                self.error("E0306", stmt.span, format!("`{what}` outside of a loop"));
+            }
+            StmtKind::Continue | StmtKind::Break | StmtKind::Invalid => (),
+        }
+    }
+
+    fn check_loop(&mut self, body: &Stmt) {
+        self.loops += 1;
+        self.check_stmt(body);
+        self.loops -= 1;
+    }
+
+    fn expect(&mut self, expr: &Expr, expected: &Ty, what: &str) {
+        if let Some(ty) = self.check_expr(expr)
+            && ty != *expected
+        {
+            self.error(
+                "E0307",
+                expr.span,
+                format!("{what} must have type `{expected}`, found `{ty}`"),
+            );
+        }
+    }
+
+    /// Type checks `expr`, recording and returning its type. Returns [`None`] if the type could
+    /// not be determined, in which case an error has already been reported.
+    fn check_expr(&mut self, expr: &Expr) -> Option<Ty> {
+        let ty = self.infer_expr(expr)?;
+        debug_assert!(
+            !ty.has_infer(),
+            "placeholder left in `{ty}` after inference"
+        );
+        self.info.exprs.insert(expr.id, ty.clone());
+        Some(ty)
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> Option<Ty> {
+        match &expr.kind {
+            ExprKind::Invalid => None,
+            ExprKind::Literal(lit) => match *lit.value {
+                Value::Int(v) => {
+                    if v > i32::MAX as u32 {
+                        self.error(
+                            "E0009",
+                            expr.span,
+                            format!("integer literal `{v}` is out of range for `int`"),
+                        );
+                    }
+                    Some(int())
+                }
+                Value::Char(_) => Some(char()),
+                Value::Str(_) => Some(TyKind::Pointer(char()).into()),
+                Value::Bool(_) => Some(bool()),
+                Value::Invalid => None,
+            },
+            ExprKind::Ident(name) => match self.resolutions.values.get(&expr.id)? {
+                Binding::Fn(_) => {
+                    self.error(
+                        "E0308",
+                        expr.span,
+                        format!("function `{name}` cannot be used as a value"),
+                    );
+                    None
+                }
+                binding => self.binding_ty(binding),
+            },
+            ExprKind::Assign(lhs, rhs) => {
+                let lhs_ty = self.check_expr(lhs);
+                let rhs_ty = self.check_expr(rhs);
+                let (lhs_ty, rhs_ty) = (lhs_ty?, rhs_ty?);
+                if !is_lvalue(lhs) {
+                    self.error("E0309", lhs.span, format!("cannot assign to `{lhs}`"));
+                } else if let TyKind::Array(..) = *lhs_ty.kind {
+                    self.error("E0310", lhs.span, format!("cannot assign to array `{lhs}`"));
+                } else if let Some((name, binding)) = self.immutable_root(lhs) {
+                    self.assign_immutable(lhs, name, binding);
+                } else if !compatible(&lhs_ty, &rhs_ty) {
+                    self.mismatch(rhs.span, &lhs_ty, &rhs_ty);
+                }
+                Some(lhs_ty)
+            }
+            ExprKind::CompoundAssign(lhs, op, rhs) => {
+                let lhs_ty = self.check_expr(lhs);
+                let rhs_ty = self.check_expr(rhs);
+                let (lhs_ty, rhs_ty) = (lhs_ty?, rhs_ty?);
+                if !is_lvalue(lhs) {
+                    self.error("E0309", lhs.span, format!("cannot assign to `{lhs}`"));
+                } else if let Some((name, binding)) = self.immutable_root(lhs) {
+                    self.assign_immutable(lhs, name, binding);
+                } else if !is_int(&lhs_ty) || !is_int(&rhs_ty) {
+                    self.error(
+                        "E0311",
+                        expr.span,
+                        format!("cannot apply `{op}=` to `{lhs_ty}` and `{rhs_ty}`"),
+                    );
+                }
+                Some(lhs_ty)
+            }
+            ExprKind::BinOp(lhs, op, rhs) => {
+                let lhs_ty = self.check_expr(lhs);
+                let rhs_ty = self.check_expr(rhs);
+                let (lhs_ty, rhs_ty) = (lhs_ty?, rhs_ty?);
+                let (ok, ty) = match op {
+                    Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge => (
+                        lhs_ty == rhs_ty
+                            && matches!(
+                                *lhs_ty.kind,
+                                TyKind::Primitive(Primitive::Int | Primitive::Char)
+                            ),
+                        bool(),
+                    ),
+                    Operator::Eq | Operator::Ne => (
+                        lhs_ty == rhs_ty
+                            && matches!(*lhs_ty.kind, TyKind::Primitive(_) | TyKind::Pointer(_)),
+                        bool(),
+                    ),
+                    Operator::Or | Operator::And => (is_bool(&lhs_ty) && is_bool(&rhs_ty), bool()),
+                    _ => (is_int(&lhs_ty) && is_int(&rhs_ty), int()),
+                };
+                if !ok {
+                    self.error(
+                        "E0311",
+                        expr.span,
+                        format!("cannot apply `{op}` to `{lhs_ty}` and `{rhs_ty}`"),
+                    );
+                }
+                Some(ty)
+            }
+            ExprKind::Unary(op, inner) => {
+                let ty = match (op, &inner.kind) {
+                    // Checked here, as `2147483648` only fits in an `int` once negated.
+                    (UnaryOp::Neg, ExprKind::Literal(lit))
+                        if matches!(*lit.value, Value::Int(_)) =>
+                    {
+                        self.info.exprs.insert(inner.id, int());
+                        int()
+                    }
+                    _ => self.check_expr(inner)?,
+                };
+                let (ok, result) = match op {
+                    UnaryOp::Not => (is_bool(&ty), bool()),
+                    UnaryOp::Neg | UnaryOp::Plus | UnaryOp::BitNot => (is_int(&ty), int()),
+                };
+                if !ok {
+                    self.error("E0311", expr.span, format!("cannot apply `{op}` to `{ty}`"));
+                }
+                Some(result)
+            }
+            ExprKind::CallFn(call) => self.check_call(expr, call),
+            ExprKind::Typecast(to, inner) => {
+                let to = self.declared(expr.id, to);
+                let from = self.check_expr(inner)?;
+                let ok = match (&*from.kind, &*to.kind) {
+                    (TyKind::Primitive(_), TyKind::Primitive(_)) => true,
+                    (TyKind::Pointer(_), TyKind::Pointer(_)) => true,
+                    (TyKind::Array(_, elem), TyKind::Pointer(ptr)) => elem == ptr,
+                    _ => from == to,
+                };
+                if !ok {
+                    self.error(
+                        "E0312",
+                        expr.span,
+                        format!("cannot cast `{from}` to `{to}`"),
+                    );
+                }
+                Some(to)
+            }
+            ExprKind::Deref(inner) => {
+                let ty = self.check_expr(inner)?;
+                match &*ty.kind {
+                    TyKind::Pointer(pointee) if *pointee.kind != TyKind::Void => {
+                        Some(pointee.clone())
+                    }
+                    _ => {
+                        self.error("E0313", expr.span, format!("cannot dereference `{ty}`"));
+                        None
+                    }
+                }
+            }
+            ExprKind::Ref(inner) => {
+                let ty = self.check_expr(inner)?;
+                if !is_lvalue(inner) {
+                    self.error(
+                        "E0314",
+                        inner.span,
+                        format!("cannot take the address of `{inner}`"),
+                    );
+                }
+                Some(TyKind::Pointer(ty).into())
+            }
+            ExprKind::Index(arr, ind) => {
+                let arr_ty = self.check_expr(arr);
+                self.expect(ind, &int(), "indices");
+                let arr_ty = arr_ty?;
+                match &*arr_ty.kind {
+                    TyKind::Array(_, elem) => Some(elem.clone()),
+                    TyKind::Pointer(elem) if *elem.kind != TyKind::Void => Some(elem.clone()),
+                    _ => {
+                        self.error("E0315", arr.span, format!("cannot index into `{arr_ty}`"));
+                        None
+                    }
+                }
+            }
+            ExprKind::StructLit(ty, fields) => self.check_struct_lit(expr, ty, fields),
+            ExprKind::ArrayLit(elems) => {
+                let tys = elems
+                    .iter()
+                    .map(|elem| self.check_expr(elem))
+                    .collect::<Vec<_>>();
+                let elem_ty = tys[0].clone()?;
+                self.check_storable(elems[0].span, &elem_ty, "array elements");
+                for (elem, ty) in elems.iter().zip(tys).skip(1) {
+                    if let Some(ty) = ty
+                        && !compatible(&elem_ty, &ty)
+                    {
+                        self.mismatch(elem.span, &elem_ty, &ty);
+                    }
+                }
+                Some(TyKind::Array(elems.len().into(), elem_ty).into())
+            }
+            ExprKind::ArrayRepeat(elem, len) => {
+                let elem_ty = self.check_expr(elem)?;
+                self.check_storable(elem.span, &elem_ty, "array elements");
+                Some(TyKind::Array(*len, elem_ty).into())
+            }
+            ExprKind::FieldAccess(inner, field) => {
+                let ty = self.check_expr(inner)?;
+                let Some(name) = ty.field_owner() else {
+                    self.error(
+                        "E0316",
+                        expr.span,
+                        format!("no field `{field}` on type `{ty}`"),
+                    );
+                    return None;
+                };
+                let decl = self.struct_decl(&name)?;
+                match decl.fields.iter().find(|f| f.name == *field) {
+                    Some(f) => Some(f.ty.clone()),
+                    None => {
+                        self.error(
+                            "E0317",
+                            expr.span,
+                            format!("struct `{name}` has no field `{field}`"),
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks that a struct literal gives every field of its struct exactly once.
+    fn check_struct_lit(&mut self, expr: &Expr, ty: &Ty, fields: &[FieldInit]) -> Option<Ty> {
+        let TyKind::Struct(name) = *ty.kind else {
+            unreachable!("struct literal of a non-struct type");
+        };
+        let value_tys = fields
+            .iter()
+            .map(|field| self.check_expr(&field.value))
+            .collect::<Vec<_>>();
+        let decl = self.struct_decl(&name)?;
+        let mut given = HashSet::new();
+        for (field, value_ty) in fields.iter().zip(value_tys) {
+            if !given.insert(field.name) {
+                self.error(
+                    "E0321",
+                    field.span,
+                    format!("field `{}` specified more than once", field.name),
+                );
+                continue;
+            }
+            let Some(decl_field) = decl.fields.iter().find(|f| f.name == field.name) else {
+                self.error(
+                    "E0317",
+                    field.span,
+                    format!("struct `{name}` has no field `{}`", field.name),
+                );
+                continue;
+            };
+            if let Some(value_ty) = value_ty
+                && !compatible(&decl_field.ty, &value_ty)
+            {
+                self.mismatch(field.value.span, &decl_field.ty, &value_ty);
+            }
+        }
+        let missing = decl
+            .fields
+            .iter()
+            .filter(|f| !given.contains(&f.name))
+            .map(|f| format!("`{}`", f.name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let noun = if missing.len() == 1 {
+                "field"
+            } else {
+                "fields"
+            };
+            self.error(
+                "E0322",
+                expr.span,
+                format!("missing {noun} {} in literal of `{ty}`", missing.join(", ")),
+            );
+        }
+        Some(ty.clone())
+    }
+
+    fn check_call(&mut self, expr: &Expr, call: &CallFn) -> Option<Ty> {
+        let arg_tys = call
+            .args
+            .iter()
+            .map(|arg| self.check_expr(arg))
+            .collect::<Vec<_>>();
+        let Some(&Binding::Fn(item)) = self.resolutions.values.get(&call.name.id) else {
+            return None;
+        };
+        let sig = self.fns[&item];
+        if sig.params.len() != call.args.len() {
+            self.error(
+                "E0318",
+                expr.span,
+                format!(
+                    "function `{}` expects {} argument(s), found {}",
+                    sig.name,
+                    sig.params.len(),
+                    call.args.len()
+                ),
+            );
+        }
+        for ((param, arg), arg_ty) in sig.params.iter().zip(&call.args).zip(arg_tys) {
+            if let Some(arg_ty) = arg_ty
+                && !compatible(&param.ty, &arg_ty)
+            {
+                self.mismatch(arg.span, &param.ty, &arg_ty);
+            }
+        }
+        Some(sig.ty.clone())
+    }
+
+    /// The immutable variable that assigning to the lvalue `place` would modify, if any. Assigning
+    /// through a pointer modifies what it points to, not the pointer.
+    fn immutable_root(&self, place: &Expr) -> Option<(Ident, Binding)> {
+        match &place.kind {
+            ExprKind::Ident(name) => {
+                let binding = *self.resolutions.values.get(&place.id)?;
+                let (Binding::Local(id) | Binding::Static(id)) = binding else {
+                    return None;
+                };
+                self.immutable.contains(&id).then_some((*name, binding))
+            }
+            ExprKind::Index(base, _) | ExprKind::FieldAccess(base, _) => {
+                match *self.info.exprs.get(&base.id)?.kind {
+                    TyKind::Array(..) | TyKind::Struct(_) => self.immutable_root(base),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn assign_immutable(&mut self, place: &Expr, name: Ident, binding: Binding) {
+        let (message, label) = match binding {
+            Binding::Local(_) => (
+                format!("cannot assign to `{place}`, as local `{name}` is not mutable"),
+                format!("declared here, consider `let mut {name}`"),
+            ),
+            _ => (
+                format!("cannot assign to `{place}`, as `{name}` is a constant"),
+                "declared here".to_string(),
+            ),
+        };
+        let error = SemError::new("E0329", place.span, message);
+        self.errors
+            .push(match self.resolutions.decl_spans.get(&binding) {
+                Some(&span) => error.with_label(span, label),
+                None => error,
+            });
+    }
+
+    fn binding_ty(&self, binding: &Binding) -> Option<Ty> {
+        match binding {
+            Binding::Local(id) | Binding::Static(id) | Binding::Pattern(id) => {
+                self.info.decls.get(id).cloned()
+            }
+            Binding::Param { item, index } => Some(self.fns[item].params[*index].ty.clone()),
+            Binding::Fn(item) => Some(self.fns[item].ty.clone()),
+        }
+    }
+
+    /// Whether a value of type `ty` contains the struct `name` other than behind a pointer.
+    /// `seen` holds the structs already searched.
+    fn contains_struct(&self, ty: &Ty, name: Ident, seen: &mut HashSet<Ident>) -> bool {
+        match &*ty.kind {
+            TyKind::Array(_, elem) => self.contains_struct(elem, name, seen),
+            TyKind::Struct(inner) if *inner == name => true,
+            TyKind::Struct(inner) if seen.insert(*inner) => {
+                self.struct_decl(inner).is_some_and(|decl| {
+                    decl.fields
+                        .iter()
+                        .any(|field| self.contains_struct(&field.ty, name, seen))
+                })
+            }
+            _ => false,
+        }
+    }
+
+    fn struct_decl(&self, name: &Ident) -> Option<&'ast StructDecl> {
+        let item = self.resolutions.struct_decls.get(name)?;
+        self.structs.get(item).copied()
+    }
+}
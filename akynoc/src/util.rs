@@ -1,32 +1,99 @@
 use std::io::Write;
 
 use anyhow::Result;
+use chumsky::span::SimpleSpan;
 use serde::Serialize;
 
+use crate::{
+    diagnostic::Diagnostic,
+    lexer::{lex_lossless, SourceFile, Token},
+};
+
+/// A single stage of the compilation pipeline.
+///
+/// Each pass collects the diagnostics it reports, so that the driver can [emit](crate::diagnostic)
+/// them, then stop at the first failing stage and exit with that stage's
+/// [`FAIL_CODE`](CompilerPass::FAIL_CODE).
 pub trait CompilerPass {
-    fn num_errors(&self) -> u32;
-    fn inc_error(&mut self);
+    /// Process exit code used when this pass reports at least one error.
+    const FAIL_CODE: u8;
+    /// Every diagnostic reported so far, in order.
+    fn diagnostics(&self) -> &[Diagnostic];
+    fn report(&mut self, diagnostic: Diagnostic);
+    fn num_errors(&self) -> usize {
+        self.diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .count()
+    }
     fn has_error(&self) -> bool {
         self.num_errors() > 0
     }
 }
 
+/// Prints a node of the AST back to source code, in the canonical style of [`format`](crate::format).
 pub trait Writable {
+    /// Writes `self` starting at the current position of `writer`. With `eol`, ends the line, along
+    /// with any comment that followed `self` on the same line of the source.
     fn write<T: Write>(&self, writer: &mut Writer<T>, eol: bool) -> Result<()>;
 }
 
+/// Writes source code, keeping track of the indentation level.
+///
+/// A writer created [`with_source`](Writer::with_source) also carries over the comments and blank
+/// lines of the source the AST was parsed from, which the AST does not record.
 pub struct Writer<'a, T: Write> {
     writer: &'a mut T,
     tabc: u8,
+    /// Text inserted for each level of indentation: a tab, unless set
+    /// [`with_indent`](Self::with_indent).
+    indent: &'a str,
+    text: &'a str,
+    /// Spans of the comments not yet written, in reverse order.
+    comments: Vec<SimpleSpan>,
+    /// End of the last node or comment written at the current level of nesting, if any.
+    last: Option<usize>,
 }
 
 impl<'a, T: Write> Writer<'a, T> {
     pub fn new(writer: &'a mut T) -> Self {
-        Self { writer, tabc: 0 }
+        Self {
+            writer,
+            tabc: 0,
+            indent: "\t",
+            text: "",
+            comments: vec![],
+            last: None,
+        }
     }
+
+    pub fn with_source(writer: &'a mut T, src: &'a SourceFile) -> Self {
+        let text = src.source.text();
+        let mut comments = lex_lossless(src)
+            .filter(|(tok, _)| matches!(tok, Token::LineComment(_) | Token::BlockComment(_)))
+            .map(|(_, span)| {
+                // Line comments include their newline, which is written separately.
+                let len = text[span.into_range()].trim_end().len();
+                SimpleSpan::from(span.start..span.start + len)
+            })
+            .collect::<Vec<_>>();
+        comments.reverse();
+        Self {
+            text,
+            comments,
+            ..Self::new(writer)
+        }
+    }
+
+    /// Indents each level of nesting with `indent` instead.
+    pub fn with_indent(self, indent: &'a str) -> Self {
+        Self { indent, ..self }
+    }
+
     pub fn tabs(&mut self) -> Result<()> {
+        let indent = self.indent;
         for _ in 0..self.tabc {
-            write!(self, "\t")?;
+            write!(self, "{indent}")?;
         }
         Ok(())
     }
@@ -39,6 +106,86 @@ impl<'a, T: Write> Writer<'a, T> {
     pub fn dectabs(&mut self) {
         self.tabc -= 1;
     }
+
+    /// The source text of `span`, if this writer has a source.
+    pub fn source(&self, span: SimpleSpan) -> Option<&'a str> {
+        self.text.get(span.into_range())
+    }
+
+    /// Starts a new level of nesting, such as a block, which never begins with a blank line.
+    pub fn open(&mut self) {
+        self.last = None;
+    }
+
+    /// Records that the node ending at `end` in the source has been written.
+    pub fn close(&mut self, end: usize) {
+        self.last = Some(end);
+    }
+
+    /// Whether there are comments left to write before `pos`.
+    pub fn has_comments(&self, pos: usize) -> bool {
+        self.comments.last().is_some_and(|c| c.start < pos)
+    }
+
+    /// Writes every comment before `pos` on its own line, leaving the writer at the start of the line
+    /// of whatever starts at `pos`. A blank line is kept wherever the source had at least one, and
+    /// with `blank`, the first thing written is always preceded by one, unless it opens a level of
+    /// nesting.
+    pub fn comments(&mut self, pos: usize, blank: bool) -> Result<()> {
+        let blank = self.comments_before(pos, blank)?;
+        self.gap(pos, blank)
+    }
+
+    /// Writes every comment before `pos`, the end of the current level of nesting, on its own line.
+    pub fn closing_comments(&mut self, pos: usize) -> Result<()> {
+        self.comments_before(pos, false)?;
+        Ok(())
+    }
+
+    /// Returns whether `blank` still applies to what comes next.
+    fn comments_before(&mut self, pos: usize, mut blank: bool) -> Result<bool> {
+        while let Some(comment) = self.comments.pop_if(|c| c.start < pos) {
+            self.gap(comment.start, blank)?;
+            blank = false;
+            self.tabs()?;
+            write!(self, "{}", &self.text[comment.into_range()])?;
+            writeln!(self)?;
+            self.last = Some(comment.end);
+        }
+        Ok(blank)
+    }
+
+    /// Writes the comment following the last node written on the same line of the source, if any,
+    /// then ends the line.
+    pub fn eol(&mut self) -> Result<()> {
+        if let Some(last) = self.last {
+            let same_line =
+                |c: &mut SimpleSpan| c.start >= last && !self.text[last..c.start].contains('\n');
+            if let Some(comment) = self.comments.pop_if(same_line) {
+                write!(self, " {}", &self.text[comment.into_range()])?;
+                self.last = Some(comment.end);
+            }
+        }
+        writeln!(self)?;
+        Ok(())
+    }
+
+    /// Writes a blank line before whatever starts at `pos`, if needed.
+    fn gap(&mut self, pos: usize, blank: bool) -> Result<()> {
+        let Some(last) = self.last else {
+            return Ok(());
+        };
+        let lines = self.text.get(last..pos).unwrap_or_default().split('\n');
+        let blank_line = lines
+            .clone()
+            .skip(1)
+            .take(lines.count().saturating_sub(2))
+            .any(|line| line.trim().is_empty());
+        if blank || blank_line {
+            writeln!(self)?;
+        }
+        Ok(())
+    }
 }
 
 impl<T: Write> Write for Writer<'_, T> {
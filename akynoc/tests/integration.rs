@@ -1,22 +1,31 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
 };
 
 use akynoc::{
     ast::Item,
-    lexer::{lex, SourceFile, Token},
+    cst::SyntaxNode,
+    diagnostic::codes,
+    format::format,
+    lexer::{lex_lossless, LexerPass, SourceFile, Token},
     parser,
+    sem::SemanticAnalysis,
+    util::CompilerPass,
 };
 use anyhow::Result;
 use ariadne::FileCache;
 use chumsky::Parser;
-use insta::assert_ron_snapshot;
+use insta::{assert_ron_snapshot, assert_snapshot};
+use lsp_server::{Message, Notification, Request, RequestId};
 use rstest::rstest;
+use serde_json::{json, Value};
 const LEXER_FAIL: u32 = 250;
 const PARSER_FAIL: u32 = 245;
 const SEM_FAIL: u32 = 240;
+const RUN_FAIL: u32 = 230;
 const PASS: u32 = 0;
 
 macro_rules! set_snapshot_suffix {
@@ -28,19 +37,16 @@ macro_rules! set_snapshot_suffix {
 }
 
 fn test_lexer<'a>(src: &'a SourceFile) -> (u32, Vec<Token<'a>>) {
-    let tokens = lex(src).map(|(t, _)| t).collect::<Vec<_>>();
-    let rv = tokens.clone();
-    for tok in tokens {
-        match tok {
-            Token::Invalid => return (LEXER_FAIL, rv),
-            _ => (),
-        }
+    let mut lexer = LexerPass::new(src);
+    let tokens = lexer.lex().into_iter().map(|(t, _)| t).collect::<Vec<_>>();
+    if lexer.has_error() {
+        return (LEXER_FAIL, tokens);
     }
-    return (PASS, rv);
+    (PASS, tokens)
 }
 
-fn test_parser<'a>(src: &'a SourceFile) -> (u32, Vec<Item>) {
-    let token_stream = parser::token_stream(&src);
+fn test_parser(src: &SourceFile) -> (u32, Vec<Item>) {
+    let token_stream = parser::token_stream(src);
     let parser = parser::parser();
     match parser.parse(token_stream).into_result() {
         Ok(ast) => (PASS, ast),
@@ -48,25 +54,26 @@ fn test_parser<'a>(src: &'a SourceFile) -> (u32, Vec<Item>) {
     }
 }
 
-// fn test_sem(path: &Path) -> Result<(u32, HirPool<TypedHir>)> {
-//     let mut sem_analysis = SemanticAnalysis::from_path(path)?;
-//     let typed_hir = sem_analysis.analyse();
-//     match typed_hir {
-//         Ok(typed_hir) => Ok((PASS, typed_hir)),
-//         Err(_) => Ok((SEM_FAIL, HirPool::new())),
-//     }
-// }
+/// Analyses `ast`, returning the exit code along with the code of each error, in order.
+fn test_sem(ast: &mut [Item]) -> (u32, Vec<&'static str>) {
+    let mut sem_analysis = SemanticAnalysis::new();
+    let code = match sem_analysis.analyse(ast) {
+        Some(_) => PASS,
+        None => SEM_FAIL,
+    };
+    let errors = sem_analysis
+        .diagnostics()
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    (code, errors)
+}
 
-#[rstest]
-fn test(
-    #[base_dir = "tests/resources/source/"]
-    #[files("*.akn")]
-    path: PathBuf,
-) -> Result<()> {
-    let mut reader = BufReader::new(File::open(&path)?);
+/// Reads the expected exit code from the header comment of a test source file.
+fn expected_exit_code(path: &Path) -> Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
     let mut line = String::new();
-    let cache = FileCache::default();
-    let src = SourceFile::from_path(path.as_path(), cache);
     reader.read_line(&mut line)?;
     let expected_exit_code = if line.starts_with("/*") {
         // extract exit code
@@ -78,6 +85,87 @@ fn test(
     } else {
         0
     };
+    Ok(expected_exit_code)
+}
+
+/// Reads the codes of the expected errors, in the order they are reported, from the second line
+/// of the header comment, such as `// E0301 E0317`.
+fn expected_error_codes(path: &Path) -> Result<Vec<String>> {
+    let mut lines = BufReader::new(File::open(path)?).lines().skip(1);
+    let line = lines.next().transpose()?.unwrap_or_default();
+    let codes = line.strip_prefix("//").unwrap_or_default();
+    Ok(codes.split_whitespace().map(str::to_string).collect())
+}
+
+/// The input for a test source file, read from the `.in` file next to it, if there is one.
+fn input(path: &Path) -> Result<Vec<u8>> {
+    let path = path.with_extension("in");
+    Ok(if path.exists() {
+        fs::read(path)?
+    } else {
+        vec![]
+    })
+}
+
+/// Runs `command` with the input for `path`.
+fn run_with_input(command: &mut Command, path: &Path) -> Result<Output> {
+    let stdin = match path.with_extension("in") {
+        input if input.exists() => Stdio::from(File::open(input)?),
+        _ => Stdio::null(),
+    };
+    Ok(command.stdin(stdin).output()?)
+}
+
+/// Checks what a program printed, which must be the same however it is run.
+fn assert_output(path: &Path, output: &[u8]) {
+    set_snapshot_suffix!("output");
+    let output = String::from_utf8_lossy(output);
+    assert_snapshot!(path.file_stem().unwrap().to_str().unwrap(), output);
+}
+
+/// The AST serialized without the ids and spans that differ between two parses of the same code.
+fn shape(ast: &[Item]) -> Result<String> {
+    let ron = ron::to_string(ast)?;
+    let mut shape = String::with_capacity(ron.len());
+    let mut rest = ron.as_str();
+    while let Some(i) = rest.find(['(', ',']) {
+        shape.push_str(&rest[..=i]);
+        rest = &rest[i + 1..];
+        // Skip the balanced parentheses of each value, and the comma after it.
+        while let Some(value) = rest.strip_prefix("id:").or(rest.strip_prefix("span:")) {
+            let mut depth = 0;
+            let len = value
+                .find(|c| {
+                    depth += match c {
+                        '(' => 1,
+                        ')' => -1,
+                        _ => 0,
+                    };
+                    depth == 0
+                })
+                .unwrap();
+            rest = &value[len + 1..];
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+    shape.push_str(rest);
+    Ok(shape)
+}
+
+/// Whether a program with the expected exit code `code` runs to completion.
+fn runs(code: u32) -> bool {
+    !matches!(code, LEXER_FAIL | PARSER_FAIL | SEM_FAIL | RUN_FAIL)
+}
+
+#[rstest]
+fn test(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let cache = FileCache::default();
+    let src = SourceFile::from_path(path.as_path(), cache);
+    let expected_exit_code = expected_exit_code(&path)?;
     let lexer_expected = if expected_exit_code == LEXER_FAIL {
         LEXER_FAIL
     } else {
@@ -102,14 +190,529 @@ fn test(
     }
 
     if expected_exit_code < LEXER_FAIL {
-        let (parser_actual, ast) = test_parser(&src);
+        let (parser_actual, mut ast) = test_parser(&src);
         assert_eq!(parser_expected, parser_actual);
         if parser_expected == PASS {
             set_snapshot_suffix!("parser");
             assert_ron_snapshot!(path.file_stem().unwrap().to_str().unwrap(), ast, {
-                ".**.id" => "[nodeid]"
+                ".**.id" => "[nodeid]",
+                ".**.span" => "[span]",
             });
         }
+
+        if expected_exit_code < PARSER_FAIL {
+            let (sem_actual, errors) = test_sem(&mut ast);
+            assert_eq!(sem_expected, sem_actual);
+            if sem_expected == SEM_FAIL {
+                assert_eq!(expected_error_codes(&path)?, errors);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The lossless token stream and the CST must both reproduce the source byte for byte, even for
+/// files that fail to lex or parse.
+#[rstest]
+fn test_cst(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let src = SourceFile::from_path(path.as_path(), FileCache::default());
+    let text = fs::read_to_string(&path)?;
+    let lossless = lex_lossless(&src)
+        .map(|(_, span)| &src.source.text()[span.into_range()])
+        .collect::<String>();
+    assert_eq!(text, lossless);
+
+    let items = parser::parser()
+        .parse(parser::token_stream(&src))
+        .into_output()
+        .unwrap_or_default();
+    let cst = SyntaxNode::build(&src, &items);
+    assert_eq!(text, cst.to_string());
+    Ok(())
+}
+
+/// Formatting must keep the AST as it is, and leave formatted code unchanged.
+#[rstest]
+fn test_fmt(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = expected_exit_code(&path)?;
+    if matches!(expected, LEXER_FAIL | PARSER_FAIL) {
+        return Ok(());
+    }
+    let src = SourceFile::from_path(path.as_path(), FileCache::default());
+    let (_, ast) = test_parser(&src);
+    let formatted = format(&src, &ast)?;
+    set_snapshot_suffix!("fmt");
+    assert_snapshot!(path.file_stem().unwrap().to_str().unwrap(), formatted);
+
+    let dir = tempfile::tempdir()?;
+    let formatted_path = dir.path().join(path.file_name().unwrap());
+    fs::write(&formatted_path, &formatted)?;
+    let formatted_src = SourceFile::from_path(&formatted_path, FileCache::default());
+    let (status, formatted_ast) = test_parser(&formatted_src);
+    assert_eq!(PASS, status);
+    assert_eq!(shape(&ast)?, shape(&formatted_ast)?);
+    assert_eq!(formatted, format(&formatted_src, &formatted_ast)?);
+    Ok(())
+}
+
+#[test]
+fn test_fmt_modes() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("unformatted.akn");
+    let unformatted = "main():int{\n  let x:=1;   // one\n  x}\n";
+    let formatted = "main(): int {\n    let x := 1; // one\n    x\n}\n";
+    fs::write(&path, unformatted)?;
+    let fmt = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_akynoc"));
+        command.arg("fmt").arg(&path);
+        command
+    };
+
+    let output = fmt().output()?;
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(formatted.as_bytes(), output.stdout);
+    assert_eq!(Some(1), fmt().arg("--check").status()?.code());
+    assert_eq!(unformatted, fs::read_to_string(&path)?);
+
+    assert_eq!(Some(0), fmt().arg("--in-place").status()?.code());
+    assert_eq!(formatted, fs::read_to_string(&path)?);
+    assert_eq!(Some(0), fmt().arg("--check").status()?.code());
+
+    fs::write(&path, "main(): int {")?;
+    let status = fmt().arg("--check").stderr(Stdio::null()).status()?;
+    assert_eq!(Some(PARSER_FAIL as i32), status.code());
+    Ok(())
+}
+
+/// A client scripting a conversation with `akynoc lsp` over its stdio.
+struct LspClient {
+    reader: BufReader<std::process::ChildStdout>,
+    writer: BufWriter<std::process::ChildStdin>,
+    next_id: i32,
+}
+
+impl LspClient {
+    fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), method.to_string(), params);
+        Message::Request(request).write(&mut self.writer)?;
+        loop {
+            match Message::read(&mut self.reader)? {
+                Some(Message::Response(response)) if response.id == id => {
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    return Ok(response.result.unwrap_or_default());
+                }
+                Some(_) => continue,
+                None => anyhow::bail!("server closed before responding to {method}"),
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        let notification = Notification::new(method.to_string(), params);
+        Message::Notification(notification).write(&mut self.writer)?;
+        Ok(())
+    }
+
+    /// Waits for the next diagnostics to be published.
+    fn diagnostics(&mut self) -> Result<Value> {
+        loop {
+            match Message::read(&mut self.reader)? {
+                Some(Message::Notification(notification))
+                    if notification.method == "textDocument/publishDiagnostics" =>
+                {
+                    return Ok(notification.params["diagnostics"].clone());
+                }
+                Some(_) => continue,
+                None => anyhow::bail!("server closed before publishing diagnostics"),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lsp() -> Result<()> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut client = LspClient {
+        reader: BufReader::new(child.stdout.take().unwrap()),
+        writer: BufWriter::new(child.stdin.take().unwrap()),
+        next_id: 0,
+    };
+    let uri = "file:///test.akn";
+    let document = json!({ "uri": uri });
+    let at = |line: u32, character: u32| json!({ "textDocument": document, "position": { "line": line, "character": character } });
+
+    let capabilities = client.request("initialize", json!({ "capabilities": {} }))?;
+    assert_eq!(json!(true), capabilities["capabilities"]["hoverProvider"]);
+    client.notify("initialized", json!({}))?;
+
+    let text = "main(): int {\n    y\n}\n";
+    let item = json!({ "uri": uri, "languageId": "akyno", "version": 1, "text": text });
+    client.notify("textDocument/didOpen", json!({ "textDocument": item }))?;
+    let diagnostics = client.diagnostics()?;
+    assert_eq!(1, diagnostics.as_array().unwrap().len());
+    assert_eq!(json!("E0208"), diagnostics[0]["code"]);
+    let range =
+        json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 5 } });
+    assert_eq!(range, diagnostics[0]["range"]);
+
+    let text = [
+        "struct point {",
+        "    x: int;",
+        "}",
+        "",
+        "static origin: struct point;",
+        "",
+        "norm(p: struct point): int {",
+        "    p.x",
+        "}",
+        "",
+        "main(): int {",
+        "    let y := norm(origin);",
+        "    y",
+        "}",
+    ]
+    .join("\n");
+    let change = json!({ "textDocument": { "uri": uri, "version": 2 }, "contentChanges": [{ "text": text }] });
+    client.notify("textDocument/didChange", change)?;
+    assert_eq!(json!([]), client.diagnostics()?);
+
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": document }),
+    )?;
+    let names = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["point", "origin", "norm", "main"], names);
+    assert_eq!(json!("x"), symbols[0]["children"][0]["name"]);
+
+    let definition = client.request("textDocument/definition", at(11, 15))?;
+    assert_eq!(
+        json!({ "line": 6, "character": 0 }),
+        definition["range"]["start"]
+    );
+    let definition = client.request("textDocument/definition", at(4, 23))?;
+    assert_eq!(
+        json!({ "line": 0, "character": 7 }),
+        definition["range"]["start"]
+    );
+    let definition = client.request("textDocument/definition", at(7, 6))?;
+    assert_eq!(
+        json!({ "line": 1, "character": 4 }),
+        definition["range"]["start"]
+    );
+
+    let hover = client.request("textDocument/hover", at(11, 15))?;
+    let contents = hover["contents"]["value"].as_str().unwrap();
+    assert!(
+        contents.contains("norm(p: struct point): int"),
+        "{contents}"
+    );
+    let hover = client.request("textDocument/hover", at(12, 4))?;
+    let contents = hover["contents"]["value"].as_str().unwrap();
+    assert!(contents.contains("y: int"), "{contents}");
+
+    client.request("shutdown", Value::Null)?;
+    client.notify("exit", Value::Null)?;
+    drop(client);
+    assert_eq!(Some(0), child.wait()?.code());
+    Ok(())
+}
+
+#[test]
+fn test_repl() -> Result<()> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let input = "fact(n: int): int {\n    if (n < 2) { return 1; }\n    n * fact(n - 1)\n}\n\
+                 let x := fact(5);\nx + y\nx + 1\n:type x\n";
+    let mut stdin = child.stdin.take().unwrap();
+    std::io::Write::write_all(&mut stdin, input.as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!("121\nint\n", String::from_utf8(output.stdout)?);
+    assert!(String::from_utf8(output.stderr)?.contains("cannot find value `y`"));
+    Ok(())
+}
+
+/// A function that can end without a value is rejected before anything runs, whether in a file or
+/// entered in the REPL, so the interpreter never reaches its end.
+#[test]
+fn test_interpret_missing_return() -> Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--pass", "interpret"])
+        .arg("tests/resources/source/missing_return.akn")
+        .output()?;
+    assert_eq!(Some(SEM_FAIL as i32), output.status.code());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.contains("[E0330]"));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let input = "positive(x: int): int {\n    if (x > 0) { return 1; }\n}\npositive(1)\n";
+    let mut stdin = child.stdin.take().unwrap();
+    std::io::Write::write_all(&mut stdin, input.as_bytes())?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    assert_eq!(Some(0), output.status.code());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("[E0330]"));
+    assert!(stderr.contains("cannot find function `positive`"));
+    Ok(())
+}
+
+#[rstest]
+fn test_driver(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = match expected_exit_code(&path)? {
+        code @ (LEXER_FAIL | PARSER_FAIL | SEM_FAIL) => code,
+        _ => PASS,
+    };
+    // Without options, the program is compiled to assembly named after it, in the current
+    // directory.
+    let dir = tempfile::tempdir()?;
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .current_dir(dir.path())
+        .arg(&path)
+        .status()?;
+    assert_eq!(Some(expected as i32), status.code());
+    let asm = dir
+        .path()
+        .join(path.file_stem().unwrap())
+        .with_extension("s");
+    assert_eq!(expected == PASS, asm.exists());
+    Ok(())
+}
+
+/// Checks that `--pass sem` stops after analysis, even when asked for code.
+#[test]
+fn test_driver_sem() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let asm = dir.path().join("out.s");
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--pass", "sem", "--emit", "asm", "-o"])
+        .arg(&asm)
+        .arg("tests/resources/source/factorial.akn")
+        .status()?;
+    assert!(status.success());
+    assert!(!asm.exists());
+    Ok(())
+}
+
+#[rstest]
+fn test_error_format(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = expected_exit_code(&path)?;
+    if !matches!(expected, LEXER_FAIL | PARSER_FAIL | SEM_FAIL) {
+        return Ok(());
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--error-format", "json"])
+        .arg(&path)
+        .output()?;
+    assert_eq!(Some(expected as i32), output.status.code());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.lines().count() > 0);
+    for line in stderr.lines() {
+        let diagnostic: Value = serde_json::from_str(line)?;
+        assert_eq!(json!("error"), diagnostic["severity"]);
+        let code = diagnostic["code"].as_str().unwrap();
+        assert!(codes::explain(code).is_some(), "{code} has no explanation");
+        for label in diagnostic["labels"].as_array().unwrap() {
+            assert_eq!(json!(path.to_str().unwrap()), label["file"]);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_error_format_locations() -> Result<()> {
+    let path = Path::new("tests/resources/source/undeclared_ident.akn");
+    let output = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--error-format", "json"])
+        .arg(path)
+        .output()?;
+    let diagnostic: Value = serde_json::from_slice(&output.stderr)?;
+    let label = &diagnostic["labels"][0];
+    assert_eq!(json!(true), label["primary"]);
+    assert_eq!(
+        json!({ "offset": 56, "line": 6, "column": 9 }),
+        label["start"]
+    );
+    assert_eq!(
+        json!({ "offset": 57, "line": 6, "column": 10 }),
+        label["end"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_explain() -> Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--explain", "E0208"])
+        .output()?;
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8(output.stdout)?.starts_with("E0208: "));
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--explain", "E9999"])
+        .stderr(Stdio::null())
+        .status()?;
+    assert_eq!(Some(1), status.code());
+    Ok(())
+}
+
+#[rstest]
+fn test_interpret(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = expected_exit_code(&path)?;
+    let output = run_with_input(
+        Command::new(env!("CARGO_BIN_EXE_akynoc"))
+            .args(["--pass", "interpret"])
+            .arg(&path),
+        &path,
+    )?;
+    assert_eq!(Some(expected as i32), output.status.code());
+    if runs(expected) {
+        assert_output(&path, &output.stdout);
+    }
+    Ok(())
+}
+
+/// Compiles each program that runs to completion, and checks the executable's exit status and
+/// output.
+#[rstest]
+fn test_compile(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = expected_exit_code(&path)?;
+    if !runs(expected) {
+        return Ok(());
+    }
+    let dir = tempfile::tempdir()?;
+    let exe = dir.path().join(path.file_stem().unwrap());
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--emit", "exe", "-o"])
+        .arg(&exe)
+        .arg(&path)
+        .status()?;
+    assert!(status.success());
+    let output = run_with_input(&mut Command::new(&exe), &path)?;
+    assert_eq!(Some(expected as i32), output.status.code());
+    assert_output(&path, &output.stdout);
+    Ok(())
+}
+
+/// Compiles each program that divides by zero, which must exit as the interpreter does rather
+/// than trap.
+#[rstest]
+fn test_compile_division_fault(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*_by_zero.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let exe = dir.path().join(path.file_stem().unwrap());
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--emit", "exe", "-o"])
+        .arg(&exe)
+        .arg(&path)
+        .status()?;
+    assert!(status.success());
+    let output = Command::new(&exe).output()?;
+    assert_eq!(Some(RUN_FAIL as i32), output.status.code());
+    Ok(())
+}
+
+/// Compiles each program that runs to completion to MIPS, and checks its exit status and output
+/// in the simulator.
+#[rstest]
+fn test_mips(
+    #[base_dir = "tests/resources/source/"]
+    #[files("*.akn")]
+    path: PathBuf,
+) -> Result<()> {
+    let expected = expected_exit_code(&path)?;
+    if !runs(expected) {
+        return Ok(());
     }
+    let dir = tempfile::tempdir()?;
+    let asm = dir.path().join("out.s");
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--target", "mips", "--emit", "asm", "-o"])
+        .arg(&asm)
+        .arg(&path)
+        .status()?;
+    assert!(status.success());
+    let program = mipsim::assemble(&fs::read_to_string(&asm)?)?;
+    let mut output = vec![];
+    let status = mipsim::run(
+        &program,
+        &mut input(&path)?.as_slice(),
+        &mut output,
+        Some(100_000_000),
+    )?;
+    // Like a process exit status, only the low byte is kept.
+    assert_eq!(expected, status as u8 as u32);
+    assert_output(&path, &output);
+    Ok(())
+}
+
+/// Links compiled Akyno functions with C code that calls them, and is called by them.
+#[test]
+fn test_c_interop() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let asm = dir.path().join("abi.s");
+    let exe = dir.path().join("abi");
+    let status = Command::new(env!("CARGO_BIN_EXE_akynoc"))
+        .args(["--emit", "asm", "-o"])
+        .arg(&asm)
+        .arg("tests/resources/interop/abi.akn")
+        .status()?;
+    assert!(status.success());
+    let status = Command::new("cc")
+        .arg("-o")
+        .arg(&exe)
+        .arg(&asm)
+        .arg("tests/resources/interop/abi.c")
+        .status()?;
+    assert!(status.success());
+    assert_eq!(Some(0), Command::new(&exe).status()?.code());
     Ok(())
 }
@@ -0,0 +1,70 @@
+// Calls into, and is called from, `abi.akn`. Exits with the number of the first failed check.
+
+struct pair {
+    int a;
+    char b;
+};
+
+struct triple {
+    int x, y, z;
+};
+
+struct big {
+    int v[6];
+    char tag;
+};
+
+int sum8(int, int, int, int, int, int, int, int);
+struct triple make_triple(int);
+struct big make_big(int);
+int mix(struct pair, struct triple, int, struct big, char);
+int spill(int, int, int, int, int, struct triple, int);
+int call_c(void);
+
+int c_sum8(int a, int b, int c, int d, int e, int f, int g, int h) {
+    return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h;
+}
+
+struct triple c_make_triple(int x) {
+    struct triple t = {x, x + 1, x + 2};
+    return t;
+}
+
+struct big c_make_big(int seed) {
+    struct big b;
+    for (int i = 0; i < 6; i++) {
+        b.v[i] = seed + i;
+    }
+    b.tag = 'c';
+    return b;
+}
+
+int c_check(struct pair p, struct triple t, struct big b, char c) {
+    return p.a + p.b + t.x + t.y + t.z + 1 + b.v[0] + b.v[5] + b.tag + c;
+}
+
+int c_spill(int a, int b, int c, int d, int e, struct triple t, int f) {
+    return a + b + c + d + e + t.x * t.y * t.z + f;
+}
+
+int main(void) {
+    if (sum8(1, 2, 3, 4, 5, 6, 7, 8) != 204) {
+        return 1;
+    }
+    struct triple t = make_triple(5);
+    if (t.x != 5 || t.y != 6 || t.z != 7) {
+        return 2;
+    }
+    struct big b = make_big(3);
+    if (b.v[0] != 3 || b.v[5] != 8 || b.tag != 'b') {
+        return 3;
+    }
+    struct pair p = {1, 'q'};
+    if (mix(p, t, 4, b, 'x') != 1 + 'q' + 18 + 4 + 3 + 8 + 'b' + 'x') {
+        return 4;
+    }
+    if (spill(1, 2, 3, 4, 5, t, 6) != 231) {
+        return 5;
+    }
+    return 10 * call_c();
+}
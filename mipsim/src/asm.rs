@@ -0,0 +1,737 @@
+//! Assembler for the subset of MIPS assembly understood by MARS and SPIM that compilers need.
+//!
+//! Source is read line by line. A line may start with any number of `label:`s, followed by a
+//! directive or an instruction, and `#` starts a comment.
+//!
+//! Supported directives:
+//! - `.text` and `.data` switch segments, and `.globl` is accepted and ignored.
+//! - `.align n` aligns the data segment to `2^n` bytes.
+//! - `.word`, `.half` and `.byte` emit values, aligned to their size. `.word` also accepts labels.
+//! - `.space n` emits `n` zero bytes.
+//! - `.ascii` and `.asciiz` emit a string, the latter followed by a NUL byte.
+//!
+//! Besides the real instructions in [`Inst`], the pseudo-instructions `li`, `la`, `move`, `neg`,
+//! `not`, `nop`, `b`, `beqz` and `bnez` are expanded as MARS does. Immediates and load/store
+//! offsets that do not fit in 16 bits are built in `$at` first.
+use std::{collections::HashMap, fmt::Display};
+
+use crate::inst::{parse_reg, AluOp, Cond, Inst, MulDivOp, Reg, ShiftOp, Width, AT, RA, ZERO};
+
+/// Address of the first instruction.
+pub const TEXT_BASE: u32 = 0x0040_0000;
+/// Address of the first byte of the data segment.
+pub const DATA_BASE: u32 = 0x1001_0000;
+
+/// An assembled program, ready to [`run`](crate::machine::run).
+#[derive(Debug, Clone)]
+pub struct Program {
+    /// Instructions, the first at [`TEXT_BASE`].
+    pub text: Vec<Inst>,
+    /// Initial contents of the data segment, starting at [`DATA_BASE`].
+    pub data: Vec<u8>,
+    /// Address of the first instruction to run: the label `__start` if there is one, or else the
+    /// first instruction.
+    pub entry: u32,
+    /// The source line of each instruction.
+    pub lines: Vec<usize>,
+}
+
+/// An error in the source of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// The line the error is on, starting at `1`.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+type Result<T> = std::result::Result<T, String>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Text,
+    Data,
+}
+
+/// An instruction, waiting for every label to be defined.
+struct Pending<'src> {
+    line: usize,
+    mnemonic: &'src str,
+    operands: Vec<&'src str>,
+}
+
+#[derive(Default)]
+struct Assembler<'src> {
+    labels: HashMap<&'src str, u32>,
+    /// Labels on the lines since the last directive or instruction, defined once it is known
+    /// whether it needs aligning.
+    unplaced: Vec<&'src str>,
+    pending: Vec<Pending<'src>>,
+    /// Number of instructions, including those pseudo-instructions expand to.
+    text_len: u32,
+    data: Vec<u8>,
+    /// Labels in `.word`s, to be filled in once every label is defined.
+    fixups: Vec<(usize, usize, &'src str)>,
+}
+
+/// Assembles the MIPS assembly `src`.
+pub fn assemble(src: &str) -> std::result::Result<Program, AsmError> {
+    let mut asm = Assembler::default();
+    let mut segment = Segment::Text;
+    for (index, line) in src.lines().enumerate() {
+        let line_no = index + 1;
+        asm.line(line_no, line, &mut segment)
+            .map_err(|message| AsmError {
+                line: line_no,
+                message,
+            })?;
+    }
+    asm.place_labels(segment).map_err(|message| AsmError {
+        line: src.lines().count(),
+        message,
+    })?;
+    asm.finish()
+}
+
+impl<'src> Assembler<'src> {
+    fn line(&mut self, line_no: usize, line: &'src str, segment: &mut Segment) -> Result<()> {
+        let mut rest = strip_comment(line).trim();
+        while let Some((label, after)) = split_label(rest) {
+            self.unplaced.push(label);
+            rest = after.trim_start();
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+        let (head, args) = match rest.split_once(char::is_whitespace) {
+            Some((head, args)) => (head, args.trim()),
+            None => (rest, ""),
+        };
+
+        if let Some(directive) = head.strip_prefix('.') {
+            return self.directive(line_no, directive, args, segment);
+        }
+        if *segment != Segment::Text {
+            return Err(format!("instruction `{head}` outside of the text segment"));
+        }
+        self.place_labels(Segment::Text)?;
+        let operands = split_operands(args);
+        // Labels are not yet known, but do not change how many instructions are generated.
+        let size = expand(head, &operands, &|_| Ok(0))?.len() as u32;
+        self.pending.push(Pending {
+            line: line_no,
+            mnemonic: head,
+            operands,
+        });
+        self.text_len += size;
+        Ok(())
+    }
+
+    /// Defines the unplaced labels at the current address of `segment`.
+    fn place_labels(&mut self, segment: Segment) -> Result<()> {
+        let addr = match segment {
+            Segment::Text => TEXT_BASE + 4 * self.text_len,
+            Segment::Data => DATA_BASE + self.data.len() as u32,
+        };
+        for label in self.unplaced.drain(..) {
+            if self.labels.insert(label, addr).is_some() {
+                return Err(format!("label `{label}` is defined multiple times"));
+            }
+        }
+        Ok(())
+    }
+
+    fn align_data(&mut self, align: usize) {
+        self.data.resize(self.data.len().next_multiple_of(align), 0);
+    }
+
+    fn directive(
+        &mut self,
+        line_no: usize,
+        directive: &str,
+        args: &'src str,
+        segment: &mut Segment,
+    ) -> Result<()> {
+        match directive {
+            "text" | "data" => {
+                self.place_labels(*segment)?;
+                *segment = if directive == "text" {
+                    Segment::Text
+                } else {
+                    Segment::Data
+                };
+                return Ok(());
+            }
+            "globl" | "global" => return Ok(()),
+            _ => (),
+        }
+        if *segment != Segment::Data {
+            return Err(format!(
+                "directive `.{directive}` outside of the data segment"
+            ));
+        }
+        match directive {
+            "align" => {
+                let n = imm(args)?;
+                if !(0..=12).contains(&n) {
+                    return Err(format!("alignment `{n}` is out of range"));
+                }
+                self.align_data(1 << n);
+                self.place_labels(Segment::Data)
+            }
+            "word" | "half" | "byte" => {
+                let width = match directive {
+                    "word" => Width::Word,
+                    "half" => Width::Half,
+                    _ => Width::Byte,
+                };
+                self.align_data(width as usize);
+                self.place_labels(Segment::Data)?;
+                for operand in split_operands(args) {
+                    let value = match (width, imm(operand)) {
+                        (_, Ok(value)) => value,
+                        (Width::Word, Err(_)) if is_label(operand) => {
+                            self.fixups.push((line_no, self.data.len(), operand));
+                            0
+                        }
+                        (_, Err(err)) => return Err(err),
+                    };
+                    let bytes = fit(value, width)?;
+                    self.data
+                        .extend_from_slice(&bytes.to_le_bytes()[..width as usize]);
+                }
+                Ok(())
+            }
+            "space" => {
+                self.place_labels(Segment::Data)?;
+                let n = imm(args)?;
+                let n = usize::try_from(n).map_err(|_| format!("invalid size `{n}`"))?;
+                self.data.resize(self.data.len() + n, 0);
+                Ok(())
+            }
+            "ascii" | "asciiz" => {
+                self.place_labels(Segment::Data)?;
+                let mut bytes = string(args)?;
+                if directive == "asciiz" {
+                    bytes.push(0);
+                }
+                self.data.extend(bytes);
+                Ok(())
+            }
+            _ => Err(format!("unknown directive `.{directive}`")),
+        }
+    }
+
+    fn finish(self) -> std::result::Result<Program, AsmError> {
+        let labels = &self.labels;
+        let resolve = |label: &str| {
+            labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| format!("undefined label `{label}`"))
+        };
+        let mut data = self.data;
+        for (line, offset, label) in self.fixups {
+            let addr = resolve(label).map_err(|message| AsmError { line, message })?;
+            data[offset..offset + 4].copy_from_slice(&addr.to_le_bytes());
+        }
+        let mut text = vec![];
+        let mut lines = vec![];
+        for pending in self.pending {
+            let insts =
+                expand(pending.mnemonic, &pending.operands, &resolve).map_err(|message| {
+                    AsmError {
+                        line: pending.line,
+                        message,
+                    }
+                })?;
+            lines.extend(std::iter::repeat_n(pending.line, insts.len()));
+            text.extend(insts);
+        }
+        Ok(Program {
+            text,
+            data,
+            entry: labels.get("__start").copied().unwrap_or(TEXT_BASE),
+            lines,
+        })
+    }
+}
+
+/// Removes a `#` comment from the end of `line`, ignoring any `#` in a string or character.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+/// Splits a leading `label:` off `s`.
+fn split_label(s: &str) -> Option<(&str, &str)> {
+    let (label, rest) = s.split_once(':')?;
+    is_label(label).then_some((label, rest))
+}
+
+/// Splits comma-separated operands.
+fn split_operands(args: &str) -> Vec<&str> {
+    if args.is_empty() {
+        return vec![];
+    }
+    args.split(',').map(str::trim).collect()
+}
+
+fn reg(operand: &str) -> Result<Reg> {
+    parse_reg(operand).ok_or_else(|| format!("expected a register, found `{operand}`"))
+}
+
+/// Parses a decimal, hexadecimal or character immediate.
+fn imm(operand: &str) -> Result<i64> {
+    let invalid = || format!("expected an immediate, found `{operand}`");
+    if let Some(c) = operand
+        .strip_prefix('\'')
+        .and_then(|c| c.strip_suffix('\''))
+    {
+        let bytes = unescape(c)?;
+        return match bytes[..] {
+            [b] => Ok(b as i64),
+            _ => Err(invalid()),
+        };
+    }
+    let (negative, digits) = match operand.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, operand),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    }
+    .map_err(|_| invalid())?;
+    Ok(if negative { -value } else { value })
+}
+
+/// Truncates `value` to `width`, if it fits as either a signed or an unsigned value.
+fn fit(value: i64, width: Width) -> Result<u32> {
+    let bits = 8 * width as u32;
+    if value < -(1 << (bits - 1)) || value >= 1 << bits {
+        return Err(format!("`{value}` does not fit in {bits} bits"));
+    }
+    Ok(value as u32)
+}
+
+/// Parses a memory operand, `offset(base)`, where the offset is optional.
+fn mem(operand: &str) -> Result<(i64, Reg)> {
+    let invalid = || format!("expected an address, found `{operand}`");
+    let (offset, base) = operand.split_once('(').ok_or_else(invalid)?;
+    let base = base.strip_suffix(')').ok_or_else(invalid)?;
+    let offset = match offset.trim() {
+        "" => 0,
+        offset => imm(offset)?,
+    };
+    Ok((offset, reg(base.trim())?))
+}
+
+/// Parses a string literal.
+fn string(operand: &str) -> Result<Vec<u8>> {
+    operand
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a string, found `{operand}`"))
+        .and_then(unescape)
+}
+
+fn unescape(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some(c @ ('\\' | '"' | '\'')) => c as u8,
+            Some(c) => return Err(format!("unknown escape `\\{c}`")),
+            None => return Err("unterminated escape".into()),
+        });
+    }
+    Ok(bytes)
+}
+
+/// Builds the 32-bit `value` in `rd`.
+fn load_imm(rd: Reg, value: i64) -> Result<Vec<Inst>> {
+    if i16::try_from(value).is_ok() {
+        return Ok(vec![Inst::AluImm(AluOp::Addu, rd, ZERO, value as i32)]);
+    }
+    if (0..=0xffff).contains(&value) {
+        return Ok(vec![Inst::AluImm(AluOp::Or, rd, ZERO, value as i32)]);
+    }
+    let value = fit(value, Width::Word)?;
+    Ok(vec![
+        Inst::Lui(rd, (value >> 16) as u16),
+        Inst::AluImm(AluOp::Or, rd, rd, (value & 0xffff) as i32),
+    ])
+}
+
+/// Expands a single line of source into instructions. `resolve` gives the address of a label.
+fn expand(
+    mnemonic: &str,
+    ops: &[&str],
+    resolve: &dyn Fn(&str) -> Result<u32>,
+) -> Result<Vec<Inst>> {
+    let arity = |n: usize| {
+        if ops.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "`{mnemonic}` expects {n} operand(s), found {}",
+                ops.len()
+            ))
+        }
+    };
+    let alu = match mnemonic {
+        "add" => Some(AluOp::Add),
+        "addu" => Some(AluOp::Addu),
+        "sub" => Some(AluOp::Sub),
+        "subu" => Some(AluOp::Subu),
+        "and" => Some(AluOp::And),
+        "or" => Some(AluOp::Or),
+        "xor" => Some(AluOp::Xor),
+        "nor" => Some(AluOp::Nor),
+        "slt" => Some(AluOp::Slt),
+        "sltu" => Some(AluOp::Sltu),
+        "sllv" => Some(AluOp::Sllv),
+        "srlv" => Some(AluOp::Srlv),
+        "srav" => Some(AluOp::Srav),
+        "mul" => Some(AluOp::Mul),
+        _ => None,
+    };
+    if let Some(op) = alu {
+        arity(3)?;
+        return Ok(vec![Inst::Alu(
+            op,
+            reg(ops[0])?,
+            reg(ops[1])?,
+            reg(ops[2])?,
+        )]);
+    }
+    // Immediate forms, and whether their immediate is zero-extended.
+    let alu_imm = match mnemonic {
+        "addi" => Some((AluOp::Add, false)),
+        "addiu" => Some((AluOp::Addu, false)),
+        "slti" => Some((AluOp::Slt, false)),
+        "sltiu" => Some((AluOp::Sltu, false)),
+        "andi" => Some((AluOp::And, true)),
+        "ori" => Some((AluOp::Or, true)),
+        "xori" => Some((AluOp::Xor, true)),
+        _ => None,
+    };
+    if let Some((op, unsigned)) = alu_imm {
+        arity(3)?;
+        let (rt, rs, value) = (reg(ops[0])?, reg(ops[1])?, imm(ops[2])?);
+        let fits = if unsigned {
+            (0..=0xffff).contains(&value)
+        } else {
+            i16::try_from(value).is_ok()
+        };
+        if fits {
+            return Ok(vec![Inst::AluImm(op, rt, rs, value as i32)]);
+        }
+        let mut insts = load_imm(AT, value)?;
+        insts.push(Inst::Alu(op, rt, rs, AT));
+        return Ok(insts);
+    }
+    let access = match mnemonic {
+        "lb" => Some((Width::Byte, Some(true))),
+        "lbu" => Some((Width::Byte, Some(false))),
+        "lh" => Some((Width::Half, Some(true))),
+        "lhu" => Some((Width::Half, Some(false))),
+        "lw" => Some((Width::Word, Some(true))),
+        "sb" => Some((Width::Byte, None)),
+        "sh" => Some((Width::Half, None)),
+        "sw" => Some((Width::Word, None)),
+        _ => None,
+    };
+    if let Some((width, signed)) = access {
+        arity(2)?;
+        let rt = reg(ops[0])?;
+        let (offset, base) = mem(ops[1])?;
+        let access = |base: Reg, offset: i16| match signed {
+            Some(signed) => Inst::Load {
+                width,
+                signed,
+                rt,
+                base,
+                offset,
+            },
+            None => Inst::Store {
+                width,
+                rt,
+                base,
+                offset,
+            },
+        };
+        if let Ok(offset) = i16::try_from(offset) {
+            return Ok(vec![access(base, offset)]);
+        }
+        // The low half is sign-extended, so the high half is rounded to compensate.
+        let offset = fit(offset, Width::Word)?;
+        let high = offset.wrapping_add(0x8000) >> 16;
+        return Ok(vec![
+            Inst::Lui(AT, high as u16),
+            Inst::Alu(AluOp::Addu, AT, AT, base),
+            access(AT, offset as u16 as i16),
+        ]);
+    }
+    let branch = match mnemonic {
+        "beq" => Some(Cond::Eq),
+        "bne" => Some(Cond::Ne),
+        _ => None,
+    };
+    if let Some(cond) = branch {
+        arity(3)?;
+        return Ok(vec![Inst::Branch(
+            cond,
+            reg(ops[0])?,
+            reg(ops[1])?,
+            resolve(ops[2])?,
+        )]);
+    }
+    let branch_zero = match mnemonic {
+        "blez" => Some(Cond::Lez),
+        "bgtz" => Some(Cond::Gtz),
+        "bltz" => Some(Cond::Ltz),
+        "bgez" => Some(Cond::Gez),
+        "beqz" => Some(Cond::Eq),
+        "bnez" => Some(Cond::Ne),
+        _ => None,
+    };
+    if let Some(cond) = branch_zero {
+        arity(2)?;
+        return Ok(vec![Inst::Branch(
+            cond,
+            reg(ops[0])?,
+            ZERO,
+            resolve(ops[1])?,
+        )]);
+    }
+    let shift = match mnemonic {
+        "sll" => Some(ShiftOp::Sll),
+        "srl" => Some(ShiftOp::Srl),
+        "sra" => Some(ShiftOp::Sra),
+        _ => None,
+    };
+    if let Some(op) = shift {
+        arity(3)?;
+        let amount = imm(ops[2])?;
+        if !(0..32).contains(&amount) {
+            return Err(format!("shift amount `{amount}` is out of range"));
+        }
+        return Ok(vec![Inst::Shift(
+            op,
+            reg(ops[0])?,
+            reg(ops[1])?,
+            amount as u8,
+        )]);
+    }
+    let mul_div = match mnemonic {
+        "mult" => Some(MulDivOp::Mult),
+        "multu" => Some(MulDivOp::Multu),
+        "div" => Some(MulDivOp::Div),
+        "divu" => Some(MulDivOp::Divu),
+        _ => None,
+    };
+    if let Some(op) = mul_div {
+        arity(2)?;
+        return Ok(vec![Inst::MulDiv(op, reg(ops[0])?, reg(ops[1])?)]);
+    }
+
+    let inst = match mnemonic {
+        "lui" => {
+            arity(2)?;
+            let value = imm(ops[1])?;
+            if !(0..=0xffff).contains(&value) {
+                return Err(format!("`{value}` does not fit in 16 bits"));
+            }
+            Inst::Lui(reg(ops[0])?, value as u16)
+        }
+        "mfhi" => {
+            arity(1)?;
+            Inst::Mfhi(reg(ops[0])?)
+        }
+        "mflo" => {
+            arity(1)?;
+            Inst::Mflo(reg(ops[0])?)
+        }
+        "j" | "b" => {
+            arity(1)?;
+            let target = resolve(ops[0])?;
+            if mnemonic == "j" {
+                Inst::J(target)
+            } else {
+                Inst::Branch(Cond::Eq, ZERO, ZERO, target)
+            }
+        }
+        "jal" => {
+            arity(1)?;
+            Inst::Jal(resolve(ops[0])?)
+        }
+        "jr" => {
+            arity(1)?;
+            Inst::Jr(reg(ops[0])?)
+        }
+        "jalr" => match ops {
+            [rs] => Inst::Jalr(RA, reg(rs)?),
+            _ => {
+                arity(2)?;
+                Inst::Jalr(reg(ops[0])?, reg(ops[1])?)
+            }
+        },
+        "syscall" => {
+            arity(0)?;
+            Inst::Syscall
+        }
+        "break" => match ops {
+            [] => Inst::Break(0),
+            _ => {
+                arity(1)?;
+                Inst::Break(fit(imm(ops[0])?, Width::Word)?)
+            }
+        },
+        "teq" => match ops {
+            [rs, rt] => Inst::Teq(reg(rs)?, reg(rt)?, 0),
+            _ => {
+                arity(3)?;
+                Inst::Teq(reg(ops[0])?, reg(ops[1])?, fit(imm(ops[2])?, Width::Word)?)
+            }
+        },
+        "nop" => {
+            arity(0)?;
+            Inst::Shift(ShiftOp::Sll, ZERO, ZERO, 0)
+        }
+        "move" => {
+            arity(2)?;
+            Inst::Alu(AluOp::Addu, reg(ops[0])?, reg(ops[1])?, ZERO)
+        }
+        "neg" => {
+            arity(2)?;
+            Inst::Alu(AluOp::Sub, reg(ops[0])?, ZERO, reg(ops[1])?)
+        }
+        "not" => {
+            arity(2)?;
+            Inst::Alu(AluOp::Nor, reg(ops[0])?, reg(ops[1])?, ZERO)
+        }
+        "li" => {
+            arity(2)?;
+            return load_imm(reg(ops[0])?, imm(ops[1])?);
+        }
+        "la" => {
+            arity(2)?;
+            let (rd, addr) = (reg(ops[0])?, resolve(ops[1])?);
+            return Ok(vec![
+                Inst::Lui(rd, (addr >> 16) as u16),
+                Inst::AluImm(AluOp::Or, rd, rd, (addr & 0xffff) as i32),
+            ]);
+        }
+        _ => return Err(format!("unknown instruction `{mnemonic}`")),
+    };
+    Ok(vec![inst])
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("li $t0, 5", vec![Inst::AluImm(AluOp::Addu, 8, ZERO, 5)])]
+    #[case("li $t0, 0xffff", vec![Inst::AluImm(AluOp::Or, 8, ZERO, 0xffff)])]
+    #[case("li $t0, -70000", vec![Inst::Lui(8, 0xfffe), Inst::AluImm(AluOp::Or, 8, 8, 0xee90)])]
+    #[case("lw $t0, -4($fp)", vec![Inst::Load { width: Width::Word, signed: true, rt: 8, base: 30, offset: -4 }])]
+    #[case("sb $t0, ($sp)", vec![Inst::Store { width: Width::Byte, rt: 8, base: 29, offset: 0 }])]
+    #[case("sw $t0, -40000($fp)", vec![
+        Inst::Lui(AT, 0xffff),
+        Inst::Alu(AluOp::Addu, AT, AT, 30),
+        Inst::Store { width: Width::Word, rt: 8, base: AT, offset: 25536 },
+    ])]
+    #[case("addiu $sp, $sp, -40000", vec![
+        Inst::Lui(AT, 0xffff),
+        Inst::AluImm(AluOp::Or, AT, AT, 0x63c0),
+        Inst::Alu(AluOp::Addu, 29, 29, AT),
+    ])]
+    #[case("l: b l", vec![Inst::Branch(Cond::Eq, ZERO, ZERO, TEXT_BASE)])]
+    fn test_expand(#[case] input: &str, #[case] expected: Vec<Inst>) {
+        assert_eq!(expected, assemble(input).unwrap().text);
+    }
+
+    #[test]
+    fn test_data() {
+        let src = "
+            .data
+            c: .byte 'a'
+            w: .word 1, w
+            s: .asciiz \"a#\\n\" # comment
+            .text
+            la $t0, s
+        ";
+        let program = assemble(src).unwrap();
+        let w = DATA_BASE + 4;
+        let mut data = vec![b'a', 0, 0, 0, 1, 0, 0, 0];
+        data.extend(w.to_le_bytes());
+        data.extend(b"a#\n\0");
+        assert_eq!(data, program.data);
+        let s = DATA_BASE + 12;
+        assert_eq!(
+            vec![
+                Inst::Lui(8, (s >> 16) as u16),
+                Inst::AluImm(AluOp::Or, 8, 8, (s & 0xffff) as i32),
+            ],
+            program.text
+        );
+    }
+
+    #[rstest]
+    #[case("foo $t0", 1, "unknown instruction `foo`")]
+    #[case("\n addu $t0, $t1", 2, "`addu` expects 3 operand(s), found 2")]
+    #[case("j nowhere", 1, "undefined label `nowhere`")]
+    #[case("a:\na: nop", 2, "label `a` is defined multiple times")]
+    #[case("lw $t0, 4", 1, "expected an address, found `4`")]
+    #[case("addu $t0, $t1, $t99", 1, "expected a register, found `$t99`")]
+    fn test_errors(#[case] input: &str, #[case] line: usize, #[case] message: &str) {
+        let expected = AsmError {
+            line,
+            message: message.into(),
+        };
+        assert_eq!(Err(expected), assemble(input).map(|_| ()));
+    }
+}
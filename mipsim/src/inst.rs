@@ -0,0 +1,174 @@
+//! Decoded MIPS32 instructions.
+//!
+//! Instructions are kept decoded rather than as machine words, since the simulator never needs
+//! their encoding. Registers are numbered `0` to `31`, and immediates are stored already extended
+//! to 32 bits as the instruction requires.
+use std::fmt::Display;
+
+/// A general-purpose register, numbered `0` to `31`.
+pub type Reg = u8;
+
+pub const ZERO: Reg = 0;
+pub const AT: Reg = 1;
+pub const V0: Reg = 2;
+pub const A0: Reg = 4;
+pub const A1: Reg = 5;
+pub const GP: Reg = 28;
+pub const SP: Reg = 29;
+pub const RA: Reg = 31;
+
+/// Conventional names of the registers, indexed by number.
+const NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+/// Parses a register written as `$name` or `$number`.
+pub fn parse_reg(s: &str) -> Option<Reg> {
+    let name = s.strip_prefix('$')?;
+    if let Ok(n) = name.parse::<u8>() {
+        return (n < 32).then_some(n);
+    }
+    match name {
+        "s8" => Some(30),
+        _ => NAMES.iter().position(|&n| n == name).map(|n| n as Reg),
+    }
+}
+
+/// Operations on two registers, or on a register and an immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    /// Signed addition, which traps on overflow.
+    Add,
+    Addu,
+    /// Signed subtraction, which traps on overflow.
+    Sub,
+    Subu,
+    And,
+    Or,
+    Xor,
+    Nor,
+    Slt,
+    Sltu,
+    /// Shifts by the low five bits of a register.
+    Sllv,
+    Srlv,
+    Srav,
+    /// The low 32 bits of a product.
+    Mul,
+}
+
+/// Shifts by a constant amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Sll,
+    Srl,
+    Sra,
+}
+
+/// Operations writing their result to `hi` and `lo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulDivOp {
+    Mult,
+    Multu,
+    Div,
+    Divu,
+}
+
+/// Size of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte = 1,
+    Half = 2,
+    Word = 4,
+}
+
+/// Conditions of a conditional branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    /// `rs == rt`
+    Eq,
+    /// `rs != rt`
+    Ne,
+    /// `rs <= 0`
+    Lez,
+    /// `rs > 0`
+    Gtz,
+    /// `rs < 0`
+    Ltz,
+    /// `rs >= 0`
+    Gez,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inst {
+    /// `op rd, rs, rt`
+    Alu(AluOp, Reg, Reg, Reg),
+    /// `op rt, rs, imm`
+    AluImm(AluOp, Reg, Reg, i32),
+    /// `op rd, rt, shamt`
+    Shift(ShiftOp, Reg, Reg, u8),
+    /// `lui rt, imm`
+    Lui(Reg, u16),
+    /// `op rs, rt`
+    MulDiv(MulDivOp, Reg, Reg),
+    Mfhi(Reg),
+    Mflo(Reg),
+    /// `l{b,h,w}[u] rt, offset(base)`, where `signed` selects sign extension.
+    Load {
+        width: Width,
+        signed: bool,
+        rt: Reg,
+        base: Reg,
+        offset: i16,
+    },
+    /// `s{b,h,w} rt, offset(base)`
+    Store {
+        width: Width,
+        rt: Reg,
+        base: Reg,
+        offset: i16,
+    },
+    /// Branches to the absolute address `target` if `cond` holds between `rs` and `rt`.
+    Branch(Cond, Reg, Reg, u32),
+    J(u32),
+    Jal(u32),
+    Jr(Reg),
+    /// `jalr rd, rs`
+    Jalr(Reg, Reg),
+    Syscall,
+    Break(u32),
+    /// Traps with the given code if `rs == rt`.
+    Teq(Reg, Reg, u32),
+}
+
+/// Displays a register by its conventional name.
+pub struct Name(pub Reg);
+
+impl Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "${}", NAMES[self.0 as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("$zero", Some(0))]
+    #[case("$t0", Some(8))]
+    #[case("$8", Some(8))]
+    #[case("$fp", Some(30))]
+    #[case("$s8", Some(30))]
+    #[case("$ra", Some(31))]
+    #[case("$32", None)]
+    #[case("t0", None)]
+    #[case("$foo", None)]
+    fn test_parse_reg(#[case] input: &str, #[case] expected: Option<Reg>) {
+        assert_eq!(expected, parse_reg(input));
+    }
+}
@@ -0,0 +1,11 @@
+//! A MIPS32 assembler and simulator, for testing the output of Akyno's MIPS backend without MARS
+//! or SPIM.
+//!
+//! A program is first [assembled](assemble), then [run] with the input and output its
+//! syscalls read from and write to.
+pub mod asm;
+pub mod inst;
+pub mod machine;
+
+pub use asm::{assemble, AsmError, Program};
+pub use machine::{run, Fault};
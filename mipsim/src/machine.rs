@@ -0,0 +1,532 @@
+//! Execution of an assembled [`Program`].
+//!
+//! Memory is laid out as in MARS:
+//! - The text segment starts at [`TEXT_BASE`], and cannot be read or written as data.
+//! - The data segment starts at [`DATA_BASE`], and is followed by the heap, which `sbrk` grows up
+//!   to [`HEAP_SIZE`] bytes.
+//! - The stack grows down from [`STACK_TOP`], up to [`STACK_SIZE`] bytes.
+//!
+//! Any other access faults, as does an access not aligned to its size. Branches and jumps take
+//! effect immediately, without a delay slot. The program stops when it exits through a syscall,
+//! or when it runs past its last instruction, which exits with status `0`.
+//!
+//! Supported syscalls, selected by `$v0`:
+//!
+//! | `$v0` | Service      | Arguments                         | Result                    |
+//! |-------|--------------|-----------------------------------|---------------------------|
+//! | 1     | print int    | `$a0`: the `int`                  |                           |
+//! | 4     | print string | `$a0`: address of a NUL-terminated string |                   |
+//! | 5     | read int     |                                   | `$v0`: the `int`, or `0` if there is none |
+//! | 8     | read string  | `$a0`: buffer, `$a1`: its size    |                           |
+//! | 9     | sbrk         | `$a0`: number of bytes            | `$v0`: their address, or `0` if the heap is full |
+//! | 10    | exit         |                                   |                           |
+//! | 11    | print char   | `$a0`: the character              |                           |
+//! | 12    | read char    |                                   | `$v0`: the character, or `-1` at the end of input |
+//! | 17    | exit2        | `$a0`: exit status                |                           |
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    asm::{Program, DATA_BASE, TEXT_BASE},
+    inst::{AluOp, Cond, Inst, MulDivOp, Reg, ShiftOp, Width, A0, A1, GP, RA, SP, V0},
+};
+
+/// Initial value of `$sp`.
+pub const STACK_TOP: u32 = 0x7fff_effc;
+/// Maximum size of the stack, in bytes.
+pub const STACK_SIZE: u32 = 64 << 20;
+/// Maximum size of the data segment and heap together, in bytes.
+pub const HEAP_SIZE: u32 = 256 << 20;
+
+/// End of the stack, just past the highest usable address.
+const STACK_END: u32 = 0x8000_0000;
+
+const PAGE_SIZE: u32 = 4096;
+
+/// An error that stops the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    /// Address of the faulting instruction.
+    pub pc: u32,
+    /// Source line of the faulting instruction, if there is one.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fault at {:#010x}", self.pc)?;
+        if let Some(line) = self.line {
+            write!(f, " (line {line})")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// Sparse, byte-addressed memory.
+struct Memory {
+    pages: HashMap<u32, Box<[u8; PAGE_SIZE as usize]>>,
+    /// End of the heap.
+    brk: u32,
+}
+
+impl Memory {
+    /// Checks that the `len` bytes at `addr` are mapped, and aligned to `len`.
+    fn check(&self, addr: u32, len: u32) -> Result<(), String> {
+        if !addr.is_multiple_of(len) {
+            return Err(format!(
+                "address {addr:#010x} is not aligned to {len} bytes"
+            ));
+        }
+        let (start, end) = (addr as u64, addr as u64 + len as u64);
+        let in_heap = DATA_BASE as u64 <= start && end <= self.brk as u64;
+        let in_stack = (STACK_END - STACK_SIZE) as u64 <= start && end <= STACK_END as u64;
+        if in_heap || in_stack {
+            Ok(())
+        } else {
+            Err(format!("address {addr:#010x} is out of range"))
+        }
+    }
+
+    fn byte(&self, addr: u32) -> u8 {
+        self.pages
+            .get(&(addr / PAGE_SIZE))
+            .map_or(0, |page| page[(addr % PAGE_SIZE) as usize])
+    }
+
+    fn set_byte(&mut self, addr: u32, value: u8) {
+        let page = self
+            .pages
+            .entry(addr / PAGE_SIZE)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE as usize]));
+        page[(addr % PAGE_SIZE) as usize] = value;
+    }
+
+    /// Reads `len` bytes at `addr` as a little-endian number.
+    fn load(&self, addr: u32, len: u32) -> Result<u32, String> {
+        self.check(addr, len)?;
+        Ok((0..len)
+            .rev()
+            .fold(0, |value, i| value << 8 | self.byte(addr + i) as u32))
+    }
+
+    /// Writes the low `len` bytes of `value` at `addr`, little-endian.
+    fn store(&mut self, addr: u32, len: u32, value: u32) -> Result<(), String> {
+        self.check(addr, len)?;
+        for i in 0..len {
+            self.set_byte(addr + i, (value >> (8 * i)) as u8);
+        }
+        Ok(())
+    }
+}
+
+struct Machine<'io> {
+    regs: [u32; 32],
+    hi: u32,
+    lo: u32,
+    mem: Memory,
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+}
+
+/// What to do after an instruction.
+enum Step {
+    /// Continue at the given address.
+    Jump(u32),
+    /// Stop with the given exit status.
+    Exit(i32),
+}
+
+/// Runs `program` until it exits, returning its exit status. The program reads from `input` and
+/// writes to `output`. If `max_steps` is given, the program faults once it has run that many
+/// instructions.
+pub fn run(
+    program: &Program,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    max_steps: Option<u64>,
+) -> Result<i32, Fault> {
+    let mut machine = Machine {
+        regs: [0; 32],
+        hi: 0,
+        lo: 0,
+        mem: Memory {
+            pages: HashMap::new(),
+            brk: DATA_BASE + program.data.len() as u32,
+        },
+        input,
+        output,
+    };
+    for (addr, &byte) in (DATA_BASE..).zip(&program.data) {
+        machine.mem.set_byte(addr, byte);
+    }
+    machine.regs[SP as usize] = STACK_TOP;
+    machine.regs[GP as usize] = 0x1000_8000;
+
+    let text_end = TEXT_BASE + 4 * program.text.len() as u32;
+    let mut pc = program.entry;
+    let mut steps = 0;
+    let status = loop {
+        if pc == text_end {
+            break Ok(0);
+        }
+        let index = pc.wrapping_sub(TEXT_BASE) / 4;
+        let fault = |message: String| Fault {
+            pc,
+            line: program.lines.get(index as usize).copied(),
+            message,
+        };
+        let inst = match program.text.get(index as usize) {
+            Some(inst) if pc.is_multiple_of(4) => inst,
+            _ => break Err(fault(format!("cannot execute address {pc:#010x}"))),
+        };
+        if max_steps.is_some_and(|max| steps == max) {
+            break Err(fault(format!("exceeded the limit of {steps} instructions")));
+        }
+        steps += 1;
+        match machine.step(*inst, pc) {
+            Ok(Step::Jump(next)) => pc = next,
+            Ok(Step::Exit(status)) => break Ok(status),
+            Err(message) => break Err(fault(message)),
+        }
+    };
+    // Output written before a fault must still appear.
+    let flushed = machine.output.flush();
+    let status = status?;
+    flushed.map_err(|err| Fault {
+        pc,
+        line: None,
+        message: format!("could not write output: {err}"),
+    })?;
+    Ok(status)
+}
+
+impl Machine<'_> {
+    fn get(&self, reg: Reg) -> u32 {
+        self.regs[reg as usize]
+    }
+
+    fn set(&mut self, reg: Reg, value: u32) {
+        // `$zero` is hardwired to `0`.
+        if reg != 0 {
+            self.regs[reg as usize] = value;
+        }
+    }
+
+    fn step(&mut self, inst: Inst, pc: u32) -> Result<Step, String> {
+        let next = pc + 4;
+        match inst {
+            Inst::Alu(op, rd, rs, rt) => {
+                let value = alu(op, self.get(rs), self.get(rt))?;
+                self.set(rd, value);
+            }
+            Inst::AluImm(op, rt, rs, imm) => {
+                let value = alu(op, self.get(rs), imm as u32)?;
+                self.set(rt, value);
+            }
+            Inst::Shift(op, rd, rt, amount) => {
+                let value = self.get(rt);
+                let value = match op {
+                    ShiftOp::Sll => value << amount,
+                    ShiftOp::Srl => value >> amount,
+                    ShiftOp::Sra => ((value as i32) >> amount) as u32,
+                };
+                self.set(rd, value);
+            }
+            Inst::Lui(rt, imm) => self.set(rt, (imm as u32) << 16),
+            Inst::MulDiv(op, rs, rt) => {
+                let (a, b) = (self.get(rs), self.get(rt));
+                (self.hi, self.lo) = match op {
+                    MulDivOp::Mult => {
+                        let product = a as i32 as i64 * b as i32 as i64;
+                        ((product >> 32) as u32, product as u32)
+                    }
+                    MulDivOp::Multu => {
+                        let product = a as u64 * b as u64;
+                        ((product >> 32) as u32, product as u32)
+                    }
+                    // Division by zero leaves an unpredictable result, as on real hardware.
+                    _ if b == 0 => (0, 0),
+                    MulDivOp::Div => {
+                        let (a, b) = (a as i32, b as i32);
+                        (a.wrapping_rem(b) as u32, a.wrapping_div(b) as u32)
+                    }
+                    MulDivOp::Divu => (a % b, a / b),
+                };
+            }
+            Inst::Mfhi(rd) => self.set(rd, self.hi),
+            Inst::Mflo(rd) => self.set(rd, self.lo),
+            Inst::Load {
+                width,
+                signed,
+                rt,
+                base,
+                offset,
+            } => {
+                let addr = self.get(base).wrapping_add(offset as u32);
+                let value = self.mem.load(addr, width as u32)?;
+                let value = match (signed, width) {
+                    (true, Width::Byte) => value as u8 as i8 as u32,
+                    (true, Width::Half) => value as u16 as i16 as u32,
+                    _ => value,
+                };
+                self.set(rt, value);
+            }
+            Inst::Store {
+                width,
+                rt,
+                base,
+                offset,
+            } => {
+                let addr = self.get(base).wrapping_add(offset as u32);
+                self.mem.store(addr, width as u32, self.get(rt))?;
+            }
+            Inst::Branch(cond, rs, rt, target) => {
+                let (a, b) = (self.get(rs), self.get(rt));
+                let taken = match cond {
+                    Cond::Eq => a == b,
+                    Cond::Ne => a != b,
+                    Cond::Lez => a as i32 <= 0,
+                    Cond::Gtz => a as i32 > 0,
+                    Cond::Ltz => (a as i32) < 0,
+                    Cond::Gez => a as i32 >= 0,
+                };
+                if taken {
+                    return Ok(Step::Jump(target));
+                }
+            }
+            Inst::J(target) => return Ok(Step::Jump(target)),
+            Inst::Jal(target) => {
+                self.set(RA, next);
+                return Ok(Step::Jump(target));
+            }
+            Inst::Jr(rs) => return Ok(Step::Jump(self.get(rs))),
+            Inst::Jalr(rd, rs) => {
+                let target = self.get(rs);
+                self.set(rd, next);
+                return Ok(Step::Jump(target));
+            }
+            Inst::Syscall => {
+                if let Some(status) = self.syscall()? {
+                    return Ok(Step::Exit(status));
+                }
+            }
+            Inst::Break(code) => return Err(format!("break {code}")),
+            Inst::Teq(rs, rt, code) => {
+                if self.get(rs) == self.get(rt) {
+                    // By convention, code 7 marks a division by zero.
+                    return Err(match code {
+                        7 => "division by zero".into(),
+                        code => format!("trap {code}"),
+                    });
+                }
+            }
+        }
+        Ok(Step::Jump(next))
+    }
+
+    /// Performs the syscall selected by `$v0`, returning the exit status if the program exits.
+    fn syscall(&mut self) -> Result<Option<i32>, String> {
+        let io_error = |err: io::Error| format!("syscall failed: {err}");
+        let a0 = self.get(A0);
+        match self.get(V0) {
+            1 => write!(self.output, "{}", a0 as i32).map_err(io_error)?,
+            4 => {
+                let mut bytes = vec![];
+                let mut addr = a0;
+                loop {
+                    match self.mem.load(addr, 1)? as u8 {
+                        0 => break,
+                        b => bytes.push(b),
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+                self.output.write_all(&bytes).map_err(io_error)?;
+            }
+            5 => {
+                self.output.flush().map_err(io_error)?;
+                let value = self.read_int().map_err(io_error)?;
+                self.set(V0, value as u32);
+            }
+            8 => {
+                self.output.flush().map_err(io_error)?;
+                // Reads up to a newline, which is kept, leaving room for the NUL byte.
+                let size = self.get(A1) as i32;
+                let mut addr = a0;
+                for _ in 1..size {
+                    let Some(b) = self.read_byte().map_err(io_error)? else {
+                        break;
+                    };
+                    self.mem.store(addr, 1, b as u32)?;
+                    addr = addr.wrapping_add(1);
+                    if b == b'\n' {
+                        break;
+                    }
+                }
+                if size > 0 {
+                    self.mem.store(addr, 1, 0)?;
+                }
+            }
+            9 => {
+                let start = self.mem.brk.next_multiple_of(8);
+                let addr = match u32::try_from(a0 as i32) {
+                    Ok(len) if start - DATA_BASE + len <= HEAP_SIZE => {
+                        self.mem.brk = start + len;
+                        start
+                    }
+                    _ => 0,
+                };
+                self.set(V0, addr);
+            }
+            10 => return Ok(Some(0)),
+            11 => self.output.write_all(&[a0 as u8]).map_err(io_error)?,
+            12 => {
+                self.output.flush().map_err(io_error)?;
+                let c = self.read_byte().map_err(io_error)?;
+                self.set(V0, c.map_or(u32::MAX, u32::from));
+            }
+            17 => return Ok(Some(a0 as i32)),
+            code => return Err(format!("unknown syscall {code}")),
+        }
+        Ok(None)
+    }
+
+    fn peek_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let b = self.peek_byte()?;
+        if b.is_some() {
+            self.input.consume(1);
+        }
+        Ok(b)
+    }
+
+    /// Skips whitespace, then reads an optionally signed decimal number, which wraps on overflow.
+    fn read_int(&mut self) -> io::Result<i32> {
+        while self.peek_byte()?.is_some_and(|b| b.is_ascii_whitespace()) {
+            self.input.consume(1);
+        }
+        let negative = match self.peek_byte()? {
+            Some(sign @ (b'-' | b'+')) => {
+                self.input.consume(1);
+                sign == b'-'
+            }
+            _ => false,
+        };
+        let mut value = 0i32;
+        while let Some(digit @ b'0'..=b'9') = self.peek_byte()? {
+            self.input.consume(1);
+            value = value.wrapping_mul(10).wrapping_add((digit - b'0') as i32);
+        }
+        Ok(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        })
+    }
+}
+
+fn alu(op: AluOp, a: u32, b: u32) -> Result<u32, String> {
+    let overflow = || "arithmetic overflow".to_string();
+    Ok(match op {
+        AluOp::Add => (a as i32).checked_add(b as i32).ok_or_else(overflow)? as u32,
+        AluOp::Addu => a.wrapping_add(b),
+        AluOp::Sub => (a as i32).checked_sub(b as i32).ok_or_else(overflow)? as u32,
+        AluOp::Subu => a.wrapping_sub(b),
+        AluOp::And => a & b,
+        AluOp::Or => a | b,
+        AluOp::Xor => a ^ b,
+        AluOp::Nor => !(a | b),
+        AluOp::Slt => ((a as i32) < (b as i32)) as u32,
+        AluOp::Sltu => (a < b) as u32,
+        AluOp::Sllv => a << (b & 31),
+        AluOp::Srlv => a >> (b & 31),
+        AluOp::Srav => ((a as i32) >> (b & 31)) as u32,
+        AluOp::Mul => a.wrapping_mul(b),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::asm::assemble;
+
+    /// Runs `src` with `input`, returning its exit status or fault, and its output.
+    fn exec(src: &str, input: &str) -> (Result<i32, String>, String) {
+        let program = assemble(src).unwrap();
+        let mut output = vec![];
+        let status = run(&program, &mut input.as_bytes(), &mut output, Some(10_000));
+        (
+            status.map_err(|fault| fault.message),
+            String::from_utf8(output).unwrap(),
+        )
+    }
+
+    #[rstest]
+    #[case::exit2("li $a0, 3\nli $v0, 17\nsyscall", Ok(3))]
+    #[case::falls_off("li $t0, 1", Ok(0))]
+    #[case::signed_div("li $t0, -7\nli $t1, 2\ndiv $t0, $t1\nmflo $a0\nmfhi $t2\nsub $a0, $a0, $t2\nli $v0, 17\nsyscall", Ok(-2))]
+    #[case::sign_extend("li $t0, 0xff\nsw $t0, -4($sp)\nlb $a0, -4($sp)\nli $v0, 17\nsyscall", Ok(-1))]
+    #[case::zero("li $zero, 5\nmove $a0, $zero\nli $v0, 17\nsyscall", Ok(0))]
+    #[case::call("jal f\nli $v0, 17\nsyscall\nf: li $a0, 9\njr $ra", Ok(9))]
+    #[case::null("lw $t0, 0($zero)", Err("address 0x00000000 is out of range".into()))]
+    #[case::unaligned("lw $t0, -2($sp)", Err("address 0x7fffeffa is not aligned to 4 bytes".into()))]
+    #[case::text("la $t0, l\nl: lw $t0, 0($t0)", Err("address 0x00400008 is out of range".into()))]
+    #[case::trap("teq $zero, $zero, 7", Err("division by zero".into()))]
+    #[case::overflow("li $t0, 0x7fffffff\naddi $t0, $t0, 1", Err("arithmetic overflow".into()))]
+    #[case::jump_to_null("jr $ra", Err("cannot execute address 0x00000000".into()))]
+    #[case::step_limit("l: b l", Err("exceeded the limit of 10000 instructions".into()))]
+    fn test_run(#[case] src: &str, #[case] expected: Result<i32, String>) {
+        assert_eq!(expected, exec(src, "").0);
+    }
+
+    #[test]
+    fn test_syscalls() {
+        let src = "
+            .data
+            hello: .asciiz \"hello \"
+            buf: .space 8
+            .text
+            la $a0, hello
+            li $v0, 4
+            syscall
+            li $v0, 5
+            syscall
+            move $a0, $v0
+            li $v0, 1
+            syscall
+            li $v0, 12
+            syscall
+            move $a0, $v0
+            li $v0, 11
+            syscall
+            la $a0, buf
+            li $a1, 4
+            li $v0, 8
+            syscall
+            li $v0, 4
+            syscall
+            li $a0, 4
+            li $v0, 9
+            syscall
+            sw $v0, 0($v0)
+            li $v0, 12
+            syscall
+            move $a0, $v0
+            li $v0, 17
+            syscall
+        ";
+        let (status, output) = exec(src, "  -42!abcdef");
+        assert_eq!(Ok(b'd' as i32), status);
+        assert_eq!("hello -42!abc", output);
+    }
+}
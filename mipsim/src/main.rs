@@ -0,0 +1,45 @@
+//! Runs a MIPS assembly file with standard input and output, exiting with the program's status.
+use std::{fs, io, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+
+/// Exit code used when the program cannot be assembled, or faults.
+const FAIL_CODE: u8 = 230;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// The MIPS assembly file to run
+    file: PathBuf,
+
+    /// Stop the program after this many instructions
+    #[arg(long)]
+    max_steps: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let src = match fs::read_to_string(&args.file) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("error: could not read `{}`: {err}", args.file.display());
+            return ExitCode::from(FAIL_CODE);
+        }
+    };
+    let program = match mipsim::assemble(&src) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("error: {}:{err}", args.file.display());
+            return ExitCode::from(FAIL_CODE);
+        }
+    };
+    let (mut input, mut output) = (io::stdin().lock(), io::stdout().lock());
+    match mipsim::run(&program, &mut input, &mut output, args.max_steps) {
+        // Like a process exit status, only the low byte is kept.
+        Ok(status) => ExitCode::from(status as u8),
+        Err(fault) => {
+            eprintln!("error: {fault}");
+            ExitCode::from(FAIL_CODE)
+        }
+    }
+}